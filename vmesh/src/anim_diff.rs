@@ -0,0 +1,384 @@
+use crate::char_anim::DEFAULT_FPS;
+use crate::rfa;
+
+/// Differences between the same bone in two animations. Key values are compared only at times present in both
+/// files, keys without a key at the same time in the other file are counted separately.
+pub(crate) struct BoneDiff {
+    pub(crate) index: usize,
+    pub(crate) weights: (f32, f32),
+    pub(crate) num_rotation_keys: (usize, usize),
+    pub(crate) num_translation_keys: (usize, usize),
+    pub(crate) rotation_keys: KeysDiff,
+    pub(crate) translation_keys: KeysDiff,
+}
+
+/// Differences between keys of one kind (rotation or translation) of the same bone
+#[derive(Default)]
+pub(crate) struct KeysDiff {
+    /// Number of keys with the same time in both files
+    pub(crate) num_common: usize,
+    /// Number of keys in both files without a key with the same time in the other file
+    pub(crate) num_unmatched: usize,
+    /// Maximal angle in degrees (rotation) or distance (translation) between keys with the same time
+    pub(crate) max_delta: f32,
+    /// Number of keys with the same time and different ease (rotation) or tangents (translation)
+    pub(crate) num_changed_shapes: usize,
+}
+
+impl KeysDiff {
+    fn is_changed(&self) -> bool {
+        self.num_unmatched > 0 || self.max_delta > 0.0 || self.num_changed_shapes > 0
+    }
+}
+
+impl BoneDiff {
+    pub(crate) fn is_changed(&self) -> bool {
+        self.weights.0 != self.weights.1
+            || self.num_rotation_keys.0 != self.num_rotation_keys.1
+            || self.num_translation_keys.0 != self.num_translation_keys.1
+            || self.rotation_keys.is_changed()
+            || self.translation_keys.is_changed()
+    }
+}
+
+/// Differences between two animations
+pub(crate) struct AnimDiff {
+    /// Descriptions of changed header fields
+    pub(crate) header_changes: Vec<String>,
+    /// Bones present in both animations (matched by index)
+    pub(crate) bones: Vec<BoneDiff>,
+    pub(crate) num_bones: (usize, usize),
+}
+
+/// Compares keys by time. `get_delta` returns the difference of values and `is_shape_changed` tells if
+/// interpolation data (ease or tangents) differs for keys with the same time. Keys must be sorted by time.
+fn compare_keys<K>(
+    old: &[K],
+    new: &[K],
+    get_time: impl Fn(&K) -> rfa::RfaTime,
+    get_delta: impl Fn(&K, &K) -> f32,
+    is_shape_changed: impl Fn(&K, &K) -> bool,
+) -> KeysDiff {
+    let mut diff = KeysDiff::default();
+    let mut new_iter = new.iter().peekable();
+    for old_key in old {
+        while new_iter
+            .next_if(|k| get_time(k) < get_time(old_key))
+            .is_some()
+        {}
+        if let Some(new_key) = new_iter.next_if(|k| get_time(k) == get_time(old_key)) {
+            diff.num_common += 1;
+            diff.max_delta = diff.max_delta.max(get_delta(old_key, new_key));
+            if is_shape_changed(old_key, new_key) {
+                diff.num_changed_shapes += 1;
+            }
+        }
+    }
+    diff.num_unmatched = old.len() + new.len() - 2 * diff.num_common;
+    diff
+}
+
+fn diff_bone(index: usize, old: &rfa::Bone, new: &rfa::Bone) -> BoneDiff {
+    let rotation_keys = compare_keys(
+        &old.rotation_keys,
+        &new.rotation_keys,
+        |k| k.time,
+        |a, b| {
+            // angle between equal quaternions is not exactly zero because of rounding
+            if a.rotation == b.rotation {
+                return 0.0;
+            }
+            a.rotation
+                .to_quat()
+                .angle_between(b.rotation.to_quat())
+                .to_degrees()
+        },
+        |a, b| a.ease_in != b.ease_in || a.ease_out != b.ease_out,
+    );
+    let translation_keys = compare_keys(
+        &old.translation_keys,
+        &new.translation_keys,
+        |k| k.time,
+        |a, b| glam::Vec3::from(a.translation).distance(b.translation.into()),
+        |a, b| a.in_tangent != b.in_tangent || a.out_tangent != b.out_tangent,
+    );
+    BoneDiff {
+        index,
+        weights: (old.weight, new.weight),
+        num_rotation_keys: (old.rotation_keys.len(), new.rotation_keys.len()),
+        num_translation_keys: (old.translation_keys.len(), new.translation_keys.len()),
+        rotation_keys,
+        translation_keys,
+    }
+}
+
+fn diff_headers(old: &rfa::FileHeader, new: &rfa::FileHeader) -> Vec<String> {
+    let mut changes = Vec::new();
+    let times = [
+        ("start time", old.start_time, new.start_time),
+        ("end time", old.end_time, new.end_time),
+        ("ramp in time", old.ramp_in_time, new.ramp_in_time),
+        ("ramp out time", old.ramp_out_time, new.ramp_out_time),
+    ];
+    for (name, old_time, new_time) in times {
+        if old_time != new_time {
+            // times come from the files so the difference can overflow
+            let delta = match new_time.checked_sub(old_time) {
+                Some(delta) => format!("{:+.3} s", delta.to_seconds(DEFAULT_FPS)),
+                None => "invalid".to_owned(),
+            };
+            changes.push(format!(
+                "{}: {} -> {} ticks ({})",
+                name, old_time, new_time, delta
+            ));
+        }
+    }
+    if old.total_rotation != new.total_rotation {
+        let delta = glam::Quat::from_array(old.total_rotation)
+            .angle_between(glam::Quat::from_array(new.total_rotation))
+            .to_degrees();
+        changes.push(format!(
+            "total rotation: {:?} -> {:?} ({:.3} deg)",
+            old.total_rotation, new.total_rotation, delta
+        ));
+    }
+    if old.total_translation != new.total_translation {
+        let delta = glam::Vec3::from(old.total_translation).distance(new.total_translation.into());
+        changes.push(format!(
+            "total translation: {:?} -> {:?} ({:.4})",
+            old.total_translation, new.total_translation, delta
+        ));
+    }
+    changes
+}
+
+/// Compares two animations. Bones are matched by index like in the game.
+pub(crate) fn diff_rfa(old: &rfa::File, new: &rfa::File) -> AnimDiff {
+    AnimDiff {
+        header_changes: diff_headers(&old.header, &new.header),
+        bones: old
+            .bones
+            .iter()
+            .zip(&new.bones)
+            .enumerate()
+            .map(|(i, (old_bone, new_bone))| diff_bone(i, old_bone, new_bone))
+            .collect(),
+        num_bones: (old.bones.len(), new.bones.len()),
+    }
+}
+
+impl AnimDiff {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.header_changes.is_empty()
+            && self.num_bones.0 == self.num_bones.1
+            && !self.bones.iter().any(BoneDiff::is_changed)
+    }
+
+    /// Formats a human-readable summary listing changed header fields and bones. `bone_names` are used in bone
+    /// descriptions if they are not empty.
+    pub(crate) fn summary(&self, bone_names: &[String]) -> String {
+        if self.is_empty() {
+            return "Animations are identical".to_owned();
+        }
+        let mut lines: Vec<_> = self.header_changes.clone();
+        if self.num_bones.0 != self.num_bones.1 {
+            lines.push(format!(
+                "bones: {} -> {} (only the first {} are compared)",
+                self.num_bones.0,
+                self.num_bones.1,
+                self.bones.len()
+            ));
+        }
+        let changed_bones: Vec<_> = self.bones.iter().filter(|b| b.is_changed()).collect();
+        for bone in &changed_bones {
+            let name = bone_names.get(bone.index).map_or_else(
+                || format!("Bone {}", bone.index),
+                |n| format!("Bone {} {}", bone.index, n),
+            );
+            let mut parts = Vec::new();
+            if bone.weights.0 != bone.weights.1 {
+                parts.push(format!("weight {} -> {}", bone.weights.0, bone.weights.1));
+            }
+            if bone.num_rotation_keys.0 != bone.num_rotation_keys.1 {
+                parts.push(format!(
+                    "rotation keys {} -> {}",
+                    bone.num_rotation_keys.0, bone.num_rotation_keys.1
+                ));
+            }
+            if bone.num_translation_keys.0 != bone.num_translation_keys.1 {
+                parts.push(format!(
+                    "translation keys {} -> {}",
+                    bone.num_translation_keys.0, bone.num_translation_keys.1
+                ));
+            }
+            if bone.rotation_keys.max_delta > 0.0 {
+                parts.push(format!(
+                    "rotation up to {:.3} deg ({} common keys)",
+                    bone.rotation_keys.max_delta, bone.rotation_keys.num_common
+                ));
+            }
+            if bone.translation_keys.max_delta > 0.0 {
+                parts.push(format!(
+                    "translation up to {:.4} ({} common keys)",
+                    bone.translation_keys.max_delta, bone.translation_keys.num_common
+                ));
+            }
+            let kinds = [
+                ("rotation", "ease", &bone.rotation_keys),
+                ("translation", "tangents", &bone.translation_keys),
+            ];
+            for (kind, shape, keys) in kinds {
+                if keys.num_unmatched > 0 {
+                    parts.push(format!(
+                        "{} {} keys without a key at the same time",
+                        keys.num_unmatched, kind
+                    ));
+                }
+                if keys.num_changed_shapes > 0 {
+                    parts.push(format!(
+                        "{} changed in {} {} keys",
+                        shape, keys.num_changed_shapes, kind
+                    ));
+                }
+            }
+            lines.push(format!("{}: {}", name, parts.join(", ")));
+        }
+        lines.push(format!(
+            "Changed bones: {} of {}",
+            changed_bones.len(),
+            self.bones.len()
+        ));
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_bone(keys: &[(i32, [i16; 4], [f32; 3])]) -> rfa::Bone {
+        rfa::Bone {
+            weight: 10.0,
+            rotation_keys: keys
+                .iter()
+                .map(|&(time, rotation, _)| rfa::RotationKey {
+                    time: rfa::RfaTime(time),
+                    rotation: rfa::ShortQuat(rotation),
+                    ease_in: 0,
+                    ease_out: 0,
+                })
+                .collect(),
+            translation_keys: keys
+                .iter()
+                .map(|&(time, _, translation)| rfa::TranslationKey {
+                    time: rfa::RfaTime(time),
+                    translation,
+                    in_tangent: translation,
+                    out_tangent: translation,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn diff_rfa_reports_changed_bones_and_header() {
+        const IDENTITY: [i16; 4] = [0, 0, 0, 16383];
+        // 90 degrees around Y axis
+        const TURN: [i16; 4] = [0, 11585, 0, 11585];
+        let old = rfa::File {
+            header: rfa::FileHeader {
+                end_time: rfa::RfaTime(320),
+                ..rfa::FileHeader::default()
+            },
+            bones: vec![
+                make_bone(&[(0, IDENTITY, [0.0; 3]), (320, TURN, [1.0, 0.0, 0.0])]),
+                make_bone(&[(0, IDENTITY, [0.0; 3])]),
+            ],
+        };
+        let new = rfa::File {
+            header: rfa::FileHeader {
+                end_time: rfa::RfaTime(480),
+                ..rfa::FileHeader::default()
+            },
+            bones: vec![
+                make_bone(&[
+                    (0, IDENTITY, [0.0; 3]),
+                    (160, IDENTITY, [0.5, 0.0, 0.0]),
+                    (320, IDENTITY, [1.0, 0.0, 0.5]),
+                ]),
+                make_bone(&[(0, IDENTITY, [0.0; 3])]),
+            ],
+        };
+        let diff = diff_rfa(&old, &new);
+
+        assert_eq!(
+            diff.header_changes,
+            ["end time: 320 -> 480 ticks (+0.033 s)"]
+        );
+        assert!(diff.bones[0].is_changed());
+        assert!(!diff.bones[1].is_changed());
+        assert_eq!(diff.bones[0].num_rotation_keys, (2, 3));
+        assert_eq!(diff.bones[0].rotation_keys.num_common, 2);
+        assert_eq!(diff.bones[0].rotation_keys.num_unmatched, 1);
+        assert!((diff.bones[0].rotation_keys.max_delta - 90.0).abs() < 0.01);
+        assert_eq!(diff.bones[0].translation_keys.max_delta, 0.5);
+        let names = ["root".to_owned(), "head".to_owned()];
+        assert!(diff.summary(&names).ends_with("Changed bones: 1 of 2"));
+        assert!(diff
+            .summary(&names)
+            .contains("Bone 0 root: rotation keys 2 -> 3"));
+
+        assert!(diff_rfa(&old, &old).is_empty());
+    }
+
+    #[test]
+    fn diff_rfa_reports_moved_keys_and_changed_interpolation() {
+        const IDENTITY: [i16; 4] = [0, 0, 0, 16383];
+        let make_file = |bone| rfa::File {
+            header: rfa::FileHeader::default(),
+            bones: vec![bone],
+        };
+        let old = make_bone(&[(0, IDENTITY, [0.0; 3]), (320, IDENTITY, [0.0; 3])]);
+        // same key values and key count but the second key is moved
+        let moved = make_bone(&[(0, IDENTITY, [0.0; 3]), (480, IDENTITY, [0.0; 3])]);
+        let diff = diff_rfa(&make_file(old.clone()), &make_file(moved));
+        assert!(diff.bones[0].is_changed());
+        assert_eq!(diff.bones[0].rotation_keys.num_unmatched, 2);
+        assert_eq!(diff.bones[0].translation_keys.num_unmatched, 2);
+
+        let mut eased = old.clone();
+        eased.rotation_keys[1].ease_in = 10;
+        eased.translation_keys[0].out_tangent = [1.0, 0.0, 0.0];
+        let diff = diff_rfa(&make_file(old), &make_file(eased));
+        assert!(diff.bones[0].is_changed());
+        assert_eq!(diff.bones[0].rotation_keys.num_changed_shapes, 1);
+        assert_eq!(diff.bones[0].translation_keys.num_changed_shapes, 1);
+        let summary = diff.summary(&[]);
+        assert!(
+            summary.contains("ease changed in 1 rotation keys"),
+            "{}",
+            summary
+        );
+        assert!(
+            summary.contains("tangents changed in 1 translation keys"),
+            "{}",
+            summary
+        );
+    }
+
+    #[test]
+    fn diff_headers_does_not_overflow_on_crafted_times() {
+        let old = rfa::FileHeader {
+            start_time: rfa::RfaTime(-2_000_000_000),
+            ..rfa::FileHeader::default()
+        };
+        let new = rfa::FileHeader {
+            start_time: rfa::RfaTime(2_000_000_000),
+            ..rfa::FileHeader::default()
+        };
+        assert_eq!(
+            diff_headers(&old, &new),
+            ["start time: -2000000000 -> 2000000000 ticks (invalid)"]
+        );
+    }
+}
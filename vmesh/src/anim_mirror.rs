@@ -0,0 +1,222 @@
+use crate::io_utils::new_custom_error;
+use crate::rfa;
+use clap::ValueEnum;
+
+/// Axis perpendicular to the mirror plane (in RF space)
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub(crate) enum MirrorAxis {
+    X,
+    Y,
+    Z,
+}
+
+impl MirrorAxis {
+    fn index(self) -> usize {
+        match self {
+            Self::X => 0,
+            Self::Y => 1,
+            Self::Z => 2,
+        }
+    }
+
+    fn mirror_vec(self, v: [f32; 3]) -> [f32; 3] {
+        let mut mirrored = v;
+        mirrored[self.index()] = -v[self.index()];
+        mirrored
+    }
+
+    /// Rotation axis is a pseudovector so components parallel to the mirror plane are negated instead
+    /// of the component along the mirror axis. The angle is unchanged.
+    fn mirror_quat<T: Copy + std::ops::Neg<Output = T>>(self, q: [T; 4]) -> [T; 4] {
+        let mut mirrored = [-q[0], -q[1], -q[2], q[3]];
+        mirrored[self.index()] = q[self.index()];
+        mirrored
+    }
+}
+
+/// Parses bone name mapping in `<left>=<right>` form, e.g. `L_=R_`
+pub(crate) fn parse_bone_name_pair(mapping: &str) -> std::io::Result<(String, String)> {
+    match mapping.split_once('=') {
+        Some((left, right)) if !left.is_empty() && !right.is_empty() => {
+            Ok((left.to_owned(), right.to_owned()))
+        }
+        _ => Err(new_custom_error(format!(
+            "invalid bone name pair {}: expected <left>=<right>",
+            mapping
+        ))),
+    }
+}
+
+/// Finds name of the mirror partner of a bone by replacing the first part of the name matching one side of
+/// a pair with the other side
+fn get_partner_name(name: &str, pairs: &[(String, String)]) -> Option<String> {
+    pairs.iter().find_map(|(left, right)| {
+        if name.contains(left.as_str()) {
+            Some(name.replacen(left.as_str(), right, 1))
+        } else if name.contains(right.as_str()) {
+            Some(name.replacen(right.as_str(), left, 1))
+        } else {
+            None
+        }
+    })
+}
+
+/// Returns index of the bone whose keys are used (after mirroring) for every bone. Bones without a partner
+/// use their own keys.
+fn find_partner_indices(bone_names: &[String], pairs: &[(String, String)]) -> Vec<usize> {
+    bone_names
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            let Some(partner_name) = get_partner_name(name, pairs) else {
+                return i;
+            };
+            match bone_names.iter().position(|n| *n == partner_name) {
+                Some(partner_index) => partner_index,
+                None => {
+                    warn!(
+                        "Bone {} has no mirror partner (expected {}). It is mirrored in place.",
+                        name, partner_name
+                    );
+                    i
+                }
+            }
+        })
+        .collect()
+}
+
+fn mirror_bone(bone: &rfa::Bone, axis: MirrorAxis) -> rfa::Bone {
+    let rotation_keys = bone
+        .rotation_keys
+        .iter()
+        .map(|k| rfa::RotationKey {
+            rotation: rfa::ShortQuat(axis.mirror_quat(k.rotation.0)),
+            ..k.clone()
+        })
+        .collect();
+    let translation_keys = bone
+        .translation_keys
+        .iter()
+        .map(|k| rfa::TranslationKey {
+            time: k.time,
+            translation: axis.mirror_vec(k.translation),
+            in_tangent: axis.mirror_vec(k.in_tangent),
+            out_tangent: axis.mirror_vec(k.out_tangent),
+        })
+        .collect();
+    rfa::Bone {
+        weight: bone.weight,
+        rotation_keys,
+        translation_keys,
+    }
+}
+
+/// Mirrors an animation across the plane perpendicular to the axis. Keys of bones paired by name (e.g. `L_`
+/// and `R_` parts of names) are swapped. `bone_names` has to contain names of all RFA bones (taken from
+/// the character mesh) or be empty if bones should not be swapped.
+pub(crate) fn mirror_rfa(
+    rfa: &rfa::File,
+    axis: MirrorAxis,
+    bone_names: &[String],
+    pairs: &[(String, String)],
+) -> std::io::Result<rfa::File> {
+    if !bone_names.is_empty() && bone_names.len() != rfa.bones.len() {
+        return Err(new_custom_error(format!(
+            "animation has {} bones but the skeleton has {}",
+            rfa.bones.len(),
+            bone_names.len()
+        )));
+    }
+    let partner_indices = if bone_names.is_empty() {
+        (0..rfa.bones.len()).collect()
+    } else {
+        find_partner_indices(bone_names, pairs)
+    };
+    let bones = partner_indices
+        .iter()
+        .map(|&i| mirror_bone(&rfa.bones[i], axis))
+        .collect();
+    let header = rfa::FileHeader {
+        total_rotation: axis.mirror_quat(rfa.header.total_rotation),
+        total_translation: axis.mirror_vec(rfa.header.total_translation),
+        ..rfa.header
+    };
+    Ok(rfa::File { header, bones })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_bone(rotation: [i16; 4], translation: [f32; 3]) -> rfa::Bone {
+        rfa::Bone {
+            weight: 10.0,
+            rotation_keys: vec![rfa::RotationKey {
+                time: rfa::RfaTime::ZERO,
+                rotation: rfa::ShortQuat(rotation),
+                ease_in: 0,
+                ease_out: 0,
+            }],
+            translation_keys: vec![rfa::TranslationKey {
+                time: rfa::RfaTime::ZERO,
+                translation,
+                in_tangent: translation,
+                out_tangent: translation,
+            }],
+        }
+    }
+
+    #[test]
+    fn mirror_rfa_swaps_bone_pairs_and_reflects_keys() {
+        let rfa = rfa::File {
+            header: rfa::FileHeader {
+                total_translation: [1.0, 0.0, 2.0],
+                ..rfa::FileHeader::default()
+            },
+            bones: vec![
+                make_bone([0, 0, 0, 16383], [0.0, 1.0, 0.0]),
+                make_bone([0, 11585, 0, 11585], [1.0, 2.0, 3.0]),
+                make_bone([0, 0, 0, 16383], [-1.0, 2.0, 3.0]),
+                make_bone([100, 200, 300, 16000], [4.0, 5.0, 6.0]),
+            ],
+        };
+        let names: Vec<_> = ["pelvis", "L_arm", "R_arm", "L_tail"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let pairs = [parse_bone_name_pair("L_=R_").unwrap()];
+        let mirrored = mirror_rfa(&rfa, MirrorAxis::X, &names, &pairs).unwrap();
+
+        assert_eq!(mirrored.header.total_translation, [-1.0, 0.0, 2.0]);
+        assert_eq!(
+            mirrored.bones[0].translation_keys[0].translation,
+            [-0.0, 1.0, 0.0]
+        );
+        // L_arm gets mirrored keys of R_arm and vice versa
+        assert_eq!(
+            mirrored.bones[1].translation_keys[0].translation,
+            [1.0, 2.0, 3.0]
+        );
+        assert_eq!(
+            mirrored.bones[2].rotation_keys[0].rotation.0,
+            [0, -11585, 0, 11585]
+        );
+        // bone without a partner is mirrored in place
+        assert_eq!(
+            mirrored.bones[3].rotation_keys[0].rotation.0,
+            [100, -200, -300, 16000]
+        );
+
+        let restored = mirror_rfa(&mirrored, MirrorAxis::X, &names, &pairs).unwrap();
+        for (restored, original) in restored.bones.iter().zip(&rfa.bones) {
+            assert_eq!(
+                restored.rotation_keys[0].rotation,
+                original.rotation_keys[0].rotation
+            );
+            assert_eq!(
+                restored.translation_keys[0].translation,
+                original.translation_keys[0].translation
+            );
+        }
+    }
+}
@@ -0,0 +1,180 @@
+use crate::gltf_export::get_bone_world_transform;
+use crate::io_utils::new_custom_error;
+use crate::rfa::{RfaTime, ShortQuat};
+use crate::{gltf_to_rf_quat, gltf_to_rf_vec, rfa, v3mc, CoordinateConvention};
+
+/// Weight of bones added to the retargeted animation. Same as weight of not animated bones in converted animations.
+const ADDED_BONE_WEIGHT: f32 = 2.0;
+
+/// Creates a track holding the bind pose of a target skeleton bone, so the bone stays in place
+fn make_bind_pose_bone(
+    bones: &[v3mc::Bone],
+    index: usize,
+    time: RfaTime,
+) -> std::io::Result<rfa::Bone> {
+    let bone = &bones[index];
+    let parent_transform = match bone.parent_index {
+        -1 => glam::Mat4::IDENTITY,
+        p if p >= 0 && (p as usize) < bones.len() => get_bone_world_transform(&bones[p as usize]),
+        p => {
+            return Err(new_custom_error(format!(
+                "bone {} has invalid parent index {}",
+                bone.name, p
+            )))
+        }
+    };
+    let local_transform = parent_transform.inverse() * get_bone_world_transform(bone);
+    let (_scale, rotation, translation) = local_transform.to_scale_rotation_translation();
+    // skeleton transforms are converted to Y-up GLTF space by get_bone_world_transform
+    let rotation = gltf_to_rf_quat(rotation.into(), CoordinateConvention::YUp);
+    let translation = gltf_to_rf_vec(translation.into(), CoordinateConvention::YUp);
+    Ok(rfa::Bone {
+        weight: ADDED_BONE_WEIGHT,
+        rotation_keys: vec![rfa::RotationKey {
+            time,
+            rotation: ShortQuat::from_quat(glam::Quat::from_array(rotation)).unwrap_or_else(|| {
+                warn!(
+                    "Bone {} has invalid rotation {:?} in the target skeleton. Identity has been used instead",
+                    bone.name, rotation
+                );
+                ShortQuat::IDENTITY
+            }),
+            ease_in: 0,
+            ease_out: 0,
+        }],
+        translation_keys: vec![rfa::TranslationKey {
+            time,
+            translation,
+            in_tangent: translation,
+            out_tangent: translation,
+        }],
+    })
+}
+
+/// Reorders bones of an animation made for the source skeleton so it can be played on the target skeleton.
+/// Bones are matched by name. Bones missing in the target skeleton are dropped and bones missing in the source
+/// skeleton get a track holding their bind pose. A warning is printed in both cases.
+pub(crate) fn retarget_rfa(
+    rfa: &rfa::File,
+    source_bone_names: &[String],
+    target_bones: &[v3mc::Bone],
+) -> std::io::Result<rfa::File> {
+    if source_bone_names.len() != rfa.bones.len() {
+        return Err(new_custom_error(format!(
+            "animation has {} bones but the source skeleton has {}",
+            rfa.bones.len(),
+            source_bone_names.len()
+        )));
+    }
+    let dropped: Vec<_> = source_bone_names
+        .iter()
+        .filter(|name| !target_bones.iter().any(|b| b.name == **name))
+        .map(String::as_str)
+        .collect();
+    if !dropped.is_empty() {
+        warn!(
+            "Bones missing in the target skeleton have been dropped from the animation: {}",
+            dropped.join(", ")
+        );
+    }
+    let mut added = Vec::new();
+    let mut bones = Vec::with_capacity(target_bones.len());
+    for (i, target_bone) in target_bones.iter().enumerate() {
+        match source_bone_names
+            .iter()
+            .position(|n| *n == target_bone.name)
+        {
+            Some(source_index) => bones.push(rfa.bones[source_index].clone()),
+            None => {
+                added.push(target_bone.name.as_str());
+                bones.push(make_bind_pose_bone(target_bones, i, rfa.header.start_time)?);
+            }
+        }
+    }
+    if !added.is_empty() {
+        warn!(
+            "Bones missing in the source skeleton use their bind pose: {}",
+            added.join(", ")
+        );
+    }
+    let header = rfa::FileHeader {
+        num_bones: bones.len() as i32,
+        ..rfa.header
+    };
+    Ok(rfa::File { header, bones })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_bone(time: i32, translation: [f32; 3]) -> rfa::Bone {
+        rfa::Bone {
+            weight: 10.0,
+            rotation_keys: vec![rfa::RotationKey {
+                time: RfaTime(time),
+                rotation: ShortQuat::IDENTITY,
+                ease_in: 0,
+                ease_out: 0,
+            }],
+            translation_keys: vec![rfa::TranslationKey {
+                time: RfaTime(time),
+                translation,
+                in_tangent: translation,
+                out_tangent: translation,
+            }],
+        }
+    }
+
+    fn make_skeleton_bone(
+        name: &str,
+        inverse_bind_translation: [f32; 3],
+        parent_index: i32,
+    ) -> v3mc::Bone {
+        v3mc::Bone {
+            name: name.to_owned(),
+            base_rotation: [0.0, 0.0, 0.0, 1.0],
+            base_translation: inverse_bind_translation,
+            parent_index,
+        }
+    }
+
+    #[test]
+    fn retarget_rfa_reorders_bones_by_name() {
+        let rfa = rfa::File {
+            header: rfa::FileHeader {
+                num_bones: 3,
+                end_time: RfaTime(4800),
+                ..rfa::FileHeader::default()
+            },
+            bones: vec![
+                make_bone(0, [0.0, 1.0, 0.0]),
+                make_bone(100, [0.0, 0.5, 0.0]),
+                make_bone(200, [0.0, 0.2, 0.0]),
+            ],
+        };
+        let source_names: Vec<_> = ["root", "spine", "tail"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let target_bones = [
+            make_skeleton_bone("spine", [0.0, -1.5, 0.0], 2),
+            make_skeleton_bone("head", [0.0, -2.0, 0.0], 0),
+            make_skeleton_bone("root", [0.0, -1.0, 0.0], -1),
+        ];
+        let retargeted = retarget_rfa(&rfa, &source_names, &target_bones).unwrap();
+
+        assert_eq!(retargeted.header.num_bones, 3);
+        assert_eq!(retargeted.header.end_time, RfaTime(4800));
+        assert_eq!(retargeted.bones[0].rotation_keys[0].time, RfaTime(100));
+        assert_eq!(retargeted.bones[2].rotation_keys[0].time, RfaTime::ZERO);
+        // head is missing in the source so it holds its bind pose relative to spine
+        let head = &retargeted.bones[1];
+        assert_eq!(head.weight, ADDED_BONE_WEIGHT);
+        assert_eq!(head.rotation_keys[0].rotation, ShortQuat::IDENTITY);
+        assert_eq!(head.translation_keys[0].translation, [0.0, 0.5, 0.0]);
+        assert!(retargeted.validate().is_ok());
+
+        assert!(retarget_rfa(&rfa, &source_names[..2], &target_bones).is_err());
+    }
+}
@@ -1,14 +1,22 @@
-use crate::io_utils::new_custom_error;
+use crate::io_utils::{add_path_to_error, new_custom_error};
+use crate::rfa::{RfaTime, ShortQuat};
 use crate::v3mc_convert::get_node_extras;
-use crate::{gltf_to_rf_quat, gltf_to_rf_vec, rfa, v3mc, Context};
+use crate::{
+    get_node_local_transform, gltf_to_rf_pos, gltf_to_rf_quat, rfa, v3mc, Args, Context,
+    CoordinateConvention,
+};
+use clap::ValueEnum;
 use gltf::animation::util::{ReadInputs, ReadOutputs};
-use gltf::animation::Interpolation;
-use serde_derive::Deserialize;
+use gltf::animation::{Interpolation, Property};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use serde_derive::{Deserialize, Serialize};
 use serde_json::Value;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashSet};
 use std::f32;
 use std::fs::File;
 use std::io::BufWriter;
+use std::path::{Path, PathBuf};
 use std::vec::Vec;
 
 #[derive(Deserialize, Debug, Default)]
@@ -30,17 +38,244 @@ impl JointExtras {
         self.get_float(&format!("weight.{}", anim_name))
     }
 
+    fn get_default_weight(&self) -> Option<f32> {
+        self.get_float("weight")
+    }
+
     fn get_float(&self, key: &str) -> Option<f32> {
         self.map.get(key).and_then(|v| v.as_f64()).map(|v| v as f32)
     }
+
+    /// Returns name of the attachment point defined by `rf_attach_point` extra: `true` uses the joint name and
+    /// a string sets the name explicitly
+    fn get_attach_point_name(&self, joint_name: &str) -> Option<String> {
+        match self.map.get("rf_attach_point")? {
+            Value::Bool(true) => Some(joint_name.to_owned()),
+            Value::String(name) if !name.is_empty() => Some(name.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// Event marker (e.g. footstep or melee hit frame) defined in animation extras
+#[derive(Deserialize, Debug)]
+struct AnimEvent {
+    /// Time in seconds
+    time: f32,
+    name: String,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct AnimExtras {
+    ramp_in_time: Option<f32>,
+    ramp_out_time: Option<f32>,
+    additive: Option<bool>,
+    #[serde(rename = "loop")]
+    looping: Option<bool>,
+    #[serde(default)]
+    events: Vec<AnimEvent>,
+    #[serde(flatten)]
+    map: BTreeMap<String, Value>,
+}
+
+impl AnimExtras {
+    fn get_joint_weight(&self, joint_name: &str) -> Option<f32> {
+        self.map
+            .get(&format!("weight.{}", joint_name))
+            .and_then(|v| v.as_f64())
+            .map(|v| v as f32)
+    }
+}
+
+fn get_anim_extras(anim: &gltf::Animation) -> AnimExtras {
+    anim.extras()
+        .as_ref()
+        .and_then(|raw| serde_json::from_str::<AnimExtras>(raw.get()).ok())
+        .unwrap_or_default()
+}
+
+/// Frame rate assumed for glTF animations if not overridden by `--fps`
+pub(crate) const DEFAULT_FPS: f32 = (rfa::RFA_TICKS_PER_SECOND / rfa::RFA_TICKS_PER_FRAME) as f32;
+
+/// Easing applied uniformly to all keys of converted animations
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub(crate) enum EasePreset {
+    /// Keep easing derived from the source keys (none for linear interpolation)
+    #[default]
+    Source,
+    /// No easing on any key, also for keys derived from CUBICSPLINE tangents
+    Linear,
+    /// Slow down when approaching every key
+    EaseIn,
+    /// Slow down when leaving every key
+    EaseOut,
+    /// Slow down when approaching and leaving every key
+    EaseInOut,
+}
+
+/// Options of animation conversion that are shared by all converted animations. The CLI fills it from the
+/// command line arguments once and passes it by reference to the converters. There is no additive option because
+/// RFA has no flag for additive animations (see `rfa::FileHeader`).
+#[derive(Debug, Clone)]
+pub(crate) struct ConversionConfig {
+    /// Frame rate used for conversion of animation time to RFA ticks
+    pub(crate) fps: f32,
+    /// Bone weight used when it is not defined in extras
+    pub(crate) anim_weight: Option<f32>,
+    /// Ramp in time in seconds used when it is not defined in extras
+    pub(crate) ramp_in_time: Option<f32>,
+    /// Ramp out time in seconds used when it is not defined in extras
+    pub(crate) ramp_out_time: Option<f32>,
+    /// Angular tolerance of rotation keyframe reduction in degrees, `None` disables the reduction
+    pub(crate) reduce_rotation_tolerance: Option<f32>,
+    /// Positional tolerance of translation keyframe reduction, `None` disables the reduction
+    pub(crate) reduce_translation_tolerance: Option<f32>,
+    /// Maximal translation change for which translation keys of non-root bones are collapsed into a single key,
+    /// `None` keeps all keys
+    pub(crate) collapse_translation_tolerance: Option<f32>,
+    /// Frame rate used for resampling of animation channels, `None` keeps source keys
+    pub(crate) bake_fps: Option<f32>,
+    /// Axis convention of the source file
+    pub(crate) coordinate_convention: CoordinateConvention,
+    /// Factor all translations are multiplied by
+    pub(crate) scale: f32,
+    /// Name of the joint carrying root motion, `None` selects it automatically
+    pub(crate) root_bone: Option<String>,
+    /// Insert the bind pose at the start of animations that do not begin in it
+    pub(crate) prepend_bind_pose: bool,
+    /// Easing applied to all keys
+    pub(crate) ease_preset: EasePreset,
+    /// Time window in seconds (start, end) animations are cut to, `None` keeps whole animations
+    pub(crate) trim: Option<(f32, f32)>,
+    /// Grid step translation keys are rounded to, `None` keeps full precision
+    pub(crate) quantize_translation: Option<f32>,
+    /// Number of most significant bits kept in rotation key components, `None` keeps all 16 bits
+    pub(crate) rotation_bits: Option<u32>,
+    /// Name patterns of animations that are meant to loop when they are not marked by `loop` extra
+    pub(crate) loop_anims: Vec<String>,
+    /// Remove uniform scale of bones in the bind pose instead of failing
+    pub(crate) bake_bone_scale: bool,
+}
+
+impl Default for ConversionConfig {
+    fn default() -> Self {
+        Self {
+            fps: DEFAULT_FPS,
+            anim_weight: None,
+            ramp_in_time: None,
+            ramp_out_time: None,
+            reduce_rotation_tolerance: None,
+            reduce_translation_tolerance: None,
+            collapse_translation_tolerance: None,
+            bake_fps: None,
+            coordinate_convention: CoordinateConvention::default(),
+            scale: 1.0_f32,
+            root_bone: None,
+            prepend_bind_pose: false,
+            ease_preset: EasePreset::default(),
+            trim: None,
+            quantize_translation: None,
+            rotation_bits: None,
+            loop_anims: Vec::new(),
+            bake_bone_scale: false,
+        }
+    }
+}
+
+impl From<&Args> for ConversionConfig {
+    fn from(args: &Args) -> Self {
+        Self {
+            fps: args.fps,
+            anim_weight: args.anim_weight,
+            ramp_in_time: args.ramp_in_time,
+            ramp_out_time: args.ramp_out_time,
+            reduce_rotation_tolerance: args.reduce_rotation_tolerance,
+            reduce_translation_tolerance: args.reduce_translation_tolerance,
+            collapse_translation_tolerance: args.collapse_translation_tolerance,
+            bake_fps: args.bake_fps,
+            coordinate_convention: args.coordinate_convention,
+            scale: args.scale,
+            root_bone: args.root_bone.clone(),
+            prepend_bind_pose: args.prepend_bind_pose,
+            ease_preset: args.ease,
+            trim: args.trim.as_deref().map(|t| (t[0], t[1])),
+            quantize_translation: args.quantize_translation,
+            rotation_bits: args.rotation_bits,
+            loop_anims: args.loop_anim.clone(),
+            bake_bone_scale: args.bake_bone_scale,
+        }
+    }
 }
 
-fn gltf_time_to_rfa_time(time_sec: f32) -> i32 {
-    (time_sec * 30.0_f32 * 160.0_f32) as i32
+fn check_for_key_time_collisions(
+    times: impl Iterator<Item = RfaTime>,
+    keys_kind: &str,
+    n: &gltf::Node,
+    anim: &gltf::Animation,
+) {
+    let times = times.collect::<Vec<_>>();
+    let num_collisions = times.windows(2).filter(|w| w[0] == w[1]).count();
+    if num_collisions > 0 {
+        warn!(
+            "Animation #{} '{}' has {} {} key(s) on node #{} '{}' mapped to the same RFA time as the previous key!",
+            anim.index(),
+            anim.name().unwrap_or_default(),
+            num_collisions,
+            keys_kind,
+            n.index(),
+            n.name().unwrap_or_default(),
+        );
+    }
+}
+
+fn is_quat_normalized(quat: [f32; 4]) -> bool {
+    (glam::Vec4::from(quat).length() - 1.0_f32).abs() <= 0.001_f32
+}
+
+fn is_quat_invalid(quat: [f32; 4]) -> bool {
+    glam::Vec4::from(quat).try_normalize().is_none()
 }
 
-fn make_short_quat(quat: [f32; 4]) -> [i16; 4] {
-    quat.map(|x| (x * 16383.0_f32) as i16)
+/// Warns about rotation keys which are not unit quaternions. Keys that cannot be normalized at all (zero length
+/// or NaN components) are replaced with identity when packed.
+fn check_for_denormalized_quats(quats: &[[f32; 4]], n: &gltf::Node, anim: &gltf::Animation) {
+    let num_invalid = quats.iter().filter(|q| is_quat_invalid(**q)).count();
+    if num_invalid > 0 {
+        warn!(
+            "Animation #{} '{}' has {} rotation key(s) with zero length or NaN components on node #{} '{}'. They have been replaced with identity.",
+            anim.index(),
+            anim.name().unwrap_or_default(),
+            num_invalid,
+            n.index(),
+            n.name().unwrap_or_default(),
+        );
+    }
+    let num_denormalized = quats
+        .iter()
+        .filter(|q| !is_quat_normalized(**q) && !is_quat_invalid(**q))
+        .count();
+    if num_denormalized > 0 {
+        warn!(
+            "Animation #{} '{}' has {} denormalized rotation key(s) on node #{} '{}'. They have been normalized.",
+            anim.index(),
+            anim.name().unwrap_or_default(),
+            num_denormalized,
+            n.index(),
+            n.name().unwrap_or_default(),
+        );
+    }
+}
+
+/// Packs a rotation computed from node transforms or other keys. It is invalid only if the transforms are
+/// degenerate (e.g. zero scale), in that case identity is used and a warning is printed.
+fn pack_computed_rotation(rotation: glam::Quat) -> ShortQuat {
+    ShortQuat::from_quat(rotation).unwrap_or_else(|| {
+        warn!(
+            "Rotation {} has zero length or NaN components (degenerate transform). Identity has been used instead.",
+            rotation
+        );
+        ShortQuat::IDENTITY
+    })
 }
 
 fn get_node_anim_channels<'a>(
@@ -52,101 +287,682 @@ fn get_node_anim_channels<'a>(
         .filter(move |c| c.target().node().index() == node_index)
 }
 
+/// Returns data of the channel animating the specified property of the node. If there is more than one such channel
+/// (malformed file) a warning is printed and the channel with the lowest sampler input accessor index is used so
+/// the result does not depend on the order of channels.
 fn get_node_anim_data<'a>(
     n: &gltf::Node,
     anim: &'a gltf::Animation,
+    property: Property,
     ctx: &'a Context,
-) -> impl Iterator<Item = (ReadInputs<'a>, ReadOutputs<'a>, Interpolation)> + 'a {
-    get_node_anim_channels(n, anim).filter_map(move |channel| {
-        let reader = channel.reader(|buffer| ctx.get_buffer_data(buffer));
-        let interpolation = channel.sampler().interpolation();
-        reader.read_inputs().and_then(|inputs| {
-            reader
-                .read_outputs()
-                .map(|outputs| (inputs, outputs, interpolation))
-        })
+) -> Option<(ReadInputs<'a>, ReadOutputs<'a>, Interpolation)> {
+    let mut channels: Vec<_> = get_node_anim_channels(n, anim)
+        .filter(|c| c.target().property() == property)
+        .collect();
+    // stable sort keeps order of channels in the file if they share the input accessor
+    channels.sort_by_key(|c| c.sampler().input().index());
+    if channels.len() > 1 {
+        warn!(
+            "Animation #{} '{}' has {} {} channels targeting node #{} '{}'. Only one of them is used.",
+            anim.index(),
+            anim.name().unwrap_or_default(),
+            channels.len(),
+            format!("{:?}", property).to_lowercase(),
+            n.index(),
+            n.name().unwrap_or_default(),
+        );
+    }
+    let channel = channels.into_iter().next()?;
+    let reader = channel.reader(|buffer| ctx.get_buffer_data(buffer));
+    let interpolation = channel.sampler().interpolation();
+    reader.read_inputs().and_then(|inputs| {
+        reader
+            .read_outputs()
+            .map(|outputs| (inputs, outputs, interpolation))
+    })
+}
+
+/// Maximal ease value that can be stored in an RFA rotation key
+const MAX_EASE: f32 = 127.0_f32;
+
+/// Estimates ease value for one side of a rotation key based on a glTF cubic spline tangent.
+///
+/// Tangent speed is compared with the average (linear) speed over the adjacent segment.
+/// A tangent as fast as the linear motion gives 0 (no easing) and a zero tangent gives
+/// the maximal value of 127 (the bone comes to a stop at the key). Values in between are mapped
+/// linearly. Speed is measured on raw quaternion components which is accurate enough for
+/// the small rotations that are typical between adjacent keys.
+fn estimate_ease(
+    tangent: [f32; 4],
+    rotation: [f32; 4],
+    adjacent_rotation: [f32; 4],
+    segment_duration: f32,
+) -> i8 {
+    if segment_duration <= 0.0_f32 {
+        return 0;
+    }
+    let linear_speed = (glam::Vec4::from(adjacent_rotation) - glam::Vec4::from(rotation)).length()
+        / segment_duration;
+    if linear_speed <= f32::EPSILON {
+        return 0;
+    }
+    let speed_ratio = glam::Vec4::from(tangent).length() / linear_speed;
+    ((1.0_f32 - speed_ratio).clamp(0.0_f32, 1.0_f32) * MAX_EASE).round() as i8
+}
+
+/// Makes sure that adjacent rotations take the short path (q and -q represent the same rotation but RF
+/// interpolates raw components). Tangents are negated together with the rotation.
+fn ensure_quat_continuity(rotations: &mut [([f32; 4], [f32; 4], [f32; 4])]) {
+    for i in 1..rotations.len() {
+        let prev_rotation = glam::Vec4::from(rotations[i - 1].1);
+        let (in_tangent, rotation, out_tangent) = &mut rotations[i];
+        if prev_rotation.dot(glam::Vec4::from(*rotation)) < 0.0_f32 {
+            for q in [in_tangent, rotation, out_tangent] {
+                *q = q.map(|x| -x);
+            }
+        }
+    }
+}
+
+/// Splits output of a sampler into (in-tangent, value, out-tangent) triples. Tangents are zero if interpolation is
+/// not cubic spline. Returns an error if the number of outputs does not match the number of inputs (one output per
+/// input or three for cubic spline) instead of silently dropping keys (invalid glTF).
+fn split_sampler_outputs<T: Copy + Default>(
+    outputs: Vec<T>,
+    num_inputs: usize,
+    interpolation: Interpolation,
+    n: &gltf::Node,
+    anim: &gltf::Animation,
+) -> std::io::Result<Vec<(T, T, T)>> {
+    let (interpolation_name, outputs_per_input) = match interpolation {
+        Interpolation::Step => ("step", 1),
+        Interpolation::Linear => ("linear", 1),
+        Interpolation::CubicSpline => ("cubic spline", 3),
+    };
+    if outputs.len() != num_inputs * outputs_per_input {
+        return Err(new_custom_error(format!(
+            "{} sampler of animation #{} '{}' on node #{} '{}' has {} outputs for {} inputs (expected {})",
+            interpolation_name,
+            anim.index(),
+            anim.name().unwrap_or_default(),
+            n.index(),
+            n.name().unwrap_or_default(),
+            outputs.len(),
+            num_inputs,
+            num_inputs * outputs_per_input
+        )));
+    }
+    Ok(if interpolation == Interpolation::CubicSpline {
+        outputs
+            .chunks_exact(3)
+            .map(|s| (s[0], s[1], s[2]))
+            .collect()
+    } else {
+        outputs
+            .into_iter()
+            .map(|v| (T::default(), v, T::default()))
+            .collect()
     })
 }
 
+/// Returns times used for baking a sampler with the given input times: every 1/`bake_fps` s starting from the first
+/// input. The last input is always included.
+fn get_bake_times(times: &[f32], bake_fps: f32) -> std::io::Result<Vec<f32>> {
+    if !(bake_fps.is_finite() && bake_fps > 0.0_f32) {
+        return Err(new_custom_error(format!(
+            "invalid bake frame rate {}",
+            bake_fps
+        )));
+    }
+    let (Some(&first), Some(&last)) = (times.first(), times.last()) else {
+        return Ok(Vec::new());
+    };
+    let num_samples = ((last - first) * bake_fps).ceil().max(0.0_f32) as usize;
+    // checked before allocating because the frame rate comes from the command line and times from the file
+    if num_samples >= rfa::MAX_BONE_KEYS {
+        return Err(new_custom_error(format!(
+            "baking {} s with {} FPS gives {} keys but at most {} are supported",
+            last - first,
+            bake_fps,
+            num_samples.saturating_add(1),
+            rfa::MAX_BONE_KEYS
+        )));
+    }
+    let mut result: Vec<f32> = (0..num_samples)
+        .map(|i| first + i as f32 / bake_fps)
+        .collect();
+    result.push(last);
+    Ok(result)
+}
+
+/// Evaluates glTF sampler at the given time. `values` contains (in-tangent, value, out-tangent) for every input
+/// time (tangents are only used by cubic splines). `lerp` interpolates linearly between two values. Values before
+/// the first and after the last input are clamped.
+fn evaluate_sampler<const N: usize>(
+    times: &[f32],
+    values: &[([f32; N], [f32; N], [f32; N])],
+    interpolation: Interpolation,
+    time: f32,
+    lerp: impl Fn([f32; N], [f32; N], f32) -> [f32; N],
+) -> [f32; N] {
+    let next = times.partition_point(|&t| t <= time);
+    let (Some(prev), Some(&(in_tangent, next_value, _))) = (next.checked_sub(1), values.get(next))
+    else {
+        let clamped = if next == 0 {
+            values.first()
+        } else {
+            values.last()
+        };
+        return clamped.map_or([0.0_f32; N], |v| v.1);
+    };
+    let (_, prev_value, out_tangent) = values[prev];
+    let duration = times[next] - times[prev];
+    let t = (time - times[prev]) / duration;
+    match interpolation {
+        Interpolation::Step => prev_value,
+        Interpolation::Linear => lerp(prev_value, next_value, t),
+        Interpolation::CubicSpline => {
+            let (t2, t3) = (t * t, t * t * t);
+            let h00 = 2.0_f32 * t3 - 3.0_f32 * t2 + 1.0_f32;
+            let h10 = t3 - 2.0_f32 * t2 + t;
+            let h01 = -2.0_f32 * t3 + 3.0_f32 * t2;
+            let h11 = t3 - t2;
+            std::array::from_fn(|i| {
+                h00 * prev_value[i]
+                    + h10 * duration * out_tangent[i]
+                    + h01 * next_value[i]
+                    + h11 * duration * in_tangent[i]
+            })
+        }
+    }
+}
+
 fn convert_rotation_keys(
     n: &gltf::Node,
     anim: &gltf::Animation,
+    config: &ConversionConfig,
     ctx: &Context,
-) -> Vec<rfa::RotationKey> {
-    get_node_anim_data(n, anim, ctx)
+) -> std::io::Result<Vec<rfa::RotationKey>> {
+    let keys = get_node_anim_data(n, anim, Property::Rotation, ctx)
+        .into_iter()
         .filter_map(|(inputs, outputs, interpolation)| match outputs {
             ReadOutputs::Rotations(rotations) => Some((inputs, rotations, interpolation)),
             _ => None,
         })
-        .map(|(inputs, rotations, interpolation)| {
-            let rotations_quads = rotations
+        .map(|(inputs, rotations, interpolation)| -> std::io::Result<_> {
+            let times = inputs.collect::<Vec<_>>();
+            let rf_rotations = rotations
                 .into_f32()
-                .map(gltf_to_rf_quat)
-                .map(make_short_quat);
-            let chunked_rotations = if interpolation == Interpolation::CubicSpline {
-                rotations_quads
-                    .collect::<Vec<_>>()
-                    .chunks(3)
-                    .map(|s| (s[0], s[1], s[2]))
-                    .collect::<Vec<_>>()
-            } else {
-                rotations_quads.map(|r| (r, r, r)).collect::<Vec<_>>()
-            };
-            inputs
-                .map(gltf_time_to_rfa_time)
-                .zip(chunked_rotations)
-                .map(|(time, (_, rotation, _))| rfa::RotationKey {
-                    time,
-                    rotation,
-                    ease_in: 0,
-                    ease_out: 0,
+                .map(|q| gltf_to_rf_quat(q, config.coordinate_convention));
+            let is_cubic_spline = interpolation == Interpolation::CubicSpline;
+            let mut chunked_rotations =
+                split_sampler_outputs(rf_rotations.collect(), times.len(), interpolation, n, anim)?;
+            ensure_quat_continuity(&mut chunked_rotations);
+            let key_rotations = chunked_rotations.iter().map(|r| r.1).collect::<Vec<_>>();
+            check_for_denormalized_quats(&key_rotations, n, anim);
+            if let Some(bake_fps) = config.bake_fps {
+                let slerp = |a: [f32; 4], b: [f32; 4], t: f32| {
+                    let q = glam::Quat::from_array(a).normalize();
+                    q.slerp(glam::Quat::from_array(b).normalize(), t).to_array()
+                };
+                let keys = get_bake_times(&times, bake_fps)?
+                    .into_iter()
+                    .map(|time| rfa::RotationKey {
+                        time: RfaTime::from_seconds(time, config.fps),
+                        // cubic spline result is normalized here, invalid keys are reported by
+                        // check_for_denormalized_quats
+                        rotation: ShortQuat::from_quat(glam::Quat::from_array(evaluate_sampler(
+                            &times,
+                            &chunked_rotations,
+                            interpolation,
+                            time,
+                            slerp,
+                        )))
+                        .unwrap_or(ShortQuat::IDENTITY),
+                        ease_in: 0,
+                        ease_out: 0,
+                    })
+                    .collect();
+                return Ok(keys);
+            }
+            let keys = times
+                .iter()
+                .zip(&chunked_rotations)
+                .enumerate()
+                .map(|(i, (&time, &(in_tangent, rotation, out_tangent)))| {
+                    let (ease_in, ease_out) = if is_cubic_spline {
+                        let ease_in = i.checked_sub(1).map_or(0, |prev| {
+                            let prev_rotation = chunked_rotations[prev].1;
+                            estimate_ease(in_tangent, rotation, prev_rotation, time - times[prev])
+                        });
+                        let ease_out = match (times.get(i + 1), chunked_rotations.get(i + 1)) {
+                            (Some(&next_time), Some(&(_, next_rotation, _))) => estimate_ease(
+                                out_tangent,
+                                rotation,
+                                next_rotation,
+                                next_time - time,
+                            ),
+                            _ => 0,
+                        };
+                        (ease_in, ease_out)
+                    } else {
+                        (0, 0)
+                    };
+                    rfa::RotationKey {
+                        time: RfaTime::from_seconds(time, config.fps),
+                        // invalid keys are reported by check_for_denormalized_quats
+                        rotation: ShortQuat::from_quat(glam::Quat::from_array(rotation))
+                            .unwrap_or(ShortQuat::IDENTITY),
+                        ease_in,
+                        ease_out,
+                    }
                 })
-                .collect::<Vec<_>>()
+                .collect::<Vec<_>>();
+            Ok(if interpolation == Interpolation::Step {
+                insert_step_hold_keys(keys, |k| k.time, |k, time| k.time = time)
+            } else {
+                keys
+            })
         })
         .next()
-        .unwrap_or_default()
+        .transpose()?
+        .unwrap_or_default();
+    check_for_key_time_collisions(keys.iter().map(|k| k.time), "rotation", n, anim);
+    Ok(sort_and_dedup_keys(keys, |k| k.time))
+}
+
+/// Sorts keys by time and removes keys with duplicated time keeping the last one, so RFA keys are strictly
+/// ascending.
+fn sort_and_dedup_keys<K>(mut keys: Vec<K>, get_time: impl Fn(&K) -> RfaTime) -> Vec<K> {
+    // stable sort keeps order of keys with the same time
+    keys.sort_by_key(&get_time);
+    let mut result: Vec<K> = Vec::with_capacity(keys.len());
+    for key in keys {
+        match result.last_mut() {
+            Some(last) if get_time(last) == get_time(&key) => *last = key,
+            _ => result.push(key),
+        }
+    }
+    result
+}
+
+/// RFA keys are always interpolated so glTF STEP interpolation is emulated by inserting a copy of every key
+/// one tick before the next key. The value is held almost until the next key and then it snaps in one tick.
+fn insert_step_hold_keys<K: Clone>(
+    keys: Vec<K>,
+    get_time: impl Fn(&K) -> RfaTime,
+    set_time: impl Fn(&mut K, RfaTime),
+) -> Vec<K> {
+    let mut result = Vec::with_capacity(keys.len() * 2);
+    for (i, key) in keys.iter().enumerate() {
+        result.push(key.clone());
+        if let Some(next_key) = keys.get(i + 1) {
+            let hold_time = get_time(next_key) - RfaTime(1);
+            if hold_time > get_time(key) {
+                let mut hold_key = key.clone();
+                set_time(&mut hold_key, hold_time);
+                result.push(hold_key);
+            }
+        }
+    }
+    result
+}
+
+/// Removes keys which can be reconstructed by interpolating between the remaining keys. A key is removed only if
+/// all keys skipped since the last kept key are redundant relative to the last kept key and the next key, so
+/// the error does not accumulate. The first and the last keys are always kept.
+fn reduce_keys<K: Clone>(keys: Vec<K>, is_redundant: impl Fn(&K, &K, &K) -> bool) -> Vec<K> {
+    if keys.len() <= 2 {
+        return keys;
+    }
+    let mut result = Vec::with_capacity(keys.len());
+    result.push(keys[0].clone());
+    let mut last_kept = 0;
+    for i in 1..keys.len() - 1 {
+        let next = &keys[i + 1];
+        let can_remove = keys[last_kept + 1..=i]
+            .iter()
+            .all(|key| is_redundant(&keys[last_kept], key, next));
+        if !can_remove {
+            result.push(keys[i].clone());
+            last_kept = i;
+        }
+    }
+    result.push(keys[keys.len() - 1].clone());
+    result
+}
+
+fn get_interpolation_factor(prev_time: RfaTime, time: RfaTime, next_time: RfaTime) -> f32 {
+    (time - prev_time).ticks() as f32 / (next_time - prev_time).ticks() as f32
+}
+
+/// Keys with easing are never removed because RF easing cannot be merged into neighbouring segments.
+fn is_rotation_key_redundant(
+    prev: &rfa::RotationKey,
+    key: &rfa::RotationKey,
+    next: &rfa::RotationKey,
+    tolerance_deg: f32,
+) -> bool {
+    if [prev.ease_out, key.ease_in, key.ease_out, next.ease_in] != [0; 4] {
+        return false;
+    }
+    let t = get_interpolation_factor(prev.time, key.time, next.time);
+    let interpolated = prev.rotation.to_quat().slerp(next.rotation.to_quat(), t);
+    interpolated
+        .angle_between(key.rotation.to_quat())
+        .to_degrees()
+        <= tolerance_deg
+}
+
+fn is_linear_translation_key(key: &rfa::TranslationKey) -> bool {
+    key.in_tangent == key.translation && key.out_tangent == key.translation
+}
+
+/// Keys of bezier segments are never removed, only keys between linear segments.
+fn is_translation_key_redundant(
+    prev: &rfa::TranslationKey,
+    key: &rfa::TranslationKey,
+    next: &rfa::TranslationKey,
+    tolerance: f32,
+) -> bool {
+    if ![prev, key, next].into_iter().all(is_linear_translation_key) {
+        return false;
+    }
+    let t = get_interpolation_factor(prev.time, key.time, next.time);
+    let interpolated = glam::Vec3::from(prev.translation).lerp(next.translation.into(), t);
+    interpolated.distance(key.translation.into()) <= tolerance
+}
+
+/// Replaces translation keys of non-root bones with a single key if the translation does not move further than
+/// the tolerance from the first key. Such bones only rotate and inherit motion from their parents.
+fn collapse_static_translations(
+    bones: &mut [rfa::Bone],
+    root_index: Option<usize>,
+    anim: &gltf::Animation,
+    config: &ConversionConfig,
+    ctx: &Context,
+) {
+    let Some(tolerance) = config.collapse_translation_tolerance else {
+        return;
+    };
+    let mut num_collapsed = 0;
+    for (bone_index, bone) in bones.iter_mut().enumerate() {
+        if Some(bone_index) == root_index || bone.translation_keys.len() <= 1 {
+            continue;
+        }
+        let first = glam::Vec3::from(bone.translation_keys[0].translation);
+        let is_static = bone
+            .translation_keys
+            .iter()
+            .all(|k| (glam::Vec3::from(k.translation) - first).length() <= tolerance);
+        if is_static {
+            bone.translation_keys.truncate(1);
+            let key = &mut bone.translation_keys[0];
+            key.in_tangent = key.translation;
+            key.out_tangent = key.translation;
+            num_collapsed += 1;
+        }
+    }
+    if ctx.args.verbose >= 1 && num_collapsed > 0 {
+        println!(
+            "Collapsed translation keys of {} bone(s) in animation {}",
+            num_collapsed,
+            anim.name().unwrap_or_default()
+        );
+    }
+}
+
+/// Sets the same easing on all rotation keys and removes velocity of translation curves at eased sides of keys
+/// by moving bezier control points onto the keys. `EasePreset::Linear` does the same on both sides of keys with no
+/// easing (linear segments) and `EasePreset::Source` keeps the keys unchanged.
+fn apply_ease_preset(bones: &mut [rfa::Bone], preset: EasePreset) {
+    let (ease_in, ease_out) = match preset {
+        EasePreset::Source => return,
+        EasePreset::Linear => (false, false),
+        EasePreset::EaseIn => (true, false),
+        EasePreset::EaseOut => (false, true),
+        EasePreset::EaseInOut => (true, true),
+    };
+    let max_ease = MAX_EASE as i8;
+    for bone in bones {
+        for key in &mut bone.rotation_keys {
+            key.ease_in = if ease_in { max_ease } else { 0 };
+            key.ease_out = if ease_out { max_ease } else { 0 };
+        }
+        for key in &mut bone.translation_keys {
+            if ease_in || preset == EasePreset::Linear {
+                key.in_tangent = key.translation;
+            }
+            if ease_out || preset == EasePreset::Linear {
+                key.out_tangent = key.translation;
+            }
+        }
+    }
+}
+
+/// Applies keyframe reduction to all bones if it is enabled in the config
+fn reduce_bone_keys(
+    bones: &mut [rfa::Bone],
+    anim: &gltf::Animation,
+    config: &ConversionConfig,
+    ctx: &Context,
+) {
+    let rotation_tolerance = config.reduce_rotation_tolerance;
+    let translation_tolerance = config.reduce_translation_tolerance;
+    if rotation_tolerance.is_none() && translation_tolerance.is_none() {
+        return;
+    }
+    let count_keys = |bones: &[rfa::Bone]| {
+        bones
+            .iter()
+            .map(|b| b.rotation_keys.len() + b.translation_keys.len())
+            .sum::<usize>()
+    };
+    let num_keys_before = count_keys(bones);
+    for bone in bones.iter_mut() {
+        if let Some(tolerance) = rotation_tolerance {
+            bone.rotation_keys = reduce_keys(std::mem::take(&mut bone.rotation_keys), |p, k, n| {
+                is_rotation_key_redundant(p, k, n, tolerance)
+            });
+        }
+        if let Some(tolerance) = translation_tolerance {
+            bone.translation_keys =
+                reduce_keys(std::mem::take(&mut bone.translation_keys), |p, k, n| {
+                    is_translation_key_redundant(p, k, n, tolerance)
+                });
+        }
+    }
+    if ctx.args.verbose >= 1 {
+        println!(
+            "Keyframe reduction of animation {}: {} -> {} keys",
+            anim.name().unwrap_or_default(),
+            num_keys_before,
+            count_keys(bones)
+        );
+    }
+}
+
+/// Rounds every component to the nearest multiple of `2^(16 - bits)` and renormalizes the result. Components of
+/// unit quaternions are in range of `RFA_QUAT_SCALE` (14 bits and sign) so the top 2 of the 16 bits are headroom and
+/// `bits` keeps about `bits - 2` bits of precision.
+fn quantize_short_quat(rotation: ShortQuat, bits: u32) -> ShortQuat {
+    let step = f32::from(1_u16 << (16 - bits));
+    let quantized = rotation.0.map(|x| (f32::from(x) / step).round() * step);
+    // rounding changes length of the quaternion, a quaternion rounded to zero is kept unchanged
+    ShortQuat::from_quat(glam::Quat::from_array(quantized)).unwrap_or(rotation)
+}
+
+fn quantize_vec(v: [f32; 3], step: f32) -> [f32; 3] {
+    v.map(|x| (x / step).round() * step)
+}
+
+/// Reduces precision of keys if it is enabled in the config. Keys that end up equal to both neighbours are
+/// removed and the achieved size reduction is reported.
+fn quantize_bone_keys(
+    bones: &mut [rfa::Bone],
+    anim: &gltf::Animation,
+    config: &ConversionConfig,
+    ctx: &Context,
+) -> std::io::Result<()> {
+    if let Some(bits) = config.rotation_bits.filter(|bits| !(2..=16).contains(bits)) {
+        return Err(new_custom_error(format!(
+            "invalid number of rotation bits {}: it must be in the range 2-16",
+            bits
+        )));
+    }
+    // all bits are kept for 16
+    let rotation_bits = config.rotation_bits.filter(|&bits| bits < 16);
+    let translation_step = config.quantize_translation;
+    if rotation_bits.is_none() && translation_step.is_none() {
+        return Ok(());
+    }
+    if let Some(step) = translation_step.filter(|&step| step <= 0.0 || !step.is_finite()) {
+        return Err(new_custom_error(format!(
+            "invalid translation quantization step {}: it must be a positive number",
+            step
+        )));
+    }
+    let size_before: usize = bones.iter().map(rfa::Bone::size).sum();
+    for bone in bones.iter_mut() {
+        if let Some(bits) = rotation_bits {
+            for key in &mut bone.rotation_keys {
+                key.rotation = quantize_short_quat(key.rotation, bits);
+            }
+            bone.rotation_keys = reduce_keys(std::mem::take(&mut bone.rotation_keys), |p, k, n| {
+                p.rotation == k.rotation && k.rotation == n.rotation
+            });
+        }
+        if let Some(step) = translation_step {
+            for key in &mut bone.translation_keys {
+                key.translation = quantize_vec(key.translation, step);
+                key.in_tangent = quantize_vec(key.in_tangent, step);
+                key.out_tangent = quantize_vec(key.out_tangent, step);
+            }
+            bone.translation_keys =
+                reduce_keys(std::mem::take(&mut bone.translation_keys), |p, k, n| {
+                    [p, k, n].into_iter().all(is_linear_translation_key)
+                        && p.translation == k.translation
+                        && k.translation == n.translation
+                });
+        }
+    }
+    let size_after: usize = bones.iter().map(rfa::Bone::size).sum();
+    if ctx.args.verbose >= 1 {
+        println!(
+            "Quantization of animation {}: bone data {} -> {} bytes ({:.1}% smaller)",
+            anim.name().unwrap_or_default(),
+            size_before,
+            size_after,
+            100.0 * (size_before - size_after) as f32 / size_before.max(1) as f32
+        );
+    }
+    Ok(())
+}
+
+/// Converts a glTF cubic spline tangent into an RF bezier control point.
+///
+/// glTF stores Hermite tangents as derivatives per second that must be scaled by the segment
+/// duration. RF keys store absolute bezier control points instead. A Hermite segment is exactly
+/// representable in the Bezier basis with control points placed at one third of the scaled
+/// tangent, so this is lossless as long as RF evaluates segments uniformly in time (which is
+/// assumed, not verified).
+fn hermite_tangent_to_control_point(
+    value: [f32; 3],
+    tangent: [f32; 3],
+    segment_duration: f32,
+) -> [f32; 3] {
+    (glam::Vec3::from(value) + glam::Vec3::from(tangent) * (segment_duration / 3.0_f32)).into()
 }
 
 fn convert_translation_keys(
     n: &gltf::Node,
     anim: &gltf::Animation,
+    config: &ConversionConfig,
     ctx: &Context,
-) -> Vec<rfa::TranslationKey> {
-    get_node_anim_data(n, anim, ctx)
+) -> std::io::Result<Vec<rfa::TranslationKey>> {
+    let keys = get_node_anim_data(n, anim, Property::Translation, ctx)
+        .into_iter()
         .filter_map(|(inputs, outputs, interpolation)| match outputs {
             ReadOutputs::Translations(translations) => Some((inputs, translations, interpolation)),
             _ => None,
         })
-        .map(|(inputs, translations, interpolation)| {
-            let rf_translations = translations.map(gltf_to_rf_vec);
-            let chunked_translations = if interpolation == Interpolation::CubicSpline {
-                rf_translations
-                    .collect::<Vec<_>>()
-                    .chunks(3)
-                    .map(|s| (s[0], s[1], s[2]))
-                    .collect::<Vec<_>>()
-            } else {
-                rf_translations.map(|t| (t, t, t)).collect::<Vec<_>>()
-            };
-            inputs
-                .map(gltf_time_to_rfa_time)
-                .zip(chunked_translations)
-                .map(|(time, (_, translation, _))|
-                    // ignore cubic spline tangents for now - RF uses bezier curve and tangents are different
-                    rfa::TranslationKey {
-                        time,
-                        in_tangent: translation,
-                        translation,
-                        out_tangent: translation,
+        .map(
+            |(inputs, translations, interpolation)| -> std::io::Result<_> {
+                let times = inputs.collect::<Vec<_>>();
+                let rf_translations = translations
+                    .map(|v| gltf_to_rf_pos(v, config.coordinate_convention, config.scale));
+                // zero tangents of not cubic spline samplers make control points equal to the key value
+                // (linear segments)
+                let chunked_translations = split_sampler_outputs(
+                    rf_translations.collect(),
+                    times.len(),
+                    interpolation,
+                    n,
+                    anim,
+                )?;
+                if let Some(bake_fps) = config.bake_fps {
+                    let lerp = |a: [f32; 3], b: [f32; 3], t: f32| {
+                        glam::Vec3::from(a).lerp(b.into(), t).to_array()
+                    };
+                    let keys = get_bake_times(&times, bake_fps)?
+                        .into_iter()
+                        .map(|time| {
+                            let translation = evaluate_sampler(
+                                &times,
+                                &chunked_translations,
+                                interpolation,
+                                time,
+                                lerp,
+                            );
+                            rfa::TranslationKey {
+                                time: RfaTime::from_seconds(time, config.fps),
+                                in_tangent: translation,
+                                translation,
+                                out_tangent: translation,
+                            }
+                        })
+                        .collect();
+                    return Ok(keys);
+                }
+                let keys = times
+                    .iter()
+                    .zip(chunked_translations)
+                    .enumerate()
+                    .map(|(i, (&time, (in_tangent, translation, out_tangent)))| {
+                        let prev_duration = if i > 0 { time - times[i - 1] } else { 0.0_f32 };
+                        let next_duration = times.get(i + 1).map_or(0.0_f32, |t| t - time);
+                        rfa::TranslationKey {
+                            time: RfaTime::from_seconds(time, config.fps),
+                            in_tangent: hermite_tangent_to_control_point(
+                                translation,
+                                in_tangent,
+                                -prev_duration,
+                            ),
+                            translation,
+                            out_tangent: hermite_tangent_to_control_point(
+                                translation,
+                                out_tangent,
+                                next_duration,
+                            ),
+                        }
                     })
-                .collect::<Vec<_>>()
-        })
+                    .collect::<Vec<_>>();
+                Ok(if interpolation == Interpolation::Step {
+                    insert_step_hold_keys(keys, |k| k.time, |k, time| k.time = time)
+                } else {
+                    keys
+                })
+            },
+        )
         .next()
-        .unwrap_or_default()
+        .transpose()?
+        .unwrap_or_default();
+    check_for_key_time_collisions(keys.iter().map(|k| k.time), "translation", n, anim);
+    Ok(sort_and_dedup_keys(keys, |k| k.time))
 }
 
-fn determine_anim_time_range(bones: &[rfa::Bone]) -> (i32, i32) {
-    bones
+fn determine_anim_time_range(bones: &[rfa::Bone]) -> (RfaTime, RfaTime) {
+    let (start_time, end_time) = bones
         .iter()
         .flat_map(|b| {
             b.rotation_keys
@@ -154,18 +970,149 @@ fn determine_anim_time_range(bones: &[rfa::Bone]) -> (i32, i32) {
                 .map(|k| k.time)
                 .chain(b.translation_keys.iter().map(|k| k.time))
         })
-        .fold((0_i32, 0_i32), |(min, max), time| {
-            (min.min(time), max.max(time))
-        })
+        .fold(
+            (RfaTime(i32::MAX), RfaTime(i32::MIN)),
+            |(min, max), time| (min.min(time), max.max(time)),
+        );
+    if start_time > end_time {
+        // No keys at all
+        (RfaTime::ZERO, RfaTime::ZERO)
+    } else {
+        (start_time, end_time)
+    }
+}
+
+/// Returns the trim window in RFA ticks
+fn get_trim_range(config: &ConversionConfig) -> std::io::Result<Option<(RfaTime, RfaTime)>> {
+    let Some((start, end)) = config.trim else {
+        return Ok(None);
+    };
+    if !(start.is_finite() && end.is_finite() && start >= 0.0_f32 && start < end) {
+        return Err(new_custom_error(format!(
+            "invalid trim range {}-{} s: start must be non-negative and less than end",
+            start, end
+        )));
+    }
+    Ok(Some((
+        RfaTime::from_seconds(start, config.fps),
+        RfaTime::from_seconds(end, config.fps),
+    )))
+}
+
+/// Inserts a rotation key with interpolated value at the given time unless there is a key at that time already.
+/// Outside of the key range the nearest key is copied.
+fn insert_rotation_key(keys: &mut Vec<rfa::RotationKey>, time: RfaTime) {
+    let next = keys.partition_point(|k| k.time < time);
+    if keys.get(next).is_some_and(|k| k.time == time) {
+        return;
+    }
+    let key = match (next.checked_sub(1).map(|i| &keys[i]), keys.get(next)) {
+        (Some(prev), Some(next_key)) => {
+            let t = get_interpolation_factor(prev.time, time, next_key.time);
+            let rotation = prev
+                .rotation
+                .to_quat()
+                .slerp(next_key.rotation.to_quat(), t);
+            rfa::RotationKey {
+                time,
+                rotation: pack_computed_rotation(rotation),
+                ease_in: 0,
+                ease_out: 0,
+            }
+        }
+        (Some(key), None) | (None, Some(key)) => rfa::RotationKey {
+            time,
+            ease_in: 0,
+            ease_out: 0,
+            ..key.clone()
+        },
+        (None, None) => return,
+    };
+    keys.insert(next, key);
+}
+
+/// Inserts a translation key at the given time unless there is a key at that time already. Linear segments are
+/// interpolated linearly and bezier segments are split (de Casteljau) so the curve is unchanged. Outside of the key
+/// range the nearest key value is copied.
+fn insert_translation_key(keys: &mut Vec<rfa::TranslationKey>, time: RfaTime) {
+    let next = keys.partition_point(|k| k.time < time);
+    if keys.get(next).is_some_and(|k| k.time == time) {
+        return;
+    }
+    let linear_key = |translation: glam::Vec3| rfa::TranslationKey {
+        time,
+        translation: translation.into(),
+        in_tangent: translation.into(),
+        out_tangent: translation.into(),
+    };
+    let key = match (next.checked_sub(1), keys.get(next)) {
+        (Some(prev), Some(next_key)) => {
+            let prev_key = &keys[prev];
+            let u = get_interpolation_factor(prev_key.time, time, next_key.time);
+            let [p0, c0, c1, p1] = [
+                prev_key.translation,
+                prev_key.out_tangent,
+                next_key.in_tangent,
+                next_key.translation,
+            ]
+            .map(glam::Vec3::from);
+            if c0 == p0 && c1 == p1 {
+                linear_key(p0.lerp(p1, u))
+            } else {
+                let (a, b, c) = (p0.lerp(c0, u), c0.lerp(c1, u), c1.lerp(p1, u));
+                let (d, e) = (a.lerp(b, u), b.lerp(c, u));
+                keys[prev].out_tangent = a.into();
+                keys[next].in_tangent = c.into();
+                rfa::TranslationKey {
+                    time,
+                    translation: d.lerp(e, u).into(),
+                    in_tangent: d.into(),
+                    out_tangent: e.into(),
+                }
+            }
+        }
+        (Some(prev), None) => linear_key(keys[prev].translation.into()),
+        (None, Some(next_key)) => linear_key(next_key.translation.into()),
+        (None, None) => return,
+    };
+    keys.insert(next, key);
 }
 
+/// Cuts all channels to the `start_time`-`end_time` window and moves keys so the window starts at 0. Keys are
+/// inserted at both ends of the window so the trimmed animation begins and ends exactly in the source poses.
+fn trim_bone_keys(bones: &mut [rfa::Bone], start_time: RfaTime, end_time: RfaTime) {
+    let window = start_time..=end_time;
+    for bone in bones {
+        insert_rotation_key(&mut bone.rotation_keys, start_time);
+        insert_rotation_key(&mut bone.rotation_keys, end_time);
+        bone.rotation_keys.retain(|k| window.contains(&k.time));
+        bone.rotation_keys
+            .iter_mut()
+            .for_each(|k| k.time -= start_time);
+        insert_translation_key(&mut bone.translation_keys, start_time);
+        insert_translation_key(&mut bone.translation_keys, end_time);
+        bone.translation_keys.retain(|k| window.contains(&k.time));
+        bone.translation_keys
+            .iter_mut()
+            .for_each(|k| k.time -= start_time);
+    }
+}
+
+/// Returns time of an event in RFA ticks relative to the start of the exported (possibly trimmed) animation
+fn get_event_time(event: &AnimEvent, config: &ConversionConfig) -> RfaTime {
+    let trim_start = config.trim.map_or(0.0_f32, |(start, _)| start);
+    RfaTime::from_seconds(event.time - trim_start, config.fps)
+}
+
+/// RFA bones have only rotation and translation keys so scale animation cannot be converted.
+/// Warn the user about it if scale actually changes.
 fn check_for_scale_channels(n: &gltf::Node, anim: &gltf::Animation, ctx: &Context) {
     for channel in get_node_anim_channels(n, anim) {
         let reader = channel.reader(|buffer| ctx.get_buffer_data(buffer));
         if let Some(ReadOutputs::Scales(scales)) = reader.read_outputs() {
             if scales.flatten().any(|s| (s - 1.0_f32).abs() > 0.01_f32) {
-                eprintln!(
-                    "Warning! Animation #{} '{}' is using unsupported scale channel on node #{} '{}'!",
+                warn!(
+                    "Animation #{} '{}' is using unsupported scale channel on node #{} '{}' (RFA has no scale keys so it is ignored)!",
                     anim.index(),
                     anim.name().unwrap_or_default(),
                     n.index(),
@@ -176,6 +1123,66 @@ fn check_for_scale_channels(n: &gltf::Node, anim: &gltf::Animation, ctx: &Contex
     }
 }
 
+fn is_morph_channel(channel: &gltf::animation::Channel) -> bool {
+    channel.target().property() == gltf::animation::Property::MorphTargetWeights
+}
+
+/// Morph target (vertex) animation is not converted. RFA has morph vertex data but it is bound to
+/// the morph vertex map of the V3C mesh which is not generated. Warn the user that it is skipped.
+fn check_for_morph_channels(anim: &gltf::Animation) {
+    let num_morph_channels = anim.channels().filter(is_morph_channel).count();
+    if num_morph_channels > 0 {
+        warn!(
+            "Animation #{} '{}' has {} morph target weights channel(s). Morph animation is not supported and has been skipped!",
+            anim.index(),
+            anim.name().unwrap_or_default(),
+            num_morph_channels,
+        );
+    }
+}
+
+/// RFA bones have to match bones of the character mesh, so channels of nodes which are not skin joints
+/// (e.g. props or the armature node) cannot be exported.
+fn check_for_non_joint_channels(anim: &gltf::Animation, skin_joints: &[gltf::Node]) {
+    let ignored_nodes: BTreeMap<_, _> = anim
+        .channels()
+        .filter(|c| !is_morph_channel(c))
+        .map(|c| c.target().node())
+        .filter(|n| !skin_joints.iter().any(|j| j.index() == n.index()))
+        .map(|n| (n.index(), n.name().unwrap_or_default().to_owned()))
+        .collect();
+    if ignored_nodes.is_empty() {
+        return;
+    }
+    let node_list = ignored_nodes
+        .iter()
+        .map(|(index, name)| format!("#{} '{}'", index, name))
+        .collect::<Vec<_>>()
+        .join(", ");
+    warn!(
+        "Animation #{} '{}' animates node(s) which are not joints of the skin: {}. Their animation has been skipped!",
+        anim.index(),
+        anim.name().unwrap_or_default(),
+        node_list,
+    );
+}
+
+/// Returns another skin of the document animated by an animation which does not target any joint of the converted
+/// skin. Channels are matched to bones by node index, so such animation (e.g. made for another character in the same
+/// file) would otherwise produce an RFA without any keys.
+fn find_other_animated_skin<'a>(
+    anim: &gltf::Animation,
+    doc: &'a gltf::Document,
+    skin_joints: &[gltf::Node],
+) -> Option<gltf::Skin<'a>> {
+    let targets: HashSet<_> = anim.channels().map(|c| c.target().node().index()).collect();
+    if skin_joints.iter().any(|j| targets.contains(&j.index())) {
+        return None;
+    }
+    doc.skins()
+        .find(|s| s.joints().any(|j| targets.contains(&j.index())))
+}
+
 fn is_death_anim(anim: &gltf::Animation) -> bool {
     anim.name().unwrap_or_default().contains("death")
 }
@@ -206,64 +1213,83 @@ fn determine_anim_weight(
     }
 }
 
-fn convert_bone_anim(node: &gltf::Node, anim: &gltf::Animation, ctx: &Context) -> rfa::Bone {
-    let rotation_keys = convert_rotation_keys(node, anim, ctx);
-    let translation_keys = convert_translation_keys(node, anim, ctx);
+fn convert_bone_anim(
+    node: &gltf::Node,
+    anim: &gltf::Animation,
+    config: &ConversionConfig,
+    ctx: &Context,
+) -> std::io::Result<rfa::Bone> {
+    let rotation_keys = convert_rotation_keys(node, anim, config, ctx)?;
+    let translation_keys = convert_translation_keys(node, anim, config, ctx)?;
     check_for_scale_channels(node, anim, ctx);
     let extras = get_node_extras::<JointExtras>(node);
+    let anim_extras = get_anim_extras(anim);
     let weight = extras
         .get_anim_weight(anim.name().unwrap_or_default())
-        .or(ctx.args.anim_weight)
+        .or_else(|| anim_extras.get_joint_weight(node.name().unwrap_or_default()))
+        .or_else(|| extras.get_default_weight())
+        .or(config.anim_weight)
         .unwrap_or_else(|| determine_anim_weight(&rotation_keys, &translation_keys));
-    rfa::Bone {
+    Ok(rfa::Bone {
         weight,
         rotation_keys,
         translation_keys,
-    }
+    })
 }
 
-fn get_default_ramp_in_time(anim: &gltf::Animation) -> i32 {
+fn get_default_ramp_in_time(anim: &gltf::Animation) -> RfaTime {
     if is_death_anim(anim) {
-        800
+        RfaTime(800)
     } else {
-        480
+        RfaTime(480)
     } // 0.1(6) s, 0.1 s
 }
 
-fn get_default_ramp_out_time(anim: &gltf::Animation) -> i32 {
+fn get_default_ramp_out_time(anim: &gltf::Animation) -> RfaTime {
     if is_death_anim(anim) {
-        0
+        RfaTime::ZERO
     } else {
-        480
+        RfaTime(480)
     } // 0.0 s, 0.1 s
 }
 
 fn determine_ramp_in_time(
     anim: &gltf::Animation,
+    anim_extras: &AnimExtras,
     root_joint_extras: &JointExtras,
-    duration: i32,
-    ctx: &Context,
-) -> i32 {
+    duration: RfaTime,
+    config: &ConversionConfig,
+) -> RfaTime {
     let anim_name = anim.name().unwrap_or_default();
-    root_joint_extras
-        .get_ramp_in_time(anim_name)
-        .or(ctx.args.ramp_in_time)
-        .map(gltf_time_to_rfa_time)
-        .unwrap_or_else(|| get_default_ramp_in_time(anim).min(duration / 2))
+    anim_extras
+        .ramp_in_time
+        .or_else(|| root_joint_extras.get_ramp_in_time(anim_name))
+        .or(config.ramp_in_time)
+        .map(|t| RfaTime::from_seconds(t, config.fps))
+        .map(|t| t.clamp(RfaTime::ZERO, duration))
+        .unwrap_or_else(|| get_default_ramp_in_time(anim).min(RfaTime(duration.ticks() / 2)))
 }
 
+/// Ramp out time is reduced if the ramp in time leaves less of the animation, so both ramps together never exceed
+/// the duration
 fn determine_ramp_out_time(
     anim: &gltf::Animation,
+    anim_extras: &AnimExtras,
     root_joint_extras: &JointExtras,
-    duration: i32,
-    ctx: &Context,
-) -> i32 {
+    duration: RfaTime,
+    ramp_in_time: RfaTime,
+    config: &ConversionConfig,
+) -> RfaTime {
     let anim_name = anim.name().unwrap_or_default();
-    root_joint_extras
-        .get_ramp_out_time(anim_name)
-        .or(ctx.args.ramp_out_time)
-        .map(gltf_time_to_rfa_time)
-        .unwrap_or_else(|| get_default_ramp_out_time(anim).min(duration / 2))
+    let ramp_out_time = anim_extras
+        .ramp_out_time
+        .or_else(|| root_joint_extras.get_ramp_out_time(anim_name))
+        .or(config.ramp_out_time)
+        .map(|t| RfaTime::from_seconds(t, config.fps))
+        .map(|t| t.clamp(RfaTime::ZERO, duration))
+        .unwrap_or_else(|| get_default_ramp_out_time(anim).min(RfaTime(duration.ticks() / 2)));
+    // ramp in time is clamped to the duration so this cannot underflow
+    ramp_out_time.min(duration - ramp_in_time)
 }
 
 fn is_root_joint(node: &gltf::Node) -> bool {
@@ -273,112 +1299,1394 @@ fn is_root_joint(node: &gltf::Node) -> bool {
         .ends_with("root")
 }
 
-fn find_root_joint<'a>(skin: &'a gltf::Skin) -> Option<gltf::Node<'a>> {
-    skin.joints().find(is_root_joint)
+/// Finds index of the exported joint carrying root motion. The joint selected by `--root-bone` option is used if
+/// specified. Otherwise the joint named root is used or the only bone without a parent. Multiple parentless bones
+/// are reported as an error because root motion would depend on joint order.
+fn find_root_joint_index(
+    joints: &[gltf::Node],
+    bone_map: &BoneMap,
+    config: &ConversionConfig,
+) -> std::io::Result<Option<usize>> {
+    if let Some(name) = &config.root_bone {
+        return joints
+            .iter()
+            .position(|n| n.name() == Some(name.as_str()))
+            .map(Some)
+            .ok_or_else(|| {
+                new_custom_error(format!("root bone {} not found in exported joints", name))
+            });
+    }
+    if let Some(index) = joints.iter().position(is_root_joint) {
+        return Ok(Some(index));
+    }
+    let parentless: Vec<_> = (0..joints.len())
+        .filter(|&i| bone_map.get_parent_bone_index(i).is_none())
+        .collect();
+    if parentless.len() > 1 {
+        let names: Vec<_> = parentless
+            .iter()
+            .map(|&i| get_bone_name(&joints[i], i))
+            .collect();
+        return Err(new_custom_error(format!(
+            "skeleton has multiple root bones ({}). Select the bone carrying root motion by --root-bone option",
+            names.join(", ")
+        )));
+    }
+    Ok(parentless.first().copied())
 }
 
-fn get_root_joint_extras(skin: &gltf::Skin) -> JointExtras {
-    find_root_joint(skin)
-        .map(|n| get_node_extras::<JointExtras>(&n))
+fn get_root_joint_extras(joints: &[gltf::Node], root_index: Option<usize>) -> JointExtras {
+    root_index
+        .map(|i| get_node_extras::<JointExtras>(&joints[i]))
         .unwrap_or_default()
 }
 
-fn make_rfa(anim: &gltf::Animation, skin: &gltf::Skin, ctx: &Context) -> rfa::File {
-    let mut bones = Vec::with_capacity(skin.joints().count());
-    for joint in skin.joints() {
-        bones.push(convert_bone_anim(&joint, anim, ctx));
-    }
-    let (start_time, end_time) = determine_anim_time_range(&bones);
-    let duration = end_time - start_time;
-    let root_joint_extras = get_root_joint_extras(skin);
-    let ramp_in_time = determine_ramp_in_time(anim, &root_joint_extras, duration, ctx);
-    let ramp_out_time = determine_ramp_out_time(anim, &root_joint_extras, duration, ctx);
-    if ctx.args.verbose >= 2 {
-        println!("Start time: {}", start_time);
-        println!("End time: {}", end_time);
-        println!("Ramp In time: {}", ramp_in_time);
-        println!("Ramp Out time: {}", ramp_out_time);
-    }
-    let header = rfa::FileHeader {
-        num_bones: bones.len() as i32,
-        start_time,
-        end_time,
-        ramp_in_time,
-        ramp_out_time,
-        total_rotation: [0.0_f32, 0.0_f32, 0.0_f32, 1.0_f32],
-        total_translation: [0.0_f32; 3],
-        ..rfa::FileHeader::default()
+fn make_rest_pose_keys(
+    node: &gltf::Node,
+    time: RfaTime,
+    config: &ConversionConfig,
+) -> (rfa::RotationKey, rfa::TranslationKey) {
+    let convention = config.coordinate_convention;
+    let (gltf_translation, gltf_rotation, _) = node.transform().decomposed();
+    let translation = gltf_to_rf_pos(gltf_translation, convention, config.scale);
+    let rotation_key = rfa::RotationKey {
+        time,
+        rotation: pack_computed_rotation(glam::Quat::from_array(gltf_to_rf_quat(
+            gltf_rotation,
+            convention,
+        ))),
+        ease_in: 0,
+        ease_out: 0,
     };
-    rfa::File { header, bones }
+    let translation_key = rfa::TranslationKey {
+        time,
+        translation,
+        in_tangent: translation,
+        out_tangent: translation,
+    };
+    (rotation_key, translation_key)
+}
+
+/// Warns if rotation and translation keys of a bone cover different time ranges, e.g. translation has only one key
+/// at the start while rotation is sampled in every frame. It usually means that some channels were not baked
+/// during export.
+fn check_key_time_alignment(bones: &[rfa::Bone], anim: &gltf::Animation, joints: &[gltf::Node]) {
+    for (joint, bone) in joints.iter().zip(bones) {
+        let (Some(first_rot), Some(last_rot), Some(first_pos), Some(last_pos)) = (
+            bone.rotation_keys.first(),
+            bone.rotation_keys.last(),
+            bone.translation_keys.first(),
+            bone.translation_keys.last(),
+        ) else {
+            continue;
+        };
+        if first_rot.time != first_pos.time || last_rot.time != last_pos.time {
+            warn!(
+                "Animation #{} '{}' has misaligned keys on node #{} '{}': {} rotation key(s) in {}-{} ticks, \
+                {} translation key(s) in {}-{} ticks",
+                anim.index(),
+                anim.name().unwrap_or_default(),
+                joint.index(),
+                joint.name().unwrap_or_default(),
+                bone.rotation_keys.len(),
+                first_rot.time,
+                last_rot.time,
+                bone.translation_keys.len(),
+                first_pos.time,
+                last_pos.time,
+            );
+        }
+    }
 }
 
-pub(crate) fn convert_animation_to_rfa(
+fn backfill_unanimated_bones(
+    bones: &mut [rfa::Bone],
     anim: &gltf::Animation,
-    index: usize,
-    skin: &gltf::Skin,
+    joints: &[gltf::Node],
+    start_time: RfaTime,
+    config: &ConversionConfig,
     ctx: &Context,
-) -> std::io::Result<()> {
-    let name = anim
-        .name()
-        .map_or_else(|| format!("anim_{}", index), str::to_owned);
-    let file_name = ctx.output_dir.join(format!("{}.rfa", name));
-    if ctx.args.verbose >= 1 {
-        println!("Exporting animation: {} -> {}", name, file_name.display());
+) {
+    for (joint, bone) in joints.iter().zip(bones) {
+        if bone.rotation_keys.is_empty() && bone.translation_keys.is_empty() {
+            if ctx.args.verbose >= 1 {
+                println!(
+                    "Joint #{} '{}' is not animated in '{}' - using its rest pose",
+                    joint.index(),
+                    joint.name().unwrap_or_default(),
+                    anim.name().unwrap_or_default(),
+                );
+            }
+            let (rotation_key, translation_key) = make_rest_pose_keys(joint, start_time, config);
+            bone.rotation_keys.push(rotation_key);
+            bone.translation_keys.push(translation_key);
+        }
+    }
+}
+
+/// Transform of a bone relative to its parent bone in RF space
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct BonePose {
+    rotation: glam::Quat,
+    translation: glam::Vec3,
+}
+
+/// Maximal difference between quaternion components (or positions) for which a key is considered to be
+/// in the bind pose
+const BIND_POSE_TOLERANCE: f32 = 0.001_f32;
+
+impl BonePose {
+    fn matches_rotation_key(&self, key: &rfa::RotationKey) -> bool {
+        // q and -q represent the same rotation
+        key.rotation.to_quat().dot(self.rotation).abs() >= 1.0 - BIND_POSE_TOLERANCE
+    }
+
+    fn matches_translation_key(&self, key: &rfa::TranslationKey) -> bool {
+        (glam::Vec3::from(key.translation) - self.translation)
+            .abs()
+            .max_element()
+            <= BIND_POSE_TOLERANCE
+    }
+}
+
+/// Computes bind pose of exported bones from inverse bind matrices of the skin. The skeleton root keeps
+/// its rest transform because its keys are relative to a non-joint parent node which is not known
+/// to the inverse bind matrices.
+fn compute_bind_pose(
+    skin: &gltf::Skin,
+    config: &ConversionConfig,
+    ctx: &Context,
+) -> std::io::Result<Vec<BonePose>> {
+    let joints: Vec<_> = skin.joints().collect();
+    check_inverse_bind_matrix_count(skin)?;
+    let inverse_bind_matrices = read_inverse_bind_matrices(skin, ctx);
+    let bone_map = &ctx.bone_map;
+    let convention = config.coordinate_convention;
+    let poses = bone_map
+        .exported_joints
+        .iter()
+        .enumerate()
+        .map(|(bone_index, &joint_index)| {
+            let parent_joint_index = bone_map
+                .get_parent_bone_index(bone_index)
+                .map(|p| bone_map.exported_joints[p]);
+            let local_transform = match parent_joint_index {
+                Some(p) => {
+                    let parent_inverse_bind =
+                        glam::Mat4::from_cols_array_2d(&inverse_bind_matrices[p]);
+                    let inverse_bind =
+                        glam::Mat4::from_cols_array_2d(&inverse_bind_matrices[joint_index]);
+                    parent_inverse_bind * inverse_bind.inverse()
+                }
+                None => glam::Mat4::from_cols_array_2d(&joints[joint_index].transform().matrix()),
+            };
+            let (_, gltf_rotation, gltf_translation) =
+                local_transform.to_scale_rotation_translation();
+            BonePose {
+                rotation: glam::Quat::from_array(gltf_to_rf_quat(gltf_rotation.into(), convention)),
+                translation: glam::Vec3::from(gltf_to_rf_pos(
+                    gltf_translation.into(),
+                    convention,
+                    config.scale,
+                )),
+            }
+        })
+        .collect();
+    Ok(poses)
+}
+
+/// Inserts bind pose keys at the start of the animation if any bone does not start in the bind pose, so RF can
+/// blend into the animation without a pop. Existing keys are delayed by one frame to make room for them.
+fn prepend_bind_pose_keys(
+    bones: &mut [rfa::Bone],
+    bind_pose: &[BonePose],
+    start_time: RfaTime,
+    anim: &gltf::Animation,
+    ctx: &Context,
+) {
+    // channels without keys use the bind pose
+    let starts_in_bind_pose = bones.iter().zip(bind_pose).all(|(bone, pose)| {
+        let rotation_matches = bone
+            .rotation_keys
+            .first()
+            .is_none_or(|k| k.time == start_time && pose.matches_rotation_key(k));
+        let translation_matches = bone
+            .translation_keys
+            .first()
+            .is_none_or(|k| k.time == start_time && pose.matches_translation_key(k));
+        rotation_matches && translation_matches
+    });
+    if starts_in_bind_pose {
+        return;
+    }
+    if ctx.args.verbose >= 1 {
+        println!(
+            "Animation '{}' does not start in the bind pose - inserting bind pose keys",
+            anim.name().unwrap_or_default()
+        );
+    }
+    let delay = RfaTime(rfa::RFA_TICKS_PER_FRAME);
+    for (bone, pose) in bones.iter_mut().zip(bind_pose) {
+        if !bone.rotation_keys.is_empty() {
+            for key in &mut bone.rotation_keys {
+                key.time += delay;
+            }
+            bone.rotation_keys.insert(
+                0,
+                rfa::RotationKey {
+                    time: start_time,
+                    rotation: pack_computed_rotation(pose.rotation),
+                    ease_in: 0,
+                    ease_out: 0,
+                },
+            );
+        }
+        if !bone.translation_keys.is_empty() {
+            for key in &mut bone.translation_keys {
+                key.time += delay;
+            }
+            let translation = pose.translation.to_array();
+            bone.translation_keys.insert(
+                0,
+                rfa::TranslationKey {
+                    time: start_time,
+                    translation,
+                    in_tangent: translation,
+                    out_tangent: translation,
+                },
+            );
+        }
+    }
+}
+
+/// RFA has no flag for single frame animations and RF plays an animation over its time range, so an animation with
+/// all keys at the same time (e.g. a reference pose) is extended to one frame by holding its keys. Returns the new end
+/// time.
+fn extend_single_pose_animation(
+    bones: &mut [rfa::Bone],
+    start_time: RfaTime,
+    anim: &gltf::Animation,
+    ctx: &Context,
+) -> RfaTime {
+    let end_time = start_time + RfaTime(rfa::RFA_TICKS_PER_FRAME);
+    if ctx.args.verbose >= 1 {
+        println!(
+            "Animation {} has all keys at the same time. It is extended to one frame ({} ticks).",
+            anim.name().unwrap_or_default(),
+            rfa::RFA_TICKS_PER_FRAME
+        );
+    }
+    for bone in bones {
+        if let Some(last) = bone.rotation_keys.last() {
+            let key = rfa::RotationKey {
+                time: end_time,
+                ..last.clone()
+            };
+            bone.rotation_keys.push(key);
+        }
+        if let Some(last) = bone.translation_keys.last() {
+            let key = rfa::TranslationKey {
+                time: end_time,
+                ..last.clone()
+            };
+            bone.translation_keys.push(key);
+        }
+    }
+    end_time
+}
+
+/// Computes net rotation and translation of the root joint between its first and last key.
+/// Identity is returned for a component that has no keys.
+fn compute_root_motion(bones: &[rfa::Bone], root_index: Option<usize>) -> ([f32; 4], [f32; 3]) {
+    let Some(root_bone) = root_index.and_then(|i| bones.get(i)) else {
+        return ([0.0_f32, 0.0_f32, 0.0_f32, 1.0_f32], [0.0_f32; 3]);
+    };
+    let total_rotation = match (
+        root_bone.rotation_keys.first(),
+        root_bone.rotation_keys.last(),
+    ) {
+        (Some(first), Some(last)) => {
+            (last.rotation.to_quat() * first.rotation.to_quat().inverse()).to_array()
+        }
+        _ => [0.0_f32, 0.0_f32, 0.0_f32, 1.0_f32],
+    };
+    let total_translation = match (
+        root_bone.translation_keys.first(),
+        root_bone.translation_keys.last(),
+    ) {
+        (Some(first), Some(last)) => {
+            (glam::Vec3::from(last.translation) - glam::Vec3::from(first.translation)).to_array()
+        }
+        _ => [0.0_f32; 3],
+    };
+    (total_rotation, total_translation)
+}
+
+/// Number of ticks that can be added after the last key: a frame for the prepended bind pose and a frame for
+/// extending a single pose animation
+const MAX_ADDED_TICKS: i32 = 2 * rfa::RFA_TICKS_PER_FRAME;
+
+/// Makes sure all key times of the animation can be represented as RFA ticks (i32), including frames added
+/// during the conversion, so time arithmetic cannot overflow.
+fn check_anim_time_range(
+    anim: &gltf::Animation,
+    config: &ConversionConfig,
+    ctx: &Context,
+) -> std::io::Result<()> {
+    for channel in anim.channels() {
+        let reader = channel.reader(|buffer| ctx.get_buffer_data(buffer));
+        let Some(inputs) = reader.read_inputs() else {
+            continue;
+        };
+        for time_sec in inputs {
+            let ticks = f64::from(time_sec) * f64::from(RfaTime::ticks_per_second(config.fps));
+            let max_ticks = i32::MAX - MAX_ADDED_TICKS;
+            if !(0.0..=f64::from(max_ticks)).contains(&ticks) {
+                return Err(new_custom_error(format!(
+                    "animation {} has key at {} s ({} ticks) which is outside of RFA time range (0-{} ticks)",
+                    anim.name().unwrap_or_default(),
+                    time_sec,
+                    ticks,
+                    max_ticks
+                )));
+            }
+        }
     }
-    let mut wrt = BufWriter::new(File::create(&file_name)?);
-    let rfa = make_rfa(anim, skin, ctx);
-    rfa.write(&mut wrt)?;
     Ok(())
 }
 
-fn get_joint_index(node: &gltf::Node, skin: &gltf::Skin) -> usize {
+/// Moves keys of bones re-parented because of excluded joints or non-joint nodes into the space of the parent bone.
+/// Only the rest pose of excluded joints is taken into account so a warning is printed if they are animated.
+fn apply_parent_offsets(
+    bones: &mut [rfa::Bone],
+    anim: &gltf::Animation,
+    skin_joints: &[gltf::Node],
+    bone_map: &BoneMap,
+    convention: CoordinateConvention,
+    scale: f32,
+) {
+    for (joint_index, joint) in skin_joints.iter().enumerate() {
+        let is_animated = anim
+            .channels()
+            .any(|c| c.target().node().index() == joint.index());
+        if bone_map.is_reparenting[joint_index] && is_animated {
+            warn!(
+                "Excluded joint {} is animated in {}. Its animation is not applied to its children.",
+                joint.name().unwrap_or_default(),
+                anim.name().unwrap_or_default()
+            );
+        }
+    }
+    for (bone_index, bone) in bones.iter_mut().enumerate() {
+        let offset = bone_map.get_parent_offset(bone_index);
+        if offset == glam::Mat4::IDENTITY {
+            continue;
+        }
+        let (_, gltf_rotation, gltf_translation) = offset.to_scale_rotation_translation();
+        let rotation = glam::Quat::from_array(gltf_to_rf_quat(gltf_rotation.into(), convention));
+        let translation =
+            glam::Vec3::from(gltf_to_rf_pos(gltf_translation.into(), convention, scale));
+        for key in &mut bone.rotation_keys {
+            let key_rotation = rotation * key.rotation.to_quat();
+            key.rotation = pack_computed_rotation(key_rotation);
+        }
+        let transform_point =
+            |p: [f32; 3]| (rotation * glam::Vec3::from(p) + translation).to_array();
+        for key in &mut bone.translation_keys {
+            key.translation = transform_point(key.translation);
+            key.in_tangent = transform_point(key.in_tangent);
+            key.out_tangent = transform_point(key.out_tangent);
+        }
+    }
+}
+
+/// RFA has no event markers. RF triggers events like footsteps or melee hits from the game code and tables, so events
+/// from extras are only listed in the animation manifest.
+fn check_anim_events(
+    anim: &gltf::Animation,
+    anim_extras: &AnimExtras,
+    start_time: RfaTime,
+    end_time: RfaTime,
+    config: &ConversionConfig,
+) {
+    if anim_extras.events.is_empty() {
+        return;
+    }
+    let anim_name = anim.name().unwrap_or_default();
+    warn!(
+        "Animation {} has {} event(s) but RFA does not support events. They are only written to \
+        the animation manifest (--anim-manifest).",
+        anim_name,
+        anim_extras.events.len()
+    );
+    for event in &anim_extras.events {
+        let time = get_event_time(event, config);
+        if !(start_time..=end_time).contains(&time) {
+            warn!(
+                "Event {} of animation {} at {} ticks is outside of the animation time range ({}-{})",
+                event.name, anim_name, time, start_time, end_time
+            );
+        }
+    }
+}
+
+/// Maximal angle in degrees between the first and the last rotation key of a looping animation bone
+const LOOP_ROTATION_TOLERANCE: f32 = 1.0;
+/// Maximal distance between the first and the last translation key of a looping animation bone
+const LOOP_TRANSLATION_TOLERANCE: f32 = 0.01;
+
+/// Checks if animation is meant to loop. `loop` extra in the animation takes precedence over `--loop-anim`
+/// patterns.
+fn is_looping_anim(
+    anim: &gltf::Animation,
+    anim_extras: &AnimExtras,
+    config: &ConversionConfig,
+) -> bool {
+    let name = anim.name().unwrap_or_default();
+    anim_extras.looping.unwrap_or_else(|| {
+        config
+            .loop_anims
+            .iter()
+            .any(|pattern| matches_name_pattern(name, pattern))
+    })
+}
+
+/// Describes bones of a looping animation whose first and last keys differ. The game would show a visible pop
+/// when such animation starts over. Root joint translation is not compared because root motion is expected to
+/// move the character.
+fn find_loop_pose_mismatches(
+    bones: &[rfa::Bone],
+    joints: &[gltf::Node],
+    root_index: Option<usize>,
+) -> Vec<String> {
+    let mut mismatches = Vec::new();
+    for (i, (bone, joint)) in bones.iter().zip(joints).enumerate() {
+        let mut deltas = Vec::new();
+        if let (Some(first), Some(last)) = (bone.rotation_keys.first(), bone.rotation_keys.last()) {
+            let angle = if first.rotation == last.rotation {
+                0.0
+            } else {
+                first
+                    .rotation
+                    .to_quat()
+                    .angle_between(last.rotation.to_quat())
+                    .to_degrees()
+            };
+            if angle > LOOP_ROTATION_TOLERANCE {
+                deltas.push(format!("{:.1} deg", angle));
+            }
+        }
+        if let (Some(first), Some(last)) =
+            (bone.translation_keys.first(), bone.translation_keys.last())
+        {
+            let distance = glam::Vec3::from(first.translation).distance(last.translation.into());
+            if Some(i) != root_index && distance > LOOP_TRANSLATION_TOLERANCE {
+                deltas.push(format!("{:.3} units", distance));
+            }
+        }
+        if !deltas.is_empty() {
+            mismatches.push(format!(
+                "{} ({})",
+                get_bone_name(joint, i),
+                deltas.join(", ")
+            ));
+        }
+    }
+    mismatches
+}
+
+/// Creates RFA for an animation. Skin joints are nodes of the document containing the animation ordered
+/// like joints of the character skin. Only joints exported according to the bone map become RFA bones.
+/// Bind pose of exported bones is inserted at the start of the animation if specified.
+pub(crate) fn make_rfa(
+    anim: &gltf::Animation,
+    skin_joints: &[gltf::Node],
+    bind_pose: Option<&[BonePose]>,
+    config: &ConversionConfig,
+    ctx: &Context,
+) -> std::io::Result<rfa::File> {
+    let bone_map = &ctx.bone_map;
+    let joints: Vec<_> = bone_map
+        .exported_joints
+        .iter()
+        .map(|&i| skin_joints[i].clone())
+        .collect();
+    let joints = joints.as_slice();
+    check_bone_count(joints.len())?;
+    let root_index = find_root_joint_index(joints, bone_map, config)?;
+    check_anim_time_range(anim, config, ctx)?;
+    check_for_morph_channels(anim);
+    check_for_non_joint_channels(anim, skin_joints);
+    let mut bones = Vec::with_capacity(joints.len());
+    for joint in joints {
+        bones.push(convert_bone_anim(joint, anim, config, ctx)?);
+    }
+    // otherwise every bone would be filled with the rest pose and the file would freeze the character
+    if bones
+        .iter()
+        .all(|b| b.rotation_keys.is_empty() && b.translation_keys.is_empty())
+    {
+        return Err(new_custom_error(format!(
+            "animation #{} '{}' has no keys for any exported joint of the skin",
+            anim.index(),
+            anim.name().unwrap_or_default()
+        )));
+    }
+    check_key_time_alignment(&bones, anim, joints);
+    if let Some((trim_start, trim_end)) = get_trim_range(config)? {
+        trim_bone_keys(&mut bones, trim_start, trim_end);
+    }
+    let (start_time, mut end_time) = determine_anim_time_range(&bones);
+    backfill_unanimated_bones(&mut bones, anim, joints, start_time, config, ctx);
+    apply_parent_offsets(
+        &mut bones,
+        anim,
+        skin_joints,
+        bone_map,
+        config.coordinate_convention,
+        config.scale,
+    );
+    if let Some(bind_pose) = bind_pose {
+        prepend_bind_pose_keys(&mut bones, bind_pose, start_time, anim, ctx);
+        end_time = determine_anim_time_range(&bones).1;
+    }
+    if end_time == start_time {
+        end_time = extend_single_pose_animation(&mut bones, start_time, anim, ctx);
+    }
+    collapse_static_translations(&mut bones, root_index, anim, config, ctx);
+    reduce_bone_keys(&mut bones, anim, config, ctx);
+    apply_ease_preset(&mut bones, config.ease_preset);
+    quantize_bone_keys(&mut bones, anim, config, ctx)?;
+    let duration = end_time - start_time;
+    let anim_extras = get_anim_extras(anim);
+    if anim_extras.additive == Some(true) {
+        // blending mode is decided by the game code, not by the animation file
+        warn!(
+            "Animation {} is marked as additive but RFA has no additive flag. The flag is ignored.",
+            anim.name().unwrap_or_default()
+        );
+    }
+    check_anim_events(anim, &anim_extras, start_time, end_time, config);
+    if is_looping_anim(anim, &anim_extras, config) {
+        // RFA header has no loop flag, the game decides which animations loop
+        let mismatches = find_loop_pose_mismatches(&bones, joints, root_index);
+        if !mismatches.is_empty() {
+            warn!(
+                "Animation {} is looping but its last pose does not match the first one, which causes \
+                a visible pop when it starts over. Bones: {}",
+                anim.name().unwrap_or_default(),
+                mismatches.join(", ")
+            );
+        }
+    }
+    let root_joint_extras = get_root_joint_extras(joints, root_index);
+    let ramp_in_time =
+        determine_ramp_in_time(anim, &anim_extras, &root_joint_extras, duration, config);
+    let ramp_out_time = determine_ramp_out_time(
+        anim,
+        &anim_extras,
+        &root_joint_extras,
+        duration,
+        ramp_in_time,
+        config,
+    );
+    let (total_rotation, total_translation) = compute_root_motion(&bones, root_index);
+    if ctx.args.verbose >= 2 {
+        println!("Start time: {}", start_time);
+        println!("End time: {}", end_time);
+        println!("Ramp In time: {}", ramp_in_time);
+        println!("Ramp Out time: {}", ramp_out_time);
+    }
+    let header = rfa::FileHeader {
+        num_bones: bones.len() as i32,
+        start_time,
+        end_time,
+        ramp_in_time,
+        ramp_out_time,
+        total_rotation,
+        total_translation,
+        ..rfa::FileHeader::default()
+    };
+    Ok(rfa::File { header, bones })
+}
+
+/// Key statistics of one bone in a converted animation
+#[derive(Debug, Clone)]
+pub(crate) struct BoneAnimStats {
+    pub(crate) name: String,
+    pub(crate) num_rotation_keys: usize,
+    pub(crate) num_translation_keys: usize,
+    /// Time of the first and the last key, `None` if bone has no keys
+    pub(crate) time_span: Option<(RfaTime, RfaTime)>,
+}
+
+/// Key statistics of a converted animation. Useful for spotting bones without keys or with too many keys.
+#[derive(Debug, Clone)]
+pub(crate) struct AnimStats {
+    pub(crate) bones: Vec<BoneAnimStats>,
+    pub(crate) num_rotation_keys: usize,
+    pub(crate) num_translation_keys: usize,
+}
+
+impl AnimStats {
+    /// Computes statistics of RFA bones. `bone_names` must be ordered like RFA bones.
+    pub(crate) fn new(rfa: &rfa::File, bone_names: &[String]) -> Self {
+        let bones: Vec<_> = rfa
+            .bones
+            .iter()
+            .zip(bone_names)
+            .map(|(bone, name)| {
+                let times = bone
+                    .rotation_keys
+                    .iter()
+                    .map(|k| k.time)
+                    .chain(bone.translation_keys.iter().map(|k| k.time));
+                let time_span = times.fold(None, |span: Option<(RfaTime, RfaTime)>, t| {
+                    Some(span.map_or((t, t), |(min, max)| (min.min(t), max.max(t))))
+                });
+                BoneAnimStats {
+                    name: name.clone(),
+                    num_rotation_keys: bone.rotation_keys.len(),
+                    num_translation_keys: bone.translation_keys.len(),
+                    time_span,
+                }
+            })
+            .collect();
+        Self {
+            num_rotation_keys: bones.iter().map(|b| b.num_rotation_keys).sum(),
+            num_translation_keys: bones.iter().map(|b| b.num_translation_keys).sum(),
+            bones,
+        }
+    }
+
+    /// Returns the statistics as a table ready to be printed
+    pub(crate) fn format_table(&self, anim_name: &str) -> String {
+        let mut table = format!(
+            "Key statistics of animation {}:\n{:<24} {:>9} {:>9} {:>13}\n",
+            anim_name, "Bone", "Rotation", "Transl.", "Time span"
+        );
+        for b in &self.bones {
+            let time_span = b.time_span.map_or_else(
+                || "-".to_owned(),
+                |(start, end)| format!("{}-{}", start, end),
+            );
+            table += &format!(
+                "{:<24} {:>9} {:>9} {:>13}\n",
+                b.name, b.num_rotation_keys, b.num_translation_keys, time_span
+            );
+        }
+        table += &format!(
+            "{:<24} {:>9} {:>9}\n",
+            "Total", self.num_rotation_keys, self.num_translation_keys
+        );
+        table
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct RfaManifestEvent {
+    name: String,
+    time: RfaTime,
+}
+
+#[derive(Serialize, Debug)]
+struct RfaManifestEntry {
+    animation: String,
+    file_name: String,
+    start_time: RfaTime,
+    end_time: RfaTime,
+    num_bones: i32,
+    #[serde(rename = "loop", skip_serializing_if = "std::ops::Not::not")]
+    looping: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    events: Vec<RfaManifestEvent>,
+}
+
+fn get_anim_name(anim: &gltf::Animation, index: usize) -> String {
+    anim.name()
+        .map_or_else(|| format!("anim_{}", index), str::to_owned)
+}
+
+/// Replaces characters that are not allowed in file names
+fn sanitize_file_name(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect()
+}
+
+/// Determines RFA file name (relative to the output directory) for an animation. Names can be changed by
+/// `--rename-anim <name>=<file name>` option. Renamed files can be placed in subdirectories. Other names get
+/// the prefix selected by `--anim-prefix` removed if `--strip-anim-prefix` is enabled.
+fn determine_rfa_file_name(name: &str, ctx: &Context) -> String {
+    let renamed = ctx.args.rename_anim.iter().find_map(|mapping| {
+        mapping
+            .split_once('=')
+            .filter(|(from, _)| *from == name)
+            .map(|(_, to)| to)
+    });
+    let stem = match renamed {
+        Some(to) => to
+            .trim_end_matches(".rfa")
+            .split(['/', '\\'])
+            // do not allow leaving the output directory
+            .filter(|part| !part.is_empty() && *part != "." && *part != "..")
+            .map(sanitize_file_name)
+            .collect::<Vec<_>>()
+            .join("/"),
+        None => {
+            let stripped = name
+                .strip_prefix(ctx.args.anim_prefix.as_str())
+                .filter(|rest| ctx.args.strip_anim_prefix && !rest.is_empty());
+            sanitize_file_name(stripped.unwrap_or(name))
+        }
+    };
+    format!("{}.rfa", stem)
+}
+
+/// Creates RFA for an animation (see `make_rfa`) and checks that the result is a valid RFA file
+fn make_valid_rfa(
+    anim: &gltf::Animation,
+    name: &str,
+    joints: &[gltf::Node],
+    bind_pose: Option<&[BonePose]>,
+    config: &ConversionConfig,
+    ctx: &Context,
+) -> std::io::Result<rfa::File> {
+    let rfa = make_rfa(anim, joints, bind_pose, config, ctx)?;
+    rfa.validate()
+        .map_err(|e| new_custom_error(format!("animation {}: {}", name, e)))?;
+    Ok(rfa)
+}
+
+fn convert_animation_to_rfa(
+    anim: &gltf::Animation,
+    index: usize,
+    rfa_file_name: &str,
+    joints: &[gltf::Node],
+    bind_pose: Option<&[BonePose]>,
+    config: &ConversionConfig,
+    ctx: &Context,
+) -> std::io::Result<RfaManifestEntry> {
+    let name = get_anim_name(anim, index);
+    let file_name = ctx.output_dir.join(rfa_file_name);
+    if ctx.args.verbose >= 2 {
+        println!("Exporting animation: {} -> {}", name, file_name.display());
+    }
+    let rfa = make_valid_rfa(anim, &name, joints, bind_pose, config, ctx)?;
+    if file_name.is_dir() {
+        let err = new_custom_error("output path of animation is a directory");
+        return Err(add_path_to_error(err, &file_name));
+    }
+    if let Some(dir) = file_name.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| add_path_to_error(e, dir))?;
+    }
+    let endian = if ctx.args.big_endian {
+        binrw::Endian::Big
+    } else {
+        binrw::Endian::Little
+    };
+    std::fs::write(&file_name, rfa.to_bytes(endian)?)
+        .map_err(|e| add_path_to_error(e, &file_name))?;
+    let anim_extras = get_anim_extras(anim);
+    if ctx.args.anim_stats {
+        let bone_names: Vec<_> = ctx
+            .bone_map
+            .exported_joints
+            .iter()
+            .map(|&i| get_bone_name(&joints[i], i))
+            .collect();
+        // print the whole table at once so output of animations converted in parallel is not interleaved
+        print!("{}", AnimStats::new(&rfa, &bone_names).format_table(&name));
+    }
+    Ok(RfaManifestEntry {
+        file_name: rfa_file_name.to_owned(),
+        animation: name,
+        start_time: rfa.header.start_time,
+        end_time: rfa.header.end_time,
+        num_bones: rfa.header.num_bones,
+        looping: is_looping_anim(anim, &anim_extras, config),
+        events: anim_extras
+            .events
+            .into_iter()
+            .map(|e| RfaManifestEvent {
+                time: get_event_time(&e, config),
+                name: e.name,
+            })
+            // events cut off by trimming are dropped
+            .filter(|e| {
+                config.trim.is_none() || (RfaTime::ZERO..=rfa.header.end_time).contains(&e.time)
+            })
+            .collect(),
+    })
+}
+
+/// Checks if animation was selected by `--anim` or `--anim-index` options. All animations are selected if
+/// none of those options is used.
+fn is_anim_selected(anim: &gltf::Animation, index: usize, ctx: &Context) -> bool {
+    let args = &ctx.args;
+    if args.anim.is_empty() && args.anim_index.is_empty() {
+        return true;
+    }
+    let name_matches = anim
+        .name()
+        .is_some_and(|name| args.anim.iter().any(|a| a == name));
+    name_matches || args.anim_index.contains(&index)
+}
+
+/// Animations loaded from a separate glTF file that uses the same skeleton as the character
+pub(crate) struct AnimFile {
+    pub(crate) path: PathBuf,
+    pub(crate) document: gltf::Document,
+    pub(crate) ctx: Context,
+}
+
+fn get_bone_name(n: &gltf::Node, index: usize) -> String {
+    n.name()
+        .map_or_else(|| format!("bone_{}", index), str::to_owned)
+}
+
+/// Finds nodes in an animation file matching the character joints by name. Returned nodes are ordered like
+/// character bones. Nodes of the first skin are used if the file has one, otherwise all named nodes.
+fn find_anim_file_joints<'a>(
+    skin: &gltf::Skin,
+    anim_file: &'a AnimFile,
+) -> std::io::Result<Vec<gltf::Node<'a>>> {
+    let doc = &anim_file.document;
+    let candidates: Vec<(String, gltf::Node)> = match doc.skins().next() {
+        Some(anim_skin) => {
+            let num_joints = anim_skin.joints().count();
+            if num_joints != skin.joints().count() {
+                return Err(new_custom_error(format!(
+                    "skin in {} has {} joints but character has {}",
+                    anim_file.path.display(),
+                    num_joints,
+                    skin.joints().count()
+                )));
+            }
+            anim_skin
+                .joints()
+                .enumerate()
+                .map(|(i, n)| (get_bone_name(&n, i), n))
+                .collect()
+        }
+        None => doc
+            .nodes()
+            .filter_map(|n| n.name().map(|name| (name.to_owned(), n.clone())))
+            .collect(),
+    };
     skin.joints()
         .enumerate()
-        .filter(|(_i, n)| node.index() == n.index())
-        .map(|(i, _n)| i)
-        .next()
-        .expect("joint not found")
+        .map(|(i, joint)| {
+            let name = get_bone_name(&joint, i);
+            candidates
+                .iter()
+                .find(|(candidate_name, _)| *candidate_name == name)
+                .map(|(_, n)| n.clone())
+                .ok_or_else(|| {
+                    new_custom_error(format!(
+                        "bone {} not found in {}",
+                        name,
+                        anim_file.path.display()
+                    ))
+                })
+        })
+        .collect()
+}
+
+/// Animation selected for conversion together with the document it comes from
+pub(crate) struct RfaJob<'a> {
+    anim: gltf::Animation<'a>,
+    index: usize,
+    name: String,
+    rfa_file_name: String,
+    joints: Vec<gltf::Node<'a>>,
+    ctx: &'a Context,
+}
+
+impl RfaJob<'_> {
+    /// Path of the RFA file written for this animation
+    pub(crate) fn output_path(&self) -> PathBuf {
+        self.ctx.output_dir.join(&self.rfa_file_name)
+    }
+}
+
+/// Selects animations of the document and of additional animation files for conversion and assigns unique RFA
+/// file names to them. Skipped animations and name collisions are reported.
+pub(crate) fn select_rfa_jobs<'a>(
+    doc: &'a gltf::Document,
+    skin: &gltf::Skin<'a>,
+    anim_files: &'a [AnimFile],
+    ctx: &'a Context,
+) -> std::io::Result<Vec<RfaJob<'a>>> {
+    let joints: Vec<_> = skin.joints().collect();
+    let mut sources = vec![(doc, joints, ctx)];
+    for anim_file in anim_files {
+        let anim_file_joints = find_anim_file_joints(skin, anim_file)?;
+        sources.push((&anim_file.document, anim_file_joints, &anim_file.ctx));
+    }
+    let mut selected = Vec::new();
+    let mut index = 0;
+    for (source_doc, source_joints, source_ctx) in &sources {
+        for anim in source_doc.animations() {
+            let other_skin = find_other_animated_skin(&anim, source_doc, source_joints);
+            if let Some(other_skin) = other_skin.filter(|_| is_anim_selected(&anim, index, ctx)) {
+                warn!(
+                    "Animation #{} '{}' animates joints of skin #{} '{}' instead of the converted skin. \
+                    It has been skipped!",
+                    index,
+                    anim.name().unwrap_or_default(),
+                    other_skin.index(),
+                    other_skin.name().unwrap_or_default()
+                );
+            } else if is_anim_selected(&anim, index, ctx) {
+                selected.push((anim, index, source_joints, *source_ctx));
+            } else if ctx.args.verbose >= 2 {
+                println!(
+                    "Skipping animation #{} '{}'",
+                    index,
+                    anim.name().unwrap_or_default()
+                );
+            }
+            index += 1;
+        }
+    }
+    for name in &ctx.args.anim {
+        let found = sources
+            .iter()
+            .any(|(d, _, _)| d.animations().any(|a| a.name() == Some(name.as_str())));
+        if !found {
+            warn!("Animation '{}' not found", name);
+        }
+    }
+    let mut jobs = Vec::with_capacity(selected.len());
+    let mut used_file_names = HashSet::new();
+    for (anim, index, source_joints, source_ctx) in selected {
+        let name = get_anim_name(&anim, index);
+        let mut rfa_file_name = determine_rfa_file_name(&name, ctx);
+        // file names are compared case-insensitively because of Windows file system
+        if !used_file_names.insert(rfa_file_name.to_lowercase()) {
+            let stem = rfa_file_name.trim_end_matches(".rfa");
+            let mut unique_file_name = format!("{}_{}.rfa", stem, index);
+            let mut suffix = 1;
+            while !used_file_names.insert(unique_file_name.to_lowercase()) {
+                unique_file_name = format!("{}_{}_{}.rfa", stem, index, suffix);
+                suffix += 1;
+            }
+            warn!(
+                "Animation {} would overwrite {}. Saving it as {}",
+                name, rfa_file_name, unique_file_name
+            );
+            rfa_file_name = unique_file_name;
+        }
+        jobs.push(RfaJob {
+            anim,
+            index,
+            name,
+            rfa_file_name,
+            joints: source_joints.clone(),
+            ctx: source_ctx,
+        });
+    }
+    Ok(jobs)
+}
+
+/// Converts animations selected by `select_rfa_jobs` to RFA files.
+/// If `manifest_path` is given a JSON file listing every written RFA with its time range and bone count
+/// is saved there. `progress` is called before converting each animation with its position, number of converted
+/// animations and name. Animations are converted in parallel if `parallel` feature is enabled and verbose output is
+/// disabled so `progress` can be called from multiple threads.
+pub(crate) fn convert_animations_to_rfa(
+    skin: &gltf::Skin,
+    jobs: &[RfaJob],
+    manifest_path: Option<&Path>,
+    progress: impl Fn(usize, usize, &str) + Sync,
+    config: &ConversionConfig,
+    ctx: &Context,
+) -> std::io::Result<()> {
+    let bind_pose = if config.prepend_bind_pose {
+        Some(compute_bind_pose(skin, config, ctx)?)
+    } else {
+        None
+    };
+    let num_jobs = jobs.len();
+    let convert_job = |(i, job): (usize, &RfaJob)| {
+        progress(i, num_jobs, &job.name);
+        convert_animation_to_rfa(
+            &job.anim,
+            job.index,
+            &job.rfa_file_name,
+            &job.joints,
+            bind_pose.as_deref(),
+            config,
+            job.ctx,
+        )
+    };
+    // animations are independent and every one is written to its own file. Verbose output is printed while
+    // converting so animations are converted one by one to keep messages of each animation together
+    #[cfg(feature = "parallel")]
+    let manifest = if ctx.args.verbose == 0 {
+        jobs.par_iter()
+            .enumerate()
+            .map(convert_job)
+            .collect::<std::io::Result<Vec<_>>>()?
+    } else {
+        jobs.iter()
+            .enumerate()
+            .map(convert_job)
+            .collect::<std::io::Result<Vec<_>>>()?
+    };
+    #[cfg(not(feature = "parallel"))]
+    let manifest = jobs
+        .iter()
+        .enumerate()
+        .map(convert_job)
+        .collect::<std::io::Result<Vec<_>>>()?;
+    if let Some(path) = manifest_path {
+        if ctx.args.verbose >= 1 {
+            println!("Exporting animation manifest: {}", path.display());
+        }
+        let file = File::create(path).map_err(|e| add_path_to_error(e, path))?;
+        let wrt = BufWriter::new(file);
+        serde_json::to_writer_pretty(wrt, &manifest)?;
+    }
+    Ok(())
 }
 
-fn get_joint_parent<'a>(node: &gltf::Node, skin: &gltf::Skin<'a>) -> Option<gltf::Node<'a>> {
+fn get_joint_index(node: &gltf::Node, skin: &gltf::Skin) -> std::io::Result<usize> {
     skin.joints()
-        .find(|n| n.children().any(|c| c.index() == node.index()))
+        .position(|n| node.index() == n.index())
+        .ok_or_else(|| {
+            new_custom_error(format!(
+                "node #{} '{}' is not a joint of the skin",
+                node.index(),
+                node.name().unwrap_or_default()
+            ))
+        })
+}
+
+/// Finds parent of a node in the document. A node listed as a child of more than one node is reported as an error.
+fn get_node_parent<'a>(
+    node: &gltf::Node,
+    doc: &'a gltf::Document,
+) -> std::io::Result<Option<gltf::Node<'a>>> {
+    let mut parents = doc
+        .nodes()
+        .filter(|n| n.children().any(|c| c.index() == node.index()));
+    let parent = parents.next();
+    if let (Some(parent), Some(other_parent)) = (&parent, parents.next()) {
+        return Err(new_custom_error(format!(
+            "node {} has ambiguous parent: it is a child of both {} and {}",
+            node.name().unwrap_or_default(),
+            parent.name().unwrap_or_default(),
+            other_parent.name().unwrap_or_default()
+        )));
+    }
+    Ok(parent)
+}
+
+/// Finds parent joint using node hierarchy. The hierarchy does not depend on the node transform representation
+/// (matrix or TRS). Some rigs have non-joint nodes between a joint and its parent joint. They are returned too
+/// (ordered from the nearest one) so their transforms can be merged into the joint.
+fn get_joint_parent<'a>(
+    node: &gltf::Node,
+    doc: &'a gltf::Document,
+    skin: &gltf::Skin,
+) -> std::io::Result<Option<(gltf::Node<'a>, Vec<gltf::Node<'a>>)>> {
+    let mut intermediate_nodes = Vec::new();
+    let mut current = get_node_parent(node, doc)?;
+    while let Some(parent) = current {
+        if is_joint(&parent, skin) {
+            return Ok(Some((parent, intermediate_nodes)));
+        }
+        current = get_node_parent(&parent, doc)?;
+        intermediate_nodes.push(parent);
+    }
+    Ok(None)
+}
+
+/// Checks if a name matches a pattern. `*` in the pattern matches any sequence of characters.
+fn matches_name_pattern(name: &str, pattern: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let Some(mut rest) = name.strip_prefix(parts.next().unwrap_or_default()) else {
+        return false;
+    };
+    let parts: Vec<_> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        // no wildcard in the pattern
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(pos) => rest = &rest[pos + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+/// Mapping of skin joints to exported bones. Joints with names matching `--exclude-bone` patterns (e.g. IK targets
+/// or control bones) are not exported. Children of an excluded joint are re-parented to its nearest exported
+/// ancestor and transforms of excluded joints in between are folded into the children. The same is done for non-joint
+/// nodes placed between joints.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct BoneMap {
+    /// Skin joint index of every exported bone
+    exported_joints: Vec<usize>,
+    /// Bone index for every skin joint: the bone of the joint itself or of its nearest exported ancestor
+    joint_bones: Vec<Option<usize>>,
+    /// Parent joint index of every skin joint
+    joint_parents: Vec<Option<usize>>,
+    /// Transform of non-joint nodes between every skin joint and its parent joint
+    intermediate_transforms: Vec<glam::Mat4>,
+    /// Rest pose transform (glTF space) of the chain of excluded joints ending with the joint relative to
+    /// the nearest exported ancestor. Identity for exported joints
+    excluded_transforms: Vec<glam::Mat4>,
+    /// True for excluded joints that are ancestors of exported bones
+    is_reparenting: Vec<bool>,
+}
+
+impl BoneMap {
+    pub(crate) fn new(
+        doc: &gltf::Document,
+        skin: &gltf::Skin,
+        exclude_patterns: &[String],
+    ) -> std::io::Result<Self> {
+        let joints: Vec<_> = skin.joints().collect();
+        let mut joint_parents = Vec::with_capacity(joints.len());
+        let mut intermediate_transforms = Vec::with_capacity(joints.len());
+        for joint in &joints {
+            let Some((parent, intermediate_nodes)) = get_joint_parent(joint, doc, skin)? else {
+                joint_parents.push(None);
+                intermediate_transforms.push(glam::Mat4::IDENTITY);
+                continue;
+            };
+            if !intermediate_nodes.is_empty() {
+                let names: Vec<_> = intermediate_nodes
+                    .iter()
+                    .map(|n| n.name().unwrap_or_default())
+                    .collect();
+                warn!(
+                    "Joint {} is attached to joint {} through non-joint node(s): {}. \
+                    Their transform is merged into the joint.",
+                    joint.name().unwrap_or_default(),
+                    parent.name().unwrap_or_default(),
+                    names.join(", ")
+                );
+            }
+            joint_parents.push(Some(get_joint_index(&parent, skin)?));
+            intermediate_transforms.push(
+                intermediate_nodes
+                    .iter()
+                    .rev()
+                    .fold(glam::Mat4::IDENTITY, |acc, n| {
+                        acc * get_node_local_transform(n)
+                    }),
+            );
+        }
+        let is_excluded: Vec<_> = joints
+            .iter()
+            .map(|n| {
+                let name = n.name().unwrap_or_default();
+                n.name().is_some()
+                    && exclude_patterns
+                        .iter()
+                        .any(|pattern| matches_name_pattern(name, pattern))
+            })
+            .collect();
+        let exported_joints: Vec<_> = (0..joints.len()).filter(|&i| !is_excluded[i]).collect();
+        let mut own_bones = vec![None; joints.len()];
+        for (bone_index, &joint_index) in exported_joints.iter().enumerate() {
+            own_bones[joint_index] = Some(bone_index);
+        }
+        let mut joint_bones = vec![None; joints.len()];
+        let mut excluded_transforms = vec![glam::Mat4::IDENTITY; joints.len()];
+        let mut is_reparenting = vec![false; joints.len()];
+        for i in 0..joints.len() {
+            let mut joint_index = Some(i);
+            while let Some(j) = joint_index.filter(|&j| is_excluded[j]) {
+                excluded_transforms[i] = intermediate_transforms[j]
+                    * get_node_local_transform(&joints[j])
+                    * excluded_transforms[i];
+                joint_index = joint_parents[j];
+            }
+            joint_bones[i] = joint_index.and_then(|j| own_bones[j]);
+            if !is_excluded[i] {
+                let mut ancestor = joint_parents[i];
+                while let Some(j) = ancestor.filter(|&j| is_excluded[j]) {
+                    is_reparenting[j] = true;
+                    ancestor = joint_parents[j];
+                }
+            }
+        }
+        Ok(Self {
+            exported_joints,
+            joint_bones,
+            joint_parents,
+            intermediate_transforms,
+            excluded_transforms,
+            is_reparenting,
+        })
+    }
+
+    pub(crate) fn num_bones(&self) -> usize {
+        self.exported_joints.len()
+    }
+
+    /// Returns index of the bone replacing a skin joint (the joint itself or its nearest exported ancestor)
+    pub(crate) fn get_bone_index(&self, joint_index: usize) -> Option<usize> {
+        self.joint_bones.get(joint_index).copied().flatten()
+    }
+
+    /// Returns transform of a node attached to a skin joint relative to the bone returned by `get_bone_index`
+    pub(crate) fn get_attachment_transform(&self, joint_index: usize) -> glam::Mat4 {
+        self.excluded_transforms
+            .get(joint_index)
+            .copied()
+            .unwrap_or(glam::Mat4::IDENTITY)
+    }
+
+    fn get_parent_bone_index(&self, bone_index: usize) -> Option<usize> {
+        self.joint_parents[self.exported_joints[bone_index]].and_then(|p| self.joint_bones[p])
+    }
+
+    /// Returns transform of excluded joints and non-joint nodes between an exported bone and its parent bone
+    fn get_parent_offset(&self, bone_index: usize) -> glam::Mat4 {
+        let joint_index = self.exported_joints[bone_index];
+        let excluded_transform = self.joint_parents[joint_index]
+            .map_or(glam::Mat4::IDENTITY, |p| self.excluded_transforms[p]);
+        excluded_transform * self.intermediate_transforms[joint_index]
+    }
+}
+
+/// Warns about joints that have no parent joint even though the skin declares one of the joints as
+/// the skeleton root. It usually means that the hierarchy was flattened by the exporter.
+fn check_skeleton_root(skin: &gltf::Skin, bones: &[v3mc::Bone], bone_map: &BoneMap) {
+    let Some(skeleton) = skin.skeleton() else {
+        return;
+    };
+    let Some(skeleton_bone_index) = skin
+        .joints()
+        .position(|n| n.index() == skeleton.index())
+        .and_then(|i| bone_map.get_bone_index(i))
+    else {
+        return;
+    };
+    for (i, bone) in bones.iter().enumerate() {
+        if bone.parent_index == -1 && i != skeleton_bone_index {
+            warn!(
+                "Joint {} has no parent joint but skeleton root of the skin is {}",
+                bone.name,
+                skeleton.name().unwrap_or_default()
+            );
+        }
+    }
+}
+
+/// Checks if bone scale in the bind pose can be handled. Returns true if the scale has to be removed.
+fn check_bone_scale(
+    name: &str,
+    gltf_scale: glam::Vec3,
+    bake_bone_scale: bool,
+) -> std::io::Result<bool> {
+    let is_uniform_scale = (gltf_scale - glam::Vec3::splat(gltf_scale.x))
+        .abs()
+        .max_element()
+        < 0.01_f32;
+    if !is_uniform_scale {
+        return Err(new_custom_error(format!(
+            "bone {} has non-uniform scale {} which is not supported",
+            name, gltf_scale
+        )));
+    }
+    let is_scaled = (gltf_scale - glam::Vec3::ONE).abs().max_element() >= 0.01_f32;
+    if is_scaled && !bake_bone_scale {
+        return Err(new_custom_error(format!(
+            "bone {} has scale {} which is not supported (use --bake-bone-scale to ignore it)",
+            name, gltf_scale
+        )));
+    }
+    Ok(is_scaled)
+}
+
+/// Checks if bone transform in the bind pose can be represented by scale, rotation and translation.
+/// Shear (or projection) cannot be stored in RF bones and would silently produce a wrong rotation.
+fn check_bone_shear(
+    name: &str,
+    transform: &glam::Mat4,
+    scale: glam::Vec3,
+    rotation: glam::Quat,
+) -> std::io::Result<()> {
+    let reconstructed = glam::Mat3::from_quat(rotation) * glam::Mat3::from_diagonal(scale);
+    let tolerance = 0.01_f32 * scale.abs().max_element().max(1.0);
+    let is_affine = transform.row(3).abs_diff_eq(glam::Vec4::W, 0.001_f32);
+    if !is_affine || !glam::Mat3::from_mat4(*transform).abs_diff_eq(reconstructed, tolerance) {
+        return Err(new_custom_error(format!(
+            "bone {} has unrepresentable transform (shear) in the inverse bind matrix",
+            name
+        )));
+    }
+    Ok(())
 }
 
 fn convert_bone(
     n: &gltf::Node,
     inverse_bind_matrix: &[[f32; 4]; 4],
     index: usize,
-    skin: &gltf::Skin,
-) -> v3mc::Bone {
-    let name = n
-        .name()
-        .map_or_else(|| format!("bone_{}", index), str::to_owned);
-    let parent_node_opt = get_joint_parent(n, skin);
-    let parent_index = parent_node_opt.map_or(-1, |pn| get_joint_index(&pn, skin) as i32);
+    parent_index: i32,
+    config: &ConversionConfig,
+) -> std::io::Result<v3mc::Bone> {
+    let name = get_bone_name(n, index);
     let inv_transform = glam::Mat4::from_cols_array_2d(inverse_bind_matrix);
-    let (gltf_scale, gltf_rotation, gltf_translation) =
+    let (gltf_scale, gltf_rotation, mut gltf_translation) =
         inv_transform.to_scale_rotation_translation();
-    assert!(
-        (gltf_scale - glam::Vec3::ONE).max_element() < 0.01_f32,
-        "scale is not supported: {}",
-        gltf_scale
-    );
-    let base_rotation = gltf_to_rf_quat(gltf_rotation.into());
-    let base_translation = gltf_to_rf_vec(gltf_translation.into());
-    v3mc::Bone {
+    if check_bone_scale(&name, gltf_scale, config.bake_bone_scale)? {
+        // Keep bone position in the bind pose unchanged after removing the scale
+        gltf_translation /= gltf_scale.x;
+    }
+    check_bone_shear(&name, &inv_transform, gltf_scale, gltf_rotation)?;
+    let convention = config.coordinate_convention;
+    let base_rotation = gltf_to_rf_quat(gltf_rotation.into(), convention);
+    let base_translation = gltf_to_rf_pos(gltf_translation.into(), convention, config.scale);
+    Ok(v3mc::Bone {
         name,
         base_rotation,
         base_translation,
         parent_index,
+    })
+}
+
+/// Reads inverse bind matrices of the skin. If they are not defined glTF specification says that
+/// identity matrices should be used.
+fn read_inverse_bind_matrices(skin: &gltf::Skin, ctx: &Context) -> Vec<[[f32; 4]; 4]> {
+    let reader = skin.reader(|buffer| ctx.get_buffer_data(buffer));
+    match reader.read_inverse_bind_matrices() {
+        Some(iter) => iter.collect(),
+        None => vec![glam::Mat4::IDENTITY.to_cols_array_2d(); skin.joints().count()],
     }
 }
 
-pub(crate) fn convert_bones(skin: &gltf::Skin, ctx: &Context) -> std::io::Result<Vec<v3mc::Bone>> {
+/// Checks that the skin defines an inverse bind matrix for every joint. The accessor count is used so matrices
+/// do not have to be read.
+fn check_inverse_bind_matrix_count(skin: &gltf::Skin) -> std::io::Result<()> {
     let num_joints = skin.joints().count();
-    if ctx.args.verbose >= 2 {
-        println!("Bones (joints): {}/{}", num_joints, v3mc::MAX_BONES);
+    let num_matrices = skin
+        .inverse_bind_matrices()
+        .map_or(num_joints, |accessor| accessor.count());
+    if num_matrices != num_joints {
+        let err_msg = format!(
+            "invalid number of inverse bind matrices: expected {}, got {}",
+            num_joints, num_matrices
+        );
+        return Err(new_custom_error(err_msg));
     }
+    Ok(())
+}
+
+fn check_bone_count(num_joints: usize) -> std::io::Result<()> {
     if num_joints > v3mc::MAX_BONES {
         let err_msg = format!(
             "too many bones: found {} but only {} are supported",
@@ -387,27 +2695,51 @@ pub(crate) fn convert_bones(skin: &gltf::Skin, ctx: &Context) -> std::io::Result
         );
         return Err(new_custom_error(err_msg));
     }
+    Ok(())
+}
 
-    let inverse_bind_matrices: Vec<_> = skin
-        .reader(|buffer| ctx.get_buffer_data(buffer))
-        .read_inverse_bind_matrices()
-        .expect("expected inverse bind matrices")
-        .collect();
-
-    if inverse_bind_matrices.len() != num_joints {
-        let err_msg = format!(
-            "invalid number of inverse bind matrices: expected {}, got {}",
-            num_joints,
-            inverse_bind_matrices.len()
+pub(crate) fn convert_bones(
+    skin: &gltf::Skin,
+    config: &ConversionConfig,
+    ctx: &Context,
+) -> std::io::Result<Vec<v3mc::Bone>> {
+    let num_joints = skin.joints().count();
+    let bone_map = &ctx.bone_map;
+    if ctx.args.verbose >= 2 {
+        println!(
+            "Bones (joints): {}/{}",
+            bone_map.num_bones(),
+            v3mc::MAX_BONES
         );
-        return Err(new_custom_error(err_msg));
+        if bone_map.num_bones() < num_joints {
+            println!("Excluded joints: {}", num_joints - bone_map.num_bones());
+        }
     }
+    check_bone_count(bone_map.num_bones())?;
+
+    check_inverse_bind_matrix_count(skin)?;
 
-    let mut bones = Vec::with_capacity(num_joints);
-    for (i, n) in skin.joints().enumerate() {
-        let bone = convert_bone(&n, &inverse_bind_matrices[i], i, skin);
+    // matrices are read one by one instead of being collected for all joints
+    let reader = skin.reader(|buffer| ctx.get_buffer_data(buffer));
+    let identity = glam::Mat4::IDENTITY.to_cols_array_2d();
+    let inverse_bind_matrices = reader
+        .read_inverse_bind_matrices()
+        .into_iter()
+        .flatten()
+        .chain(std::iter::repeat(identity));
+    let mut bones = Vec::with_capacity(bone_map.num_bones());
+    for (i, (joint, inverse_bind_matrix)) in skin.joints().zip(inverse_bind_matrices).enumerate() {
+        // exported joints are sorted so bones are created in order of their indices
+        let Ok(bone_index) = bone_map.exported_joints.binary_search(&i) else {
+            continue;
+        };
+        let parent_index = bone_map
+            .get_parent_bone_index(bone_index)
+            .map_or(-1, |p| p as i32);
+        let bone = convert_bone(&joint, &inverse_bind_matrix, i, parent_index, config)?;
         bones.push(bone);
     }
+    check_skeleton_root(skin, &bones, bone_map);
     Ok(bones)
 }
 
@@ -415,13 +2747,872 @@ fn is_joint(node: &gltf::Node, skin: &gltf::Skin) -> bool {
     skin.joints().any(|joint| node.index() == joint.index())
 }
 
+/// Returns nodes attached to joints of the skin with index of the parent bone (-1 if none) and transform of
+/// excluded joints between the bone and the node.
 pub(crate) fn get_nodes_parented_to_bones<'a>(
     skin: &'a gltf::Skin,
-) -> impl Iterator<Item = (gltf::Node<'a>, i32)> {
+    bone_map: &'a BoneMap,
+) -> impl Iterator<Item = (gltf::Node<'a>, i32, glam::Mat4)> + 'a {
     skin.joints()
-        .flat_map(move |joint| {
-            let joint_index = get_joint_index(&joint, skin) as i32;
-            joint.children().map(move |n| (n, joint_index))
+        .enumerate()
+        .flat_map(move |(joint_index, joint)| {
+            let parent_index = bone_map
+                .get_bone_index(joint_index)
+                .map_or(-1, |i| i as i32);
+            let transform = bone_map.get_attachment_transform(joint_index);
+            joint.children().map(move |n| (n, parent_index, transform))
+        })
+        .filter(move |(node, _, _)| !is_joint(node, skin))
+}
+
+/// Returns attachment points (e.g. weapon mounts) defined by `rf_attach_point` extras of joints: name, parent bone
+/// index and transform relative to the parent bone. V3C bones have no flags so they are exported as prop points
+/// placed at the joint origin.
+pub(crate) fn get_joint_attach_points<'a>(
+    skin: &'a gltf::Skin,
+    bone_map: &'a BoneMap,
+) -> impl Iterator<Item = (String, i32, glam::Mat4)> + 'a {
+    skin.joints()
+        .enumerate()
+        .filter_map(move |(joint_index, joint)| {
+            let name = get_node_extras::<JointExtras>(&joint)
+                .get_attach_point_name(joint.name().unwrap_or_default())?;
+            let parent_index = bone_map
+                .get_bone_index(joint_index)
+                .map_or(-1, |i| i as i32);
+            Some((
+                name,
+                parent_index,
+                bone_map.get_attachment_transform(joint_index),
+            ))
         })
-        .filter(move |(node, _)| !is_joint(node, skin))
+}
+
+/// Collects all problems that would be hit when converting the skin and the selected animations instead of
+/// stopping at the first one. Bones and animations are converted like in the real conversion but nothing is written.
+pub(crate) fn validate_skin(
+    skin: &gltf::Skin,
+    jobs: &[RfaJob],
+    config: &ConversionConfig,
+    ctx: &Context,
+) -> Vec<String> {
+    let mut issues = Vec::new();
+    if let Err(err) = convert_bones(skin, config, ctx) {
+        issues.push(err.to_string());
+    }
+    let bind_pose = if config.prepend_bind_pose {
+        compute_bind_pose(skin, config, ctx)
+            .map_err(|err| issues.push(err.to_string()))
+            .ok()
+    } else {
+        None
+    };
+    for job in jobs {
+        let result = make_valid_rfa(
+            &job.anim,
+            &job.name,
+            &job.joints,
+            bind_pose.as_deref(),
+            config,
+            job.ctx,
+        );
+        if let Err(err) = result {
+            issues.push(err.to_string());
+        }
+    }
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{load_test_gltf, Args};
+    use clap::Parser;
+    use std::io::Cursor;
+
+    /// Two joints (`root` with child `bone1`) and one 1 s long `walk` animation: linear translation of `root` along Y
+    /// axis and rotation of `bone1` around Y axis, both keyed at 0, 0.5 and 1 s
+    const SKINNED_ANIM_GLTF: &[u8] = include_bytes!("../tests/data/skinned_anim.gltf");
+    /// Same as `skinned_anim.gltf` but with sparse key and vertex position accessors
+    const SPARSE_ANIM_GLTF: &[u8] = include_bytes!("../tests/data/sparse_anim.gltf");
+    /// Two characters with own skins and animations
+    const TWO_SKINS_GLTF: &[u8] = include_bytes!("../tests/data/two_skins.gltf");
+
+    #[test]
+    fn skinned_anim_fixture_converts_to_expected_skeleton_and_keys() {
+        let (doc, ctx) = load_test_gltf(SKINNED_ANIM_GLTF, &[]).unwrap();
+        let skin = doc.skins().next().unwrap();
+        let joints: Vec<_> = skin.joints().collect();
+        let anim = doc.animations().next().unwrap();
+
+        let bones = convert_bones(&skin, &ConversionConfig::default(), &ctx).unwrap();
+        let names: Vec<_> = bones.iter().map(|b| b.name.as_str()).collect();
+        let parents: Vec<_> = bones.iter().map(|b| b.parent_index).collect();
+        assert_eq!(names, ["root", "bone1"]);
+        assert_eq!(parents, [-1, 0]);
+        // base translation is the inverse bind translation of the joint (bone1 is 1 unit above the root)
+        assert_eq!(bones[1].base_translation[1], -1.0);
+
+        let rfa = make_rfa(&anim, &joints, None, &ConversionConfig::default(), &ctx).unwrap();
+        rfa.validate().unwrap();
+        assert_eq!(rfa.header.num_bones, 2);
+        assert_eq!(rfa.header.start_time, RfaTime::ZERO);
+        assert_eq!(rfa.header.end_time, RfaTime(4800));
+        assert_eq!(rfa.header.ramp_in_time, RfaTime(480));
+        assert_eq!(rfa.header.ramp_out_time, RfaTime(480));
+        assert_eq!(rfa.header.total_translation, [0.0, 1.0, 0.0]);
+        let key_times = |bone: &rfa::Bone| -> (Vec<i32>, Vec<i32>) {
+            (
+                bone.rotation_keys.iter().map(|k| k.time.ticks()).collect(),
+                bone.translation_keys
+                    .iter()
+                    .map(|k| k.time.ticks())
+                    .collect(),
+            )
+        };
+        // root is only translated and bone1 is only rotated, keys are at 0, 0.5 and 1 s
+        assert_eq!(key_times(&rfa.bones[0]), (vec![], vec![0, 2400, 4800]));
+        assert_eq!(key_times(&rfa.bones[1]), (vec![0, 2400, 4800], vec![]));
+    }
+
+    #[test]
+    fn rfa_round_trip_preserves_keys() {
+        let (doc, ctx) = load_test_gltf(SKINNED_ANIM_GLTF, &[]).unwrap();
+        let skin = doc.skins().next().unwrap();
+        let joints: Vec<_> = skin.joints().collect();
+        let anim = doc.animations().next().unwrap();
+        let rfa = make_rfa(&anim, &joints, None, &ConversionConfig::default(), &ctx).unwrap();
+        let parsed = rfa::File::read(&mut Cursor::new(
+            rfa.to_bytes(binrw::Endian::Little).unwrap(),
+        ))
+        .unwrap();
+        let parsed_be =
+            rfa::File::read(&mut Cursor::new(rfa.to_bytes(binrw::Endian::Big).unwrap())).unwrap();
+        assert_eq!(
+            parsed_be.to_bytes(binrw::Endian::Little).unwrap(),
+            rfa.to_bytes(binrw::Endian::Little).unwrap()
+        );
+
+        assert_eq!(parsed.header.start_time.ticks(), 0);
+        assert_eq!(parsed.header.end_time.ticks(), rfa::RFA_TICKS_PER_SECOND);
+        assert_eq!(parsed.bones.len(), 2);
+
+        // root joint is translated along Y axis
+        let root_times: Vec<_> = parsed.bones[0]
+            .translation_keys
+            .iter()
+            .map(|k| k.time.ticks())
+            .collect();
+        assert_eq!(root_times, [0, 2400, 4800]);
+        for (key, y) in parsed.bones[0].translation_keys.iter().zip([0.0, 0.5, 1.0]) {
+            assert!(
+                (glam::Vec3::from(key.translation) - glam::Vec3::new(0.0, y, 0.0)).length()
+                    < 0.0001
+            );
+        }
+
+        // bone1 is rotated around Y axis (RF quaternions have negated X so Y rotation is unchanged)
+        let bone_times: Vec<_> = parsed.bones[1]
+            .rotation_keys
+            .iter()
+            .map(|k| k.time.ticks())
+            .collect();
+        assert_eq!(bone_times, [0, 2400, 4800]);
+        for (key, half_angle) in parsed.bones[1]
+            .rotation_keys
+            .iter()
+            .zip([0.0_f32, 0.4, 0.8])
+        {
+            let expected = [0.0, half_angle.sin(), 0.0, half_angle.cos()];
+            for (actual, expected) in key.rotation.0.iter().zip(expected) {
+                assert!((*actual as f32 / rfa::RFA_QUAT_SCALE - expected).abs() < 0.001);
+            }
+        }
+    }
+
+    #[test]
+    fn trim_interpolates_boundaries_and_rebases_keys() {
+        let (doc, ctx) = load_test_gltf(SKINNED_ANIM_GLTF, &[]).unwrap();
+        let skin = doc.skins().next().unwrap();
+        let joints: Vec<_> = skin.joints().collect();
+        let anim = doc.animations().next().unwrap();
+        let trim = |start: f32, end: f32| {
+            let config = ConversionConfig {
+                trim: Some((start, end)),
+                ..ConversionConfig::default()
+            };
+            make_rfa(&anim, &joints, None, &config, &ctx).unwrap()
+        };
+
+        let rfa = trim(0.25, 0.75);
+        assert_eq!(rfa.header.start_time.ticks(), 0);
+        assert_eq!(rfa.header.end_time.ticks(), rfa::RFA_TICKS_PER_SECOND / 2);
+        let root_keys: Vec<_> = rfa.bones[0]
+            .translation_keys
+            .iter()
+            .map(|k| (k.time.ticks(), k.translation[1]))
+            .collect();
+        assert_eq!(root_keys, [(0, 0.25), (1200, 0.5), (2400, 0.75)]);
+        let rotation = rfa.bones[1].rotation_keys[0].rotation;
+        let expected = [0.0, 0.2_f32.sin(), 0.0, 0.2_f32.cos()];
+        for (actual, expected) in rotation.0.iter().zip(expected) {
+            assert!((*actual as f32 / rfa::RFA_QUAT_SCALE - expected).abs() < 0.001);
+        }
+
+        // both ends inside one segment
+        let rfa = trim(0.1, 0.2);
+        let root_keys = &rfa.bones[0].translation_keys;
+        assert_eq!(root_keys.len(), 2);
+        assert_eq!(root_keys[1].time.ticks(), 480);
+        assert!((root_keys[0].translation[1] - 0.1).abs() < 0.0001);
+        assert!((root_keys[1].translation[1] - 0.2).abs() < 0.0001);
+
+        let config = ConversionConfig {
+            trim: Some((1.0, 0.5)),
+            ..ConversionConfig::default()
+        };
+        assert!(make_rfa(&anim, &joints, None, &config, &ctx).is_err());
+    }
+
+    #[test]
+    fn ramp_times_together_do_not_exceed_duration() {
+        let (doc, ctx) = load_test_gltf(SKINNED_ANIM_GLTF, &[]).unwrap();
+        let skin = doc.skins().next().unwrap();
+        let joints: Vec<_> = skin.joints().collect();
+        let anim = doc.animations().next().unwrap();
+        // animation is 1 s long
+        let config = ConversionConfig {
+            ramp_in_time: Some(0.75),
+            ramp_out_time: Some(0.5),
+            ..ConversionConfig::default()
+        };
+        let rfa = make_rfa(&anim, &joints, None, &config, &ctx).unwrap();
+        rfa.validate().unwrap();
+        assert_eq!(rfa.header.ramp_in_time, RfaTime(3600));
+        assert_eq!(rfa.header.ramp_out_time, RfaTime(1200));
+    }
+
+    #[test]
+    fn rfa_summary_shows_times_in_seconds() {
+        let (doc, ctx) = load_test_gltf(SKINNED_ANIM_GLTF, &[]).unwrap();
+        let skin = doc.skins().next().unwrap();
+        let joints: Vec<_> = skin.joints().collect();
+        let anim = doc.animations().next().unwrap();
+        let rfa = make_rfa(&anim, &joints, None, &ConversionConfig::default(), &ctx).unwrap();
+        let summary = rfa.summary(DEFAULT_FPS);
+        assert!(summary.contains("End time: 4800 ticks (1.000 s at 30 FPS)"));
+        assert!(summary.contains("Bones: 2"));
+        assert!(summary.contains("Bone 1: weight 10, 3 rotation keys, 0 translation keys"));
+    }
+
+    #[test]
+    fn attach_points_are_read_from_joint_extras() {
+        let gltf = String::from_utf8(SKINNED_ANIM_GLTF.to_vec()).unwrap();
+        let gltf = gltf
+            .replace(
+                r#""name": "root","#,
+                r#""name": "root", "extras": {"rf_attach_point": false, "note": 1},"#,
+            )
+            .replace(
+                r#""name": "bone1","#,
+                r#""name": "bone1", "extras": {"rf_attach_point": "weapon"},"#,
+            );
+        let (doc, ctx) = load_test_gltf(gltf.as_bytes(), &[]).unwrap();
+        let skin = doc.skins().next().unwrap();
+        let attach_points: Vec<_> = get_joint_attach_points(&skin, &ctx.bone_map).collect();
+        assert_eq!(attach_points.len(), 1);
+        let (name, parent_index, transform) = &attach_points[0];
+        assert_eq!(name, "weapon");
+        assert_eq!(*parent_index, 1);
+        assert_eq!(*transform, glam::Mat4::IDENTITY);
+    }
+
+    #[test]
+    fn animations_of_another_skin_are_detected() {
+        let (doc, ctx) = load_test_gltf(TWO_SKINS_GLTF, &[]).unwrap();
+        let skin = doc.skins().next().unwrap();
+        let joints: Vec<_> = skin.joints().collect();
+        let walk = doc.animations().next().unwrap();
+        let wave = doc.animations().nth(1).unwrap();
+        assert!(find_other_animated_skin(&walk, &doc, &joints).is_none());
+        let other_skin = find_other_animated_skin(&wave, &doc, &joints).unwrap();
+        assert_eq!(other_skin.index(), 1);
+
+        // channels of the other skin are never mapped to bones of the converted skin
+        let config = ConversionConfig::default();
+        let rfa = make_rfa(&walk, &joints, None, &config, &ctx).unwrap();
+        assert_eq!(rfa.bones[1].rotation_keys.len(), 3);
+        let err = make_rfa(&wave, &joints, None, &config, &ctx).err().unwrap();
+        assert!(err
+            .to_string()
+            .contains("has no keys for any exported joint"));
+    }
+
+    #[test]
+    fn scale_multiplies_bone_and_key_translations() {
+        let (doc, ctx) = load_test_gltf(SKINNED_ANIM_GLTF, &[]).unwrap();
+        let skin = doc.skins().next().unwrap();
+        let joints: Vec<_> = skin.joints().collect();
+        let anim = doc.animations().next().unwrap();
+        let unscaled_config = ConversionConfig::default();
+        let unscaled_bones = convert_bones(&skin, &unscaled_config, &ctx).unwrap();
+        let unscaled = make_rfa(&anim, &joints, None, &unscaled_config, &ctx).unwrap();
+
+        let config = ConversionConfig {
+            scale: 2.0,
+            ..ConversionConfig::default()
+        };
+        let bones = convert_bones(&skin, &config, &ctx).unwrap();
+        let rfa = make_rfa(&anim, &joints, None, &config, &ctx).unwrap();
+        for (bone, unscaled_bone) in bones.iter().zip(&unscaled_bones) {
+            let expected = glam::Vec3::from(unscaled_bone.base_translation) * 2.0;
+            assert_eq!(glam::Vec3::from(bone.base_translation), expected);
+            assert_eq!(bone.base_rotation, unscaled_bone.base_rotation);
+        }
+        let keys = &rfa.bones[0].translation_keys;
+        let unscaled_keys = &unscaled.bones[0].translation_keys;
+        for (key, unscaled_key) in keys.iter().zip(unscaled_keys) {
+            let expected = glam::Vec3::from(unscaled_key.translation) * 2.0;
+            assert_eq!(glam::Vec3::from(key.translation), expected);
+        }
+        assert_eq!(rfa.header.total_translation[1], 2.0);
+        assert_eq!(
+            rfa.bones[1].rotation_keys[2].rotation,
+            unscaled.bones[1].rotation_keys[2].rotation
+        );
+    }
+
+    #[test]
+    fn sampler_with_fewer_outputs_than_inputs_is_rejected() {
+        let mut gltf: Value = serde_json::from_slice(SKINNED_ANIM_GLTF).unwrap();
+        // 3 key times but only 2 rotations of bone1
+        gltf["accessors"][7]["count"] = 2.into();
+        let (doc, ctx) = load_test_gltf(&serde_json::to_vec(&gltf).unwrap(), &[]).unwrap();
+        let skin = doc.skins().next().unwrap();
+        let joints: Vec<_> = skin.joints().collect();
+        let anim = doc.animations().next().unwrap();
+        // rfa::File is not Debug so unwrap_err cannot be used
+        let message = make_rfa(&anim, &joints, None, &ConversionConfig::default(), &ctx)
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(message.contains("linear sampler"), "{}", message);
+        assert!(message.contains("'walk'"), "{}", message);
+        assert!(message.contains("'bone1'"), "{}", message);
+        assert!(message.contains("2 outputs for 3 inputs"), "{}", message);
+    }
+
+    #[test]
+    fn validate_skin_reports_bone_and_animation_problems_at_once() {
+        let mut gltf: Value = serde_json::from_slice(SKINNED_ANIM_GLTF).unwrap();
+        // 2 joints but only 1 inverse bind matrix
+        gltf["accessors"][5]["count"] = 1.into();
+        // 3 key times but only 2 rotations of bone1
+        gltf["accessors"][7]["count"] = 2.into();
+        let (doc, ctx) = load_test_gltf(&serde_json::to_vec(&gltf).unwrap(), &[]).unwrap();
+        let skin = doc.skins().next().unwrap();
+        let jobs = select_rfa_jobs(&doc, &skin, &[], &ctx).unwrap();
+        let issues = validate_skin(&skin, &jobs, &ConversionConfig::default(), &ctx);
+        assert_eq!(issues.len(), 2, "{:?}", issues);
+        assert!(issues[0].contains("inverse bind matri"), "{}", issues[0]);
+        assert!(
+            issues[1].contains("2 outputs for 3 inputs"),
+            "{}",
+            issues[1]
+        );
+    }
+
+    #[test]
+    fn validate_skin_reports_key_outside_of_rfa_time_range() {
+        let (doc, mut ctx) = load_test_gltf(SKINNED_ANIM_GLTF, &[]).unwrap();
+        // last key time of the animation (buffer view 6)
+        ctx.buffers[0].0[324..328].copy_from_slice(&1e6_f32.to_le_bytes());
+        let skin = doc.skins().next().unwrap();
+        let jobs = select_rfa_jobs(&doc, &skin, &[], &ctx).unwrap();
+        let issues = validate_skin(&skin, &jobs, &ConversionConfig::default(), &ctx);
+        assert_eq!(issues.len(), 1, "{:?}", issues);
+        assert!(
+            issues[0].contains("outside of RFA time range"),
+            "{}",
+            issues[0]
+        );
+    }
+
+    #[test]
+    fn linear_ease_preset_removes_derived_easing() {
+        let make_bones = || {
+            vec![rfa::Bone {
+                weight: 1.0,
+                rotation_keys: vec![rfa::RotationKey {
+                    time: RfaTime(0),
+                    rotation: rfa::ShortQuat([0, 0, 0, 16383]),
+                    ease_in: 10,
+                    ease_out: 20,
+                }],
+                translation_keys: vec![rfa::TranslationKey {
+                    time: RfaTime(0),
+                    in_tangent: [-1.0, 0.0, 0.0],
+                    translation: [0.0; 3],
+                    out_tangent: [1.0, 0.0, 0.0],
+                }],
+            }]
+        };
+        let mut bones = make_bones();
+        apply_ease_preset(&mut bones, EasePreset::Source);
+        assert_eq!(bones[0].rotation_keys[0].ease_in, 10);
+        assert_eq!(bones[0].translation_keys[0].out_tangent, [1.0, 0.0, 0.0]);
+
+        let mut bones = make_bones();
+        apply_ease_preset(&mut bones, EasePreset::Linear);
+        let rotation_key = &bones[0].rotation_keys[0];
+        assert_eq!((rotation_key.ease_in, rotation_key.ease_out), (0, 0));
+        assert!(is_linear_translation_key(&bones[0].translation_keys[0]));
+    }
+
+    #[test]
+    fn single_pose_animation_is_extended_to_one_frame() {
+        let mut gltf: Value = serde_json::from_slice(SKINNED_ANIM_GLTF).unwrap();
+        // keep only the first key of every channel
+        for accessor in &mut gltf["accessors"].as_array_mut().unwrap()[6..=8] {
+            accessor["count"] = 1.into();
+            accessor.as_object_mut().unwrap().remove("max");
+        }
+        let (doc, ctx) = load_test_gltf(&serde_json::to_vec(&gltf).unwrap(), &[]).unwrap();
+        let skin = doc.skins().next().unwrap();
+        let joints: Vec<_> = skin.joints().collect();
+        let anim = doc.animations().next().unwrap();
+        let rfa = make_rfa(&anim, &joints, None, &ConversionConfig::default(), &ctx).unwrap();
+        rfa.validate().unwrap();
+        assert_eq!(rfa.header.start_time.ticks(), 0);
+        assert_eq!(rfa.header.end_time.ticks(), rfa::RFA_TICKS_PER_FRAME);
+        let times: Vec<_> = rfa.bones[1]
+            .rotation_keys
+            .iter()
+            .map(|k| k.time.ticks())
+            .collect();
+        assert_eq!(times, [0, rfa::RFA_TICKS_PER_FRAME]);
+        assert_eq!(
+            rfa.bones[1].rotation_keys[0].rotation,
+            rfa.bones[1].rotation_keys[1].rotation
+        );
+    }
+
+    #[test]
+    fn quantization_rounds_keys_and_removes_duplicates() {
+        let (doc, ctx) = load_test_gltf(SKINNED_ANIM_GLTF, &[]).unwrap();
+        let anim = doc.animations().next().unwrap();
+        let rotation_key = |time, rotation| rfa::RotationKey {
+            time: RfaTime(time),
+            rotation: ShortQuat(rotation),
+            ease_in: 0,
+            ease_out: 0,
+        };
+        let translation_key = |time, translation| rfa::TranslationKey {
+            time: RfaTime(time),
+            translation,
+            in_tangent: translation,
+            out_tangent: translation,
+        };
+        let mut bones = vec![rfa::Bone {
+            weight: 10.0,
+            rotation_keys: vec![
+                rotation_key(0, [0, 100, -200, 16383]),
+                rotation_key(160, [0, 120, -190, 16383]),
+                rotation_key(320, [0, 110, -180, 16383]),
+            ],
+            translation_keys: vec![
+                translation_key(0, [0.1, 0.0, 0.0]),
+                translation_key(160, [0.2, 0.0, 0.0]),
+                translation_key(320, [1.4, 0.0, 0.0]),
+            ],
+        }];
+        let config = ConversionConfig {
+            quantize_translation: Some(0.5),
+            rotation_bits: Some(8),
+            ..ConversionConfig::default()
+        };
+        quantize_bone_keys(&mut bones, &anim, &config, &ctx).unwrap();
+
+        let rotations: Vec<_> = bones[0]
+            .rotation_keys
+            .iter()
+            .map(|k| k.rotation.0)
+            .collect();
+        assert_eq!(rotations, [[0, 0, -256, 16381], [0, 0, -256, 16381]]);
+        let translations: Vec<_> = bones[0]
+            .translation_keys
+            .iter()
+            .map(|k| k.translation)
+            .collect();
+        assert_eq!(
+            translations,
+            [[0.0, 0.0, 0.0], [0.0, 0.0, 0.0], [1.5, 0.0, 0.0]]
+        );
+        assert_eq!(bones[0].size(), 8 + 2 * 16 + 3 * 40);
+
+        let config = ConversionConfig {
+            quantize_translation: Some(0.0),
+            ..ConversionConfig::default()
+        };
+        assert!(quantize_bone_keys(&mut bones, &anim, &config, &ctx).is_err());
+        // clap checks the range of --rotation-bits but the config can be built without it
+        for bits in [0, 1, 17] {
+            let config = ConversionConfig {
+                rotation_bits: Some(bits),
+                ..ConversionConfig::default()
+            };
+            assert!(quantize_bone_keys(&mut bones, &anim, &config, &ctx).is_err());
+        }
+    }
+
+    #[test]
+    fn baking_cannot_exceed_rfa_key_count() {
+        let times = get_bake_times(&[0.0, 1.0], 30.0).unwrap();
+        assert_eq!(times.len(), 31);
+        assert_eq!(times.last(), Some(&1.0));
+        // 10 s at 4000 FPS gives 40001 keys
+        let err = get_bake_times(&[0.0, 10.0], 4000.0).err().unwrap();
+        assert!(err.to_string().contains("at most 32767"), "{}", err);
+    }
+
+    #[test]
+    fn anim_name_prefix_is_stripped_from_file_names() {
+        let (_, mut ctx) = load_test_gltf(SKINNED_ANIM_GLTF, &[]).unwrap();
+        assert_eq!(
+            determine_rfa_file_name("Armature|walk", &ctx),
+            "Armature_walk.rfa"
+        );
+        ctx.args = Args::parse_from([
+            "vmesh",
+            "test.gltf",
+            "--strip-anim-prefix",
+            "--rename-anim",
+            "Armature|run=sprint",
+        ]);
+        assert_eq!(determine_rfa_file_name("Armature|walk", &ctx), "walk.rfa");
+        assert_eq!(determine_rfa_file_name("Armature|run", &ctx), "sprint.rfa");
+        assert_eq!(determine_rfa_file_name("Rig|a:b", &ctx), "Rig_a_b.rfa");
+        assert_eq!(determine_rfa_file_name("Armature|", &ctx), "Armature_.rfa");
+        ctx.args = Args::parse_from([
+            "vmesh",
+            "test.gltf",
+            "--strip-anim-prefix",
+            "--anim-prefix",
+            "Rig|",
+        ]);
+        assert_eq!(determine_rfa_file_name("Rig|a:b", &ctx), "a_b.rfa");
+    }
+
+    #[test]
+    fn colliding_rfa_file_names_are_made_unique() {
+        let mut gltf: Value = serde_json::from_slice(SKINNED_ANIM_GLTF).unwrap();
+        let anim = gltf["animations"][0].clone();
+        gltf["animations"] = vec![anim.clone(), anim.clone(), anim].into();
+        gltf["animations"][0]["name"] = "walk_2".into();
+        let (doc, ctx) = load_test_gltf(&serde_json::to_vec(&gltf).unwrap(), &[]).unwrap();
+        let skin = doc.skins().next().unwrap();
+        let jobs = select_rfa_jobs(&doc, &skin, &[], &ctx).unwrap();
+        let file_names: Vec<_> = jobs.iter().map(|j| j.rfa_file_name.as_str()).collect();
+        // the fallback name of the last animation is taken by the first one
+        assert_eq!(file_names, ["walk_2.rfa", "walk.rfa", "walk_2_1.rfa"]);
+    }
+
+    /// Runs all conversions of the first skin and its animations
+    fn convert_gltf_slice(data: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        let (document, ctx) = load_test_gltf(data, &[])?;
+        let skin = document.skins().next().ok_or("no skin")?;
+        crate::v3mc_convert::convert_gltf_to_v3mc(&document, &ctx)?;
+        let joints: Vec<_> = skin.joints().collect();
+        for anim in document.animations() {
+            make_rfa(&anim, &joints, None, &ConversionConfig::default(), &ctx)?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn malformed_gltf_returns_error() {
+        assert!(convert_gltf_slice(SKINNED_ANIM_GLTF).is_ok());
+        let root: Value = serde_json::from_slice(SKINNED_ANIM_GLTF).unwrap();
+        let broken_values = [
+            // positions with integer components
+            ("/accessors/0/componentType", Value::from(5123)),
+            ("/accessors/0/count", Value::from(0)),
+            // joints and indices with float components
+            ("/accessors/2/componentType", Value::from(5126)),
+            ("/accessors/4/componentType", Value::from(5126)),
+            ("/accessors/5/type", Value::from("VEC4")),
+            // rotation channel using translation keys
+            ("/animations/0/channels/0/sampler", Value::from(1)),
+            ("/animations/0/channels/0/target/node", Value::from(3)),
+            (
+                "/meshes/0/primitives/0/attributes/POSITION",
+                Value::from(100000),
+            ),
+            ("/meshes/0/primitives/0/attributes/NORMAL", Value::Null),
+        ];
+        for (pointer, value) in broken_values {
+            let mut broken = root.clone();
+            if value.is_null() {
+                let (parent, key) = pointer.rsplit_once('/').unwrap();
+                broken
+                    .pointer_mut(parent)
+                    .unwrap()
+                    .as_object_mut()
+                    .unwrap()
+                    .remove(key);
+            } else {
+                *broken.pointer_mut(pointer).unwrap() = value;
+            }
+            let data = serde_json::to_vec(&broken).unwrap();
+            assert!(
+                convert_gltf_slice(&data).is_err(),
+                "{} was accepted",
+                pointer
+            );
+        }
+    }
+
+    #[test]
+    fn looping_anim_poses_are_compared() {
+        let (doc, _ctx) = load_test_gltf(SKINNED_ANIM_GLTF, &[]).unwrap();
+        let joints: Vec<_> = doc.skins().next().unwrap().joints().collect();
+        let anim = doc.animations().next().unwrap();
+        let mut config = ConversionConfig::default();
+        assert!(!is_looping_anim(&anim, &get_anim_extras(&anim), &config));
+        config.loop_anims = vec!["wa*".to_owned()];
+        assert!(is_looping_anim(&anim, &get_anim_extras(&anim), &config));
+
+        let make_bone = |rotations: &[[i16; 4]], translations: &[[f32; 3]]| rfa::Bone {
+            weight: 10.0,
+            rotation_keys: rotations
+                .iter()
+                .enumerate()
+                .map(|(i, &rotation)| rfa::RotationKey {
+                    time: RfaTime(i as i32 * 2400),
+                    rotation: ShortQuat(rotation),
+                    ease_in: 0,
+                    ease_out: 0,
+                })
+                .collect(),
+            translation_keys: translations
+                .iter()
+                .enumerate()
+                .map(|(i, &translation)| rfa::TranslationKey {
+                    time: RfaTime(i as i32 * 2400),
+                    translation,
+                    in_tangent: translation,
+                    out_tangent: translation,
+                })
+                .collect(),
+        };
+        const IDENTITY: [i16; 4] = [0, 0, 0, 16383];
+        const TURN: [i16; 4] = [0, 11585, 0, 11585];
+        // root motion is allowed to differ
+        let root = || make_bone(&[IDENTITY, IDENTITY], &[[0.0; 3], [1.0, 0.0, 0.0]]);
+        let looped = make_bone(&[IDENTITY, TURN, IDENTITY], &[[0.0; 3], [0.005, 0.0, 0.0]]);
+        let popping = make_bone(&[IDENTITY, TURN], &[[0.0; 3], [0.0, 0.5, 0.0]]);
+        assert!(find_loop_pose_mismatches(&[root(), looped], &joints, Some(0)).is_empty());
+        assert_eq!(
+            find_loop_pose_mismatches(&[root(), popping], &joints, Some(0)),
+            ["bone1 (90.0 deg, 0.500 units)"]
+        );
+    }
+
+    #[test]
+    fn sparse_accessors_are_resolved() {
+        let (doc, ctx) = load_test_gltf(SPARSE_ANIM_GLTF, &[]).unwrap();
+        let skin = doc.skins().next().unwrap();
+        let joints: Vec<_> = skin.joints().collect();
+        let anim = doc.animations().next().unwrap();
+        let rfa = make_rfa(&anim, &joints, None, &ConversionConfig::default(), &ctx).unwrap();
+
+        // translation accessor has no buffer view so keys not present in the sparse storage are zero
+        let root_ys: Vec<_> = rfa.bones[0]
+            .translation_keys
+            .iter()
+            .map(|k| k.translation[1])
+            .collect();
+        assert_eq!(root_ys, [0.0, 0.5, 2.0]);
+
+        // middle rotation key is replaced by identity
+        let bone_rotations: Vec<_> = rfa.bones[1]
+            .rotation_keys
+            .iter()
+            .map(|k| k.rotation.0)
+            .collect();
+        assert_eq!(bone_rotations.len(), 3);
+        assert_eq!(bone_rotations[1], [0, 0, 0, rfa::RFA_QUAT_SCALE as i16]);
+        assert_ne!(bone_rotations[2], bone_rotations[1]);
+
+        let prim = doc.meshes().next().unwrap().primitives().next().unwrap();
+        let reader = prim.reader(|buffer| ctx.get_buffer_data(buffer));
+        let positions: Vec<_> = reader.read_positions().unwrap().collect();
+        assert_eq!(positions[0], [0.0, 0.0, 0.0]);
+        assert_eq!(positions[1], [1.0, 0.0, 2.0]);
+    }
+
+    #[test]
+    fn rfa_validate_reports_header_inconsistencies() {
+        let (doc, ctx) = load_test_gltf(SKINNED_ANIM_GLTF, &[]).unwrap();
+        let skin = doc.skins().next().unwrap();
+        let joints: Vec<_> = skin.joints().collect();
+        let anim = doc.animations().next().unwrap();
+        let mut rfa = make_rfa(&anim, &joints, None, &ConversionConfig::default(), &ctx).unwrap();
+        rfa.validate().unwrap();
+
+        rfa.header.end_time = RfaTime(2400);
+        rfa.header.ramp_in_time = RfaTime(2000);
+        rfa.header.ramp_out_time = RfaTime(2000);
+        rfa.header.num_bones = 3;
+        let err = rfa.validate().unwrap_err().to_string();
+        assert!(err.contains("declares 3 bones"));
+        assert!(err.contains("exceed duration"));
+        assert!(err.contains("outside of time range"));
+    }
+
+    #[test]
+    fn prepend_bind_pose_inserts_keys_only_when_needed() {
+        let (doc, ctx) = load_test_gltf(SKINNED_ANIM_GLTF, &[]).unwrap();
+        let skin = doc.skins().next().unwrap();
+        let joints: Vec<_> = skin.joints().collect();
+        let anim = doc.animations().next().unwrap();
+        let config = ConversionConfig::default();
+        let rfa = make_rfa(&anim, &joints, None, &config, &ctx).unwrap();
+
+        // the test animation starts in the rest pose
+        let start_pose: Vec<_> = rfa
+            .bones
+            .iter()
+            .map(|b| BonePose {
+                rotation: b
+                    .rotation_keys
+                    .first()
+                    .map_or(glam::Quat::IDENTITY, |k| k.rotation.to_quat()),
+                translation: b
+                    .translation_keys
+                    .first()
+                    .map_or(glam::Vec3::ZERO, |k| k.translation.into()),
+            })
+            .collect();
+        let unchanged = make_rfa(&anim, &joints, Some(&start_pose), &config, &ctx).unwrap();
+        assert_eq!(unchanged.header.end_time, rfa.header.end_time);
+        assert_eq!(
+            unchanged.bones[1].rotation_keys.len(),
+            rfa.bones[1].rotation_keys.len()
+        );
+
+        let mut bind_pose = start_pose.clone();
+        bind_pose[1].rotation = glam::Quat::from_rotation_x(0.5);
+        let prepended = make_rfa(&anim, &joints, Some(&bind_pose), &config, &ctx).unwrap();
+        let delay = RfaTime(rfa::RFA_TICKS_PER_FRAME);
+        assert_eq!(prepended.header.start_time, rfa.header.start_time);
+        assert_eq!(prepended.header.end_time, rfa.header.end_time + delay);
+        let bone = &prepended.bones[1];
+        assert_eq!(
+            bone.rotation_keys.len(),
+            rfa.bones[1].rotation_keys.len() + 1
+        );
+        assert_eq!(bone.rotation_keys[0].time, rfa.header.start_time);
+        assert_eq!(
+            bone.rotation_keys[1].time,
+            rfa.bones[1].rotation_keys[0].time + delay
+        );
+        assert!(bind_pose[1].matches_rotation_key(&bone.rotation_keys[0]));
+    }
+
+    #[test]
+    fn collapse_static_translations_keeps_root_and_moving_bones() {
+        let (doc, ctx) = load_test_gltf(SKINNED_ANIM_GLTF, &[]).unwrap();
+        let anim = doc.animations().next().unwrap();
+        let make_bone = |ys: &[f32]| rfa::Bone {
+            weight: 10.0,
+            rotation_keys: Vec::new(),
+            translation_keys: ys
+                .iter()
+                .enumerate()
+                .map(|(i, &y)| rfa::TranslationKey {
+                    time: RfaTime(i as i32 * 160),
+                    translation: [0.0, y, 0.0],
+                    in_tangent: [0.0, y, 0.0],
+                    out_tangent: [0.0, y, 0.0],
+                })
+                .collect(),
+        };
+        let mut bones = vec![
+            make_bone(&[0.0, 0.0, 0.0]),
+            make_bone(&[1.0, 1.005, 0.995]),
+            make_bone(&[1.0, 1.5, 2.0]),
+        ];
+        let config = ConversionConfig {
+            collapse_translation_tolerance: Some(0.01),
+            ..ConversionConfig::default()
+        };
+        collapse_static_translations(&mut bones, Some(0), &anim, &config, &ctx);
+        let num_keys: Vec<_> = bones.iter().map(|b| b.translation_keys.len()).collect();
+        assert_eq!(num_keys, [3, 1, 3]);
+        assert_eq!(bones[1].translation_keys[0].translation, [0.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn anim_stats_count_keys_per_bone() {
+        let (doc, ctx) = load_test_gltf(SKINNED_ANIM_GLTF, &[]).unwrap();
+        let skin = doc.skins().next().unwrap();
+        let joints: Vec<_> = skin.joints().collect();
+        let anim = doc.animations().next().unwrap();
+        let rfa = make_rfa(&anim, &joints, None, &ConversionConfig::default(), &ctx).unwrap();
+        let names = ["root".to_owned(), "bone1".to_owned()];
+        let stats = AnimStats::new(&rfa, &names);
+
+        assert_eq!(stats.bones.len(), 2);
+        assert_eq!(stats.bones[0].num_translation_keys, 3);
+        assert_eq!(stats.bones[1].num_rotation_keys, 3);
+        assert_eq!(
+            stats.bones[1].time_span,
+            Some((RfaTime::ZERO, RfaTime(4800)))
+        );
+        let total_rotation_keys: usize = rfa.bones.iter().map(|b| b.rotation_keys.len()).sum();
+        assert_eq!(stats.num_rotation_keys, total_rotation_keys);
+        assert!(stats.format_table("test").contains("bone1"));
+    }
+
+    fn make_rotation_key(time: i32, x: i16) -> rfa::RotationKey {
+        rfa::RotationKey {
+            time: RfaTime(time),
+            rotation: ShortQuat([x, 0, 0, 16383]),
+            ease_in: 0,
+            ease_out: 0,
+        }
+    }
+
+    #[test]
+    fn sort_and_dedup_keys_orders_keys_and_keeps_last_duplicate() {
+        let keys = vec![
+            make_rotation_key(960, 3),
+            make_rotation_key(0, 1),
+            make_rotation_key(480, 2),
+            make_rotation_key(960, 4),
+            make_rotation_key(0, 5),
+        ];
+        let keys = sort_and_dedup_keys(keys, |k| k.time);
+        let times: Vec<_> = keys.iter().map(|k| k.time.ticks()).collect();
+        let xs: Vec<_> = keys.iter().map(|k| k.rotation.0[0]).collect();
+        assert_eq!(times, [0, 480, 960]);
+        assert_eq!(xs, [5, 2, 4]);
+    }
+
+    #[test]
+    fn check_bone_shear_detects_sheared_matrix() {
+        let transform = glam::Mat4::from_rotation_translation(
+            glam::Quat::from_rotation_y(0.5),
+            glam::Vec3::new(1.0, 2.0, 3.0),
+        );
+        let (scale, rotation, _) = transform.to_scale_rotation_translation();
+        assert!(check_bone_shear("bone", &transform, scale, rotation).is_ok());
+
+        let mut sheared = transform;
+        sheared.y_axis.x += 0.5;
+        let (scale, rotation, _) = sheared.to_scale_rotation_translation();
+        let err = check_bone_shear("bone", &sheared, scale, rotation).unwrap_err();
+        assert!(err.to_string().contains("bone bone"));
+    }
 }
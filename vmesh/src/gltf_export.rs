@@ -1,27 +1,641 @@
+use crate::io_utils::{add_path_to_error, new_custom_error};
+use crate::material::{get_alpha_mode_for_render_mode, is_render_mode_clamped, MaterialExtras};
 use crate::v3mc;
+use crate::{rf_to_gltf_face, rf_to_gltf_quat, rf_to_gltf_vec};
 
+use gltf::json;
+use gltf::json::validation::Checked::Valid;
+use gltf::json::validation::USize64;
+use serde_json::value::RawValue;
+use std::collections::HashMap;
+use std::error::Error;
 use std::fs;
 use std::io::Cursor;
-use std::error::Error;
 use std::path::Path;
-use std::path::PathBuf;
-
-use binrw::BinReaderExt;
-use binrw::{
-    binrw,    // #[binrw] attribute
-    BinRead,  // trait for reading
-    BinWrite, // trait for writing
-};
 
-pub fn parse_vmesh(vmesh_path:&Path) -> Result<(), Box<dyn Error>> {
-    let v3c_contents: Vec<u8> = fs::read(vmesh_path)?;
-    println!("Size: {}",v3c_contents.len());
+/// Checks signature and version in the file header so other files are rejected before their content is
+/// interpreted as meshes. Both byte orders are accepted.
+fn check_vmesh_header(data: &[u8]) -> std::io::Result<()> {
+    let Some(header) = data.get(..8) else {
+        return Err(new_custom_error(format!(
+            "file is too small to be a V3M/V3C file ({} bytes)",
+            data.len()
+        )));
+    };
+    let signature_bytes: [u8; 4] = header[..4].try_into().unwrap();
+    let version_bytes: [u8; 4] = header[4..].try_into().unwrap();
+    let signatures = [v3mc::V3M_SIGNATURE, v3mc::V3C_SIGNATURE];
+    let version = if signatures.contains(&u32::from_le_bytes(signature_bytes)) {
+        u32::from_le_bytes(version_bytes)
+    } else if signatures.contains(&u32::from_be_bytes(signature_bytes)) {
+        u32::from_be_bytes(version_bytes)
+    } else {
+        return Err(new_custom_error(format!(
+            "not a V3M/V3C file: expected signature RF3D or RFCM, found bytes {:02X?} ({:?})",
+            signature_bytes,
+            String::from_utf8_lossy(&signature_bytes)
+        )));
+    };
+    if version != v3mc::VERSION {
+        return Err(new_custom_error(format!(
+            "unsupported V3M/V3C version {:#x} (expected {:#x}), found bytes {:02X?}",
+            version,
+            v3mc::VERSION,
+            version_bytes
+        )));
+    }
+    Ok(())
+}
 
+pub fn parse_vmesh(vmesh_path: &Path) -> Result<v3mc::File, Box<dyn Error>> {
+    let v3c_contents: Vec<u8> =
+        fs::read(vmesh_path).map_err(|e| add_path_to_error(e, vmesh_path))?;
+    check_vmesh_header(&v3c_contents).map_err(|e| add_path_to_error(e, vmesh_path))?;
     let mut v3c_reader = Cursor::new(v3c_contents);
+    let v3c_file = v3mc::File::read(&mut v3c_reader)?;
+    Ok(v3c_file)
+}
+
+const VERTEX_TARGET: Option<json::buffer::Target> = Some(json::buffer::Target::ArrayBuffer);
+
+#[derive(Default)]
+struct GltfBuilder {
+    root: json::Root,
+    bin: Vec<u8>,
+    /// Textures by texture name and clamping
+    textures: HashMap<(String, bool), json::Index<json::Texture>>,
+}
+
+impl GltfBuilder {
+    fn push_buffer_view(
+        &mut self,
+        data: &[u8],
+        target: Option<json::buffer::Target>,
+    ) -> json::Index<json::buffer::View> {
+        // accessors data must be aligned to component size
+        while !self.bin.len().is_multiple_of(4) {
+            self.bin.push(0);
+        }
+        let byte_offset = self.bin.len();
+        self.bin.extend_from_slice(data);
+        self.root.buffer_views.push(json::buffer::View {
+            buffer: json::Index::new(0),
+            byte_length: USize64::from(data.len()),
+            byte_offset: Some(USize64::from(byte_offset)),
+            byte_stride: None,
+            name: None,
+            target: target.map(Valid),
+            extensions: None,
+            extras: Default::default(),
+        });
+        json::Index::new(self.root.buffer_views.len() as u32 - 1)
+    }
+
+    fn push_accessor(
+        &mut self,
+        data: &[u8],
+        count: usize,
+        component_type: json::accessor::ComponentType,
+        type_: json::accessor::Type,
+        target: Option<json::buffer::Target>,
+        min_max: Option<(serde_json::Value, serde_json::Value)>,
+    ) -> json::Index<json::Accessor> {
+        let buffer_view = self.push_buffer_view(data, target);
+        let (min, max) = min_max.unzip();
+        self.root.accessors.push(json::Accessor {
+            buffer_view: Some(buffer_view),
+            byte_offset: None,
+            count: USize64::from(count),
+            component_type: Valid(json::accessor::GenericComponentType(component_type)),
+            extensions: None,
+            extras: Default::default(),
+            type_: Valid(type_),
+            min,
+            max,
+            name: None,
+            normalized: false,
+            sparse: None,
+        });
+        json::Index::new(self.root.accessors.len() as u32 - 1)
+    }
+
+    fn push_f32_accessor<const N: usize>(
+        &mut self,
+        values: &[[f32; N]],
+        type_: json::accessor::Type,
+        target: Option<json::buffer::Target>,
+        with_bounds: bool,
+    ) -> json::Index<json::Accessor> {
+        let data: Vec<u8> = values
+            .iter()
+            .flatten()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+        let min_max = with_bounds.then(|| {
+            let mut min = [f32::MAX; N];
+            let mut max = [f32::MIN; N];
+            for value in values {
+                for (i, v) in value.iter().enumerate() {
+                    min[i] = min[i].min(*v);
+                    max[i] = max[i].max(*v);
+                }
+            }
+            (
+                serde_json::json!(min.to_vec()),
+                serde_json::json!(max.to_vec()),
+            )
+        });
+        self.push_accessor(
+            &data,
+            values.len(),
+            json::accessor::ComponentType::F32,
+            type_,
+            target,
+            min_max,
+        )
+    }
+
+    fn push_u8_vec4_accessor(
+        &mut self,
+        values: &[[u8; 4]],
+        normalized: bool,
+    ) -> json::Index<json::Accessor> {
+        let data: Vec<u8> = values.iter().flatten().copied().collect();
+        let index = self.push_accessor(
+            &data,
+            values.len(),
+            json::accessor::ComponentType::U8,
+            json::accessor::Type::Vec4,
+            VERTEX_TARGET,
+            None,
+        );
+        self.root.accessors[index.value()].normalized = normalized;
+        index
+    }
+
+    fn push_indices_accessor(&mut self, indices: &[u16]) -> json::Index<json::Accessor> {
+        let data: Vec<u8> = indices.iter().flat_map(|i| i.to_le_bytes()).collect();
+        self.push_accessor(
+            &data,
+            indices.len(),
+            json::accessor::ComponentType::U16,
+            json::accessor::Type::Scalar,
+            Some(json::buffer::Target::ElementArrayBuffer),
+            None,
+        )
+    }
+
+    fn get_or_push_texture(&mut self, tex_name: &str, clamped: bool) -> json::Index<json::Texture> {
+        let key = (tex_name.to_owned(), clamped);
+        if let Some(&index) = self.textures.get(&key) {
+            return index;
+        }
+        let wrapping_mode = if clamped {
+            json::texture::WrappingMode::ClampToEdge
+        } else {
+            json::texture::WrappingMode::Repeat
+        };
+        self.root.samplers.push(json::texture::Sampler {
+            wrap_s: Valid(wrapping_mode),
+            wrap_t: Valid(wrapping_mode),
+            ..Default::default()
+        });
+        self.root.images.push(json::Image {
+            buffer_view: None,
+            mime_type: None,
+            // texture name is read from image name so the URI only helps finding the file
+            name: Some(tex_name.to_owned()),
+            uri: Some(tex_name.replace('%', "%25").replace(' ', "%20")),
+            extensions: None,
+            extras: Default::default(),
+        });
+        self.root.textures.push(json::Texture {
+            name: None,
+            sampler: Some(json::Index::new(self.root.samplers.len() as u32 - 1)),
+            source: json::Index::new(self.root.images.len() as u32 - 1),
+            extensions: None,
+            extras: Default::default(),
+        });
+        let index = json::Index::new(self.root.textures.len() as u32 - 1);
+        self.textures.insert(key, index);
+        index
+    }
+
+    fn push_node(&mut self, node: json::Node) -> json::Index<json::Node> {
+        self.root.nodes.push(node);
+        json::Index::new(self.root.nodes.len() as u32 - 1)
+    }
+}
+
+fn new_node(name: String) -> json::Node {
+    json::Node {
+        camera: None,
+        children: None,
+        extensions: None,
+        extras: Default::default(),
+        matrix: None,
+        mesh: None,
+        name: Some(name),
+        rotation: None,
+        scale: None,
+        translation: None,
+        skin: None,
+        weights: None,
+    }
+}
+
+/// Converts a material used by a mesh chunk. Properties without GLTF equivalent are stored in extras
+/// (see `material::convert_material`).
+fn convert_material(
+    material: &v3mc::Material,
+    tex_name: &str,
+    render_mode: u32,
+    double_sided: bool,
+    builder: &mut GltfBuilder,
+) -> json::Index<json::Material> {
+    let texture = builder.get_or_push_texture(tex_name, is_render_mode_clamped(render_mode));
+    let extras = MaterialExtras {
+        rf_reflection_amount: (material.reflection_amount != 0.0)
+            .then_some(material.reflection_amount),
+        rf_reflection_texture: (!material.refl_tex_name.is_empty())
+            .then(|| material.refl_tex_name.clone()),
+        rf_material_flags: Some(material.flags),
+        rf_render_mode: Some(render_mode),
+    };
+    let alpha_mode = get_alpha_mode_for_render_mode(render_mode);
+    let self_illumination = material.self_illumination.clamp(0.0, 1.0);
+    builder.root.materials.push(json::Material {
+        alpha_cutoff: None,
+        alpha_mode: Valid(alpha_mode),
+        double_sided,
+        name: Path::new(tex_name)
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned()),
+        pbr_metallic_roughness: json::material::PbrMetallicRoughness {
+            base_color_texture: Some(json::texture::Info {
+                index: texture,
+                tex_coord: 0,
+                extensions: None,
+                extras: Default::default(),
+            }),
+            // reverse of mapping used by material::convert_material
+            metallic_factor: json::material::StrengthFactor(
+                material.specular_level.clamp(0.0, 1.0),
+            ),
+            roughness_factor: json::material::StrengthFactor(
+                (1.0 - material.glossiness).clamp(0.0, 1.0),
+            ),
+            ..Default::default()
+        },
+        emissive_factor: json::material::EmissiveFactor([self_illumination; 3]),
+        extras: serde_json::value::to_raw_value(&extras).ok(),
+        ..Default::default()
+    });
+    json::Index::new(builder.root.materials.len() as u32 - 1)
+}
+
+/// Materials converted for a LOD mesh. The same V3M/V3C material used with different render modes or face flags
+/// is converted to separate GLTF materials.
+struct LodMeshMaterials<'a> {
+    materials: &'a [v3mc::Material],
+    converted: Vec<((u8, u32, bool), json::Index<json::Material>)>,
+}
+
+fn get_chunk_material(
+    mesh: &v3mc::Mesh,
+    chunk_index: usize,
+    data_block: &v3mc::MeshDataBlock,
+    lod_materials: &mut LodMeshMaterials,
+    builder: &mut GltfBuilder,
+) -> std::io::Result<json::Index<json::Material>> {
+    let texture_index = data_block.chunks[chunk_index].texture_index;
+    let tex_ref = usize::try_from(texture_index)
+        .ok()
+        .and_then(|i| mesh.textures.get(i))
+        .ok_or_else(|| {
+            new_custom_error(format!(
+                "mesh chunk {} has invalid texture index {}",
+                chunk_index, texture_index
+            ))
+        })?;
+    let material = lod_materials
+        .materials
+        .get(tex_ref.material_index as usize)
+        .ok_or_else(|| {
+            new_custom_error(format!(
+                "texture {} has invalid material index {}",
+                tex_ref.tex_name, tex_ref.material_index
+            ))
+        })?;
+    let render_mode = mesh.chunks[chunk_index].render_mode;
+    let double_sided = data_block.chunks_data[chunk_index]
+        .faces
+        .iter()
+        .any(|face| face.flags & v3mc::MeshFace::DOUBLE_SIDED != 0);
+    let key = (tex_ref.material_index, render_mode, double_sided);
+    if let Some((_, index)) = lod_materials.converted.iter().find(|(k, _)| *k == key) {
+        return Ok(*index);
+    }
+    let index = convert_material(
+        material,
+        &tex_ref.tex_name,
+        render_mode,
+        double_sided,
+        builder,
+    );
+    lod_materials.converted.push((key, index));
+    Ok(index)
+}
+
+fn convert_mesh_chunk(
+    chunk_data: &v3mc::MeshChunkData,
+    material: json::Index<json::Material>,
+    builder: &mut GltfBuilder,
+) -> json::mesh::Primitive {
+    let positions: Vec<_> = chunk_data
+        .vecs
+        .iter()
+        .copied()
+        .map(rf_to_gltf_vec)
+        .collect();
+    let normals: Vec<_> = chunk_data
+        .norms
+        .iter()
+        .copied()
+        .map(rf_to_gltf_vec)
+        .collect();
+    let indices: Vec<u16> = chunk_data
+        .faces
+        .iter()
+        .flat_map(|face| rf_to_gltf_face(face.vindices))
+        .collect();
+
+    let mut attributes = std::collections::BTreeMap::new();
+    attributes.insert(
+        Valid(json::mesh::Semantic::Positions),
+        builder.push_f32_accessor(&positions, json::accessor::Type::Vec3, VERTEX_TARGET, true),
+    );
+    attributes.insert(
+        Valid(json::mesh::Semantic::Normals),
+        builder.push_f32_accessor(&normals, json::accessor::Type::Vec3, VERTEX_TARGET, false),
+    );
+    attributes.insert(
+        Valid(json::mesh::Semantic::TexCoords(0)),
+        builder.push_f32_accessor(
+            &chunk_data.uvs,
+            json::accessor::Type::Vec2,
+            VERTEX_TARGET,
+            false,
+        ),
+    );
+    if !chunk_data.wi.is_empty() {
+        let joints: Vec<_> = chunk_data.wi.iter().map(|wi| wi.indices).collect();
+        let weights: Vec<_> = chunk_data.wi.iter().map(|wi| wi.weights).collect();
+        attributes.insert(
+            Valid(json::mesh::Semantic::Joints(0)),
+            builder.push_u8_vec4_accessor(&joints, false),
+        );
+        attributes.insert(
+            Valid(json::mesh::Semantic::Weights(0)),
+            builder.push_u8_vec4_accessor(&weights, true),
+        );
+    }
+    json::mesh::Primitive {
+        attributes,
+        extensions: None,
+        extras: Default::default(),
+        indices: Some(builder.push_indices_accessor(&indices)),
+        material: Some(material),
+        mode: Valid(json::mesh::Mode::Triangles),
+        targets: None,
+    }
+}
+
+fn convert_mesh(
+    mesh: &v3mc::Mesh,
+    name: &str,
+    lod_materials: &mut LodMeshMaterials,
+    builder: &mut GltfBuilder,
+) -> std::io::Result<json::Index<json::Mesh>> {
+    let data_block = mesh.read_data_block()?;
+    let primitives = data_block
+        .chunks_data
+        .iter()
+        .enumerate()
+        .map(|(i, chunk_data)| {
+            let material = get_chunk_material(mesh, i, &data_block, lod_materials, builder)?;
+            Ok(convert_mesh_chunk(chunk_data, material, builder))
+        })
+        .collect::<std::io::Result<_>>()?;
+    builder.root.meshes.push(json::Mesh {
+        extensions: None,
+        extras: Default::default(),
+        name: Some(name.to_owned()),
+        primitives,
+        weights: None,
+    });
+    Ok(json::Index::new(builder.root.meshes.len() as u32 - 1))
+}
+
+fn make_lod_distance_extras(distance: f32) -> json::Extras {
+    let raw = serde_json::json!({ "LOD_distance": distance }).to_string();
+    RawValue::from_string(raw).ok()
+}
+
+pub(crate) fn get_bone_world_transform(bone: &v3mc::Bone) -> glam::Mat4 {
+    // RF bone stores decomposed inverse bind matrix (see char_anim::convert_bone)
+    let inverse_bind_matrix = glam::Mat4::from_rotation_translation(
+        glam::Quat::from_array(rf_to_gltf_quat(bone.base_rotation)),
+        glam::Vec3::from(rf_to_gltf_vec(bone.base_translation)),
+    );
+    inverse_bind_matrix.inverse()
+}
+
+fn convert_bones(
+    bones: &[v3mc::Bone],
+    builder: &mut GltfBuilder,
+) -> std::io::Result<(json::Index<json::Skin>, Vec<json::Index<json::Node>>)> {
+    let world_transforms: Vec<_> = bones.iter().map(get_bone_world_transform).collect();
+    let first_node_index = builder.root.nodes.len();
+    let joints: Vec<json::Index<json::Node>> = (0..bones.len())
+        .map(|i| json::Index::new((first_node_index + i) as u32))
+        .collect();
+    let mut root_joints = Vec::new();
+    for (i, bone) in bones.iter().enumerate() {
+        let parent_transform = match bone.parent_index {
+            -1 => {
+                root_joints.push(joints[i]);
+                glam::Mat4::IDENTITY
+            }
+            p if p >= 0 && (p as usize) < bones.len() => world_transforms[p as usize],
+            p => {
+                return Err(new_custom_error(format!(
+                    "bone {} has invalid parent index {}",
+                    bone.name, p
+                )))
+            }
+        };
+        let local_transform = parent_transform.inverse() * world_transforms[i];
+        let (_scale, rotation, translation) = local_transform.to_scale_rotation_translation();
+        let children: Vec<_> = bones
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| b.parent_index == i as i32)
+            .map(|(j, _)| joints[j])
+            .collect();
+        builder.push_node(json::Node {
+            children: (!children.is_empty()).then_some(children),
+            rotation: Some(json::scene::UnitQuaternion(rotation.to_array())),
+            translation: Some(translation.to_array()),
+            ..new_node(bone.name.clone())
+        });
+    }
+
+    let inverse_bind_matrices: Vec<_> = world_transforms
+        .iter()
+        .map(|m| m.inverse().to_cols_array())
+        .collect();
+    let inverse_bind_matrices_accessor = builder.push_f32_accessor(
+        &inverse_bind_matrices,
+        json::accessor::Type::Mat4,
+        None,
+        false,
+    );
+    builder.root.skins.push(json::Skin {
+        extensions: None,
+        extras: Default::default(),
+        inverse_bind_matrices: Some(inverse_bind_matrices_accessor),
+        joints,
+        name: None,
+        skeleton: None,
+    });
+    let skin_index = json::Index::new(builder.root.skins.len() as u32 - 1);
+    Ok((skin_index, root_joints))
+}
+
+fn convert_lod_mesh(
+    lod_mesh: &v3mc::LodMesh,
+    skin: Option<json::Index<json::Skin>>,
+    builder: &mut GltfBuilder,
+) -> std::io::Result<json::Index<json::Node>> {
+    let mut lod_materials = LodMeshMaterials {
+        materials: &lod_mesh.materials,
+        converted: Vec::new(),
+    };
+    // Less detailed meshes are exported as children of the most detailed one (see README)
+    let mut lod_children = Vec::new();
+    for (i, (mesh, distance)) in lod_mesh
+        .meshes
+        .iter()
+        .zip(&lod_mesh.distances)
+        .enumerate()
+        .skip(1)
+    {
+        let name = format!("{}_lod{}", lod_mesh.name, i);
+        let mesh_index = convert_mesh(mesh, &name, &mut lod_materials, builder)?;
+        lod_children.push(builder.push_node(json::Node {
+            mesh: Some(mesh_index),
+            skin,
+            extras: make_lod_distance_extras(*distance),
+            ..new_node(name)
+        }));
+    }
+    let mesh_index = match lod_mesh.meshes.first() {
+        Some(mesh) => Some(convert_mesh(
+            mesh,
+            &lod_mesh.name,
+            &mut lod_materials,
+            builder,
+        )?),
+        None => None,
+    };
+    Ok(builder.push_node(json::Node {
+        mesh: mesh_index,
+        skin,
+        translation: Some(rf_to_gltf_vec(lod_mesh.offset)),
+        children: (!lod_children.is_empty()).then_some(lod_children),
+        ..new_node(lod_mesh.name.clone())
+    }))
+}
 
-    let v3c_file_header: v3mc::FileHeader = v3c_reader.read_le()?;
-    println!("v3c_file_header: {:?}",v3c_file_header);
+pub fn export_vmesh_to_gltf(vmesh: &v3mc::File, output_path: &Path) -> Result<(), Box<dyn Error>> {
+    let mut builder = GltfBuilder::default();
+    let mut scene_nodes = Vec::with_capacity(vmesh.lod_meshes.len());
+    let skin = if vmesh.bones.is_empty() {
+        None
+    } else {
+        let (skin, root_joints) = convert_bones(&vmesh.bones, &mut builder)?;
+        scene_nodes.extend(root_joints);
+        Some(skin)
+    };
+    for lod_mesh in &vmesh.lod_meshes {
+        scene_nodes.push(convert_lod_mesh(lod_mesh, skin, &mut builder)?);
+    }
 
-    //
+    let bin_path = output_path.with_extension("bin");
+    let bin_uri = bin_path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned());
+    let GltfBuilder { mut root, bin, .. } = builder;
+    root.asset.generator = Some(format!("vmesh {}", env!("CARGO_PKG_VERSION")));
+    root.buffers.push(json::Buffer {
+        byte_length: USize64::from(bin.len()),
+        name: None,
+        uri: bin_uri,
+        extensions: None,
+        extras: Default::default(),
+    });
+    root.scenes.push(json::Scene {
+        extensions: None,
+        extras: Default::default(),
+        name: None,
+        nodes: scene_nodes,
+    });
+    root.scene = Some(json::Index::new(0));
+
+    fs::write(&bin_path, &bin).map_err(|e| add_path_to_error(e, &bin_path))?;
+    fs::write(output_path, root.to_string_pretty()?)
+        .map_err(|e| add_path_to_error(e, output_path))?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material;
+
+    #[test]
+    fn material_properties_survive_round_trip() {
+        let rf_material = v3mc::Material {
+            tex_name: "glass.tga".to_owned(),
+            self_illumination: 0.5,
+            specular_level: 0.25,
+            glossiness: 0.75,
+            reflection_amount: 0.3,
+            refl_tex_name: "env.tga".to_owned(),
+            flags: 0x15,
+        };
+        let render_mode = v3mc::encode_render_mode(
+            v3mc::TextureSource::Clamp,
+            v3mc::ColorOp::Add,
+            v3mc::AlphaOp::Mul,
+            v3mc::AlphaBlend::AlphaBlendAlpha,
+            v3mc::ZbufferType::FullAlphaTest,
+            v3mc::FogType::ForceOff,
+        );
+        let mut builder = GltfBuilder::default();
+        convert_material(&rf_material, "glass.tga", render_mode, true, &mut builder);
+        let json = builder.root.to_string().unwrap();
+        let document = gltf::Gltf::from_slice(json.as_bytes()).unwrap().document;
+        let gltf_material = document.materials().next().unwrap();
+
+        assert_eq!(gltf_material.alpha_mode(), gltf::material::AlphaMode::Blend);
+        assert!(gltf_material.double_sided());
+        assert_eq!(gltf_material.emissive_factor(), [0.5; 3]);
+        assert!(material::convert_material(&gltf_material) == rf_material);
+        assert_eq!(
+            material::compute_render_mode_for_material(&gltf_material),
+            render_mode
+        );
+    }
+}
@@ -0,0 +1,480 @@
+use crate::io_utils::{add_path_to_error, new_custom_error};
+use gltf::accessor::sparse::IndexType;
+use gltf::accessor::{DataType, Dimensions};
+use gltf::animation::Property;
+use gltf::buffer::{Data, Source};
+use gltf::json;
+use gltf::json::validation::{Checked, Error as ValidationError};
+use gltf::mesh::Semantic;
+use std::path::Path;
+
+/// Returns data of a buffer view starting at the given offset
+fn get_view_data<'a>(
+    view: &gltf::buffer::View,
+    offset: usize,
+    buffers: &'a [Data],
+) -> Option<&'a [u8]> {
+    let data = buffers.get(view.buffer().index())?;
+    let view_data = data.get(view.offset()..view.offset() + view.length())?;
+    view_data.get(offset..)
+}
+
+fn read_sparse_index(data: &[u8], index_type: IndexType) -> Option<usize> {
+    match index_type {
+        IndexType::U8 => data.first().map(|&i| i as usize),
+        IndexType::U16 => data
+            .get(..2)
+            .map(|b| u16::from_le_bytes([b[0], b[1]]) as usize),
+        IndexType::U32 => data
+            .get(..4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize),
+    }
+}
+
+/// Builds tightly packed data of a sparse accessor: elements of the base buffer view (or zeros if there is
+/// no base view) with sparse elements substituted. The element count comes from the file so it is checked before
+/// allocating: the base view has to contain all elements and without a base view the resolved data cannot be larger
+/// than all buffers of the file together (a zero-filled morph target is as large as the positions of its mesh).
+fn resolve_sparse_accessor(
+    accessor: &gltf::Accessor,
+    buffers: &[Data],
+) -> std::io::Result<Vec<u8>> {
+    let out_of_bounds_error = || {
+        new_custom_error(format!(
+            "sparse accessor #{} references data outside of its buffer view",
+            accessor.index()
+        ))
+    };
+    let elem_size = accessor.size();
+    let invalid_count_error = || {
+        new_custom_error(format!(
+            "sparse accessor #{} has invalid element count {}",
+            accessor.index(),
+            accessor.count()
+        ))
+    };
+    let size = accessor
+        .count()
+        .checked_mul(elem_size)
+        .ok_or_else(invalid_count_error)?;
+    let base = match accessor.view() {
+        Some(view) => {
+            let base =
+                get_view_data(&view, accessor.offset(), buffers).ok_or_else(out_of_bounds_error)?;
+            let stride = view.stride().unwrap_or(elem_size);
+            let end = stride
+                .checked_mul(accessor.count().saturating_sub(1))
+                .and_then(|n| n.checked_add(elem_size));
+            if end.is_none_or(|end| end > base.len()) {
+                return Err(out_of_bounds_error());
+            }
+            Some((base, stride))
+        }
+        None => {
+            let buffers_size: usize = buffers.iter().map(|b| b.len()).sum();
+            if size > buffers_size {
+                return Err(invalid_count_error());
+            }
+            None
+        }
+    };
+    let mut data = vec![0_u8; size];
+    if let Some((base, stride)) = base {
+        for (i, elem) in data.chunks_exact_mut(elem_size).enumerate() {
+            let src = base
+                .get(i * stride..i * stride + elem_size)
+                .ok_or_else(out_of_bounds_error)?;
+            elem.copy_from_slice(src);
+        }
+    }
+    let sparse = accessor.sparse().unwrap();
+    let indices = sparse.indices();
+    let index_size = match indices.index_type() {
+        IndexType::U8 => 1,
+        IndexType::U16 => 2,
+        IndexType::U32 => 4,
+    };
+    let indices_data = get_view_data(&indices.view(), indices.offset(), buffers)
+        .ok_or_else(out_of_bounds_error)?;
+    let values = sparse.values();
+    let values_data =
+        get_view_data(&values.view(), values.offset(), buffers).ok_or_else(out_of_bounds_error)?;
+    for i in 0..sparse.count() {
+        let index = indices_data
+            .get(i * index_size..)
+            .and_then(|d| read_sparse_index(d, indices.index_type()))
+            .ok_or_else(out_of_bounds_error)?;
+        let Some(dst) = data.get_mut(index * elem_size..(index + 1) * elem_size) else {
+            return Err(new_custom_error(format!(
+                "sparse accessor #{} has index {} but only {} elements",
+                accessor.index(),
+                index,
+                accessor.count()
+            )));
+        };
+        let src = values_data
+            .get(i * elem_size..(i + 1) * elem_size)
+            .ok_or_else(out_of_bounds_error)?;
+        dst.copy_from_slice(src);
+    }
+    Ok(data)
+}
+
+/// Replaces sparse accessors with regular ones referencing new buffers containing resolved data. Readers from
+/// GLTF crate do not handle sparse accessors correctly (e.g. iterating an accessor without a base buffer view
+/// never ends), so this is done once after import and the rest of the code can read accessors as usual.
+fn resolve_sparse_accessors(
+    document: gltf::Document,
+    mut buffers: Vec<Data>,
+) -> gltf::Result<(gltf::Document, Vec<Data>)> {
+    let resolved = document
+        .accessors()
+        .filter(|a| a.sparse().is_some())
+        .map(|a| Ok((a.index(), resolve_sparse_accessor(&a, &buffers)?)))
+        .collect::<std::io::Result<Vec<_>>>()
+        .map_err(gltf::Error::Io)?;
+    if resolved.is_empty() {
+        return Ok((document, buffers));
+    }
+    let mut root = document.into_json();
+    for (accessor_index, mut data) in resolved {
+        let byte_length = data.len().into();
+        root.buffers.push(json::Buffer {
+            byte_length,
+            name: None,
+            uri: None,
+            extensions: None,
+            extras: Default::default(),
+        });
+        root.buffer_views.push(json::buffer::View {
+            buffer: json::Index::new(root.buffers.len() as u32 - 1),
+            byte_length,
+            byte_offset: None,
+            byte_stride: None,
+            name: None,
+            target: None,
+            extensions: None,
+            extras: Default::default(),
+        });
+        let accessor = &mut root.accessors[accessor_index];
+        accessor.buffer_view = Some(json::Index::new(root.buffer_views.len() as u32 - 1));
+        accessor.byte_offset = None;
+        accessor.sparse = None;
+        // keep the padding applied by GLTF crate to other buffers
+        while !data.len().is_multiple_of(4) {
+            data.push(0);
+        }
+        buffers.push(Data(data));
+    }
+    Ok((gltf::Document::from_json_without_validation(root), buffers))
+}
+
+/// Checks indices that GLTF crate validation does not check (animation channel targets) or uses before checking
+/// them (mesh attributes). Such documents would make GLTF crate panic.
+fn check_unvalidated_indices(root: &json::Root) -> gltf::Result<()> {
+    let mut errors = Vec::new();
+    for (mesh_index, mesh) in root.meshes.iter().enumerate() {
+        for (prim_index, prim) in mesh.primitives.iter().enumerate() {
+            for (semantic, accessor) in &prim.attributes {
+                if accessor.value() >= root.accessors.len() {
+                    let key = match semantic {
+                        Checked::Valid(semantic) => semantic.to_string(),
+                        Checked::Invalid => String::new(),
+                    };
+                    let path = json::Path::new()
+                        .field("meshes")
+                        .index(mesh_index)
+                        .field("primitives")
+                        .index(prim_index)
+                        .field("attributes")
+                        .key(&key);
+                    errors.push((path, ValidationError::IndexOutOfBounds));
+                }
+            }
+        }
+    }
+    for (anim_index, anim) in root.animations.iter().enumerate() {
+        for (channel_index, channel) in anim.channels.iter().enumerate() {
+            let path = json::Path::new()
+                .field("animations")
+                .index(anim_index)
+                .field("channels")
+                .index(channel_index)
+                .field("target");
+            if channel.target.node.value() >= root.nodes.len() {
+                errors.push((path.field("node"), ValidationError::IndexOutOfBounds));
+            }
+            if let Checked::Invalid = channel.target.path {
+                errors.push((path.field("path"), ValidationError::Invalid));
+            }
+        }
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(gltf::Error::Validation(errors))
+    }
+}
+
+/// Checks that an accessor can be read by GLTF crate readers: it has one of the types expected by the reader and
+/// its elements lie within its buffer view. Readers panic or read garbage instead of returning an error otherwise.
+fn check_accessor(
+    accessor: &gltf::Accessor,
+    usage: &str,
+    dimensions: &[Dimensions],
+    data_types: &[DataType],
+    buffers: &[Data],
+) -> std::io::Result<()> {
+    if !dimensions.contains(&accessor.dimensions()) || !data_types.contains(&accessor.data_type()) {
+        return Err(new_custom_error(format!(
+            "accessor #{} used as {} has unsupported type {:?} of {:?}",
+            accessor.index(),
+            usage,
+            accessor.dimensions(),
+            accessor.data_type()
+        )));
+    }
+    if accessor.count() == 0 {
+        return Err(new_custom_error(format!(
+            "accessor #{} used as {} has no elements",
+            accessor.index(),
+            usage
+        )));
+    }
+    if let Some(view) = accessor.view() {
+        let elem_size = accessor.size();
+        let stride = view.stride().unwrap_or(elem_size);
+        let end = stride
+            .checked_mul(accessor.count() - 1)
+            .and_then(|n| n.checked_add(accessor.offset() + elem_size));
+        let in_bounds = stride >= elem_size
+            && end.is_some_and(|end| end <= view.length())
+            && get_view_data(&view, 0, buffers).is_some();
+        if !in_bounds {
+            return Err(new_custom_error(format!(
+                "accessor #{} used as {} references data outside of its buffer view",
+                accessor.index(),
+                usage
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Checks all accessors read by converters
+fn check_accessors(document: &gltf::Document, buffers: &[Data]) -> std::io::Result<()> {
+    use DataType::{F32, I16, I8, U16, U32, U8};
+    use Dimensions::{Mat4, Scalar, Vec2, Vec3, Vec4};
+    for mesh in document.meshes() {
+        for prim in mesh.primitives() {
+            for (semantic, accessor) in prim.attributes() {
+                let usage = format!("{} attribute", semantic.to_string());
+                let (dimensions, data_types): (&[_], &[_]) = match semantic {
+                    Semantic::Positions | Semantic::Normals => (&[Vec3], &[F32]),
+                    Semantic::Tangents => (&[Vec4], &[F32]),
+                    Semantic::Colors(_) => (&[Vec3, Vec4], &[U8, U16, F32]),
+                    Semantic::TexCoords(_) => (&[Vec2], &[U8, U16, F32]),
+                    Semantic::Joints(_) => (&[Vec4], &[U8, U16]),
+                    Semantic::Weights(_) => (&[Vec4], &[U8, U16, F32]),
+                    // application specific attributes are not read
+                    Semantic::Extras(_) => continue,
+                };
+                check_accessor(&accessor, &usage, dimensions, data_types, buffers)?;
+            }
+            if let Some(accessor) = prim.indices() {
+                check_accessor(&accessor, "indices", &[Scalar], &[U8, U16, U32], buffers)?;
+            }
+        }
+    }
+    for skin in document.skins() {
+        if let Some(accessor) = skin.inverse_bind_matrices() {
+            check_accessor(&accessor, "inverse bind matrices", &[Mat4], &[F32], buffers)?;
+        }
+    }
+    for anim in document.animations() {
+        for channel in anim.channels() {
+            let sampler = channel.sampler();
+            check_accessor(
+                &sampler.input(),
+                "animation input",
+                &[Scalar],
+                &[F32],
+                buffers,
+            )?;
+            let (usage, dimensions, data_types): (_, &[_], &[_]) = match channel.target().property()
+            {
+                Property::Translation => ("translation output", &[Vec3], &[F32]),
+                Property::Rotation => ("rotation output", &[Vec4], &[I8, U8, I16, U16, F32]),
+                Property::Scale => ("scale output", &[Vec3], &[F32]),
+                Property::MorphTargetWeights => {
+                    ("weights output", &[Scalar], &[I8, U8, I16, U16, F32])
+                }
+            };
+            check_accessor(&sampler.output(), usage, dimensions, data_types, buffers)?;
+        }
+    }
+    Ok(())
+}
+
+/// Imports a GLTF document from its JSON and the BIN chunk of a GLB file
+fn import_gltf_json(
+    json: &[u8],
+    mut blob: Option<Vec<u8>>,
+    mut resolve_uri: impl FnMut(&str) -> gltf::Result<Vec<u8>>,
+) -> gltf::Result<(gltf::Document, Vec<Data>)> {
+    let gltf::Gltf { document, .. } = gltf::Gltf::from_slice_without_validation(json)?;
+    let root = document.into_json();
+    check_unvalidated_indices(&root)?;
+    let document = gltf::Document::from_json(root)?;
+    let mut buffers = Vec::with_capacity(document.buffers().len());
+    for buffer in document.buffers() {
+        let data = match buffer.source() {
+            Source::Uri(uri) if !uri.starts_with("data:") => {
+                let mut data = resolve_uri(uri)?;
+                // keep the padding applied by GLTF crate to other buffers
+                while !data.len().is_multiple_of(4) {
+                    data.push(0);
+                }
+                Data(data)
+            }
+            source => Data::from_source_and_blob(source, None, &mut blob)?,
+        };
+        if data.len() < buffer.length() {
+            return Err(gltf::Error::BufferLength {
+                buffer: buffer.index(),
+                expected: buffer.length(),
+                actual: data.len(),
+            });
+        }
+        buffers.push(data);
+    }
+    // sparse accessors are checked like regular ones (their base view and count) before being resolved,
+    // the resolved data always matches the accessor
+    check_accessors(&document, &buffers).map_err(gltf::Error::Io)?;
+    resolve_sparse_accessors(document, buffers)
+}
+
+fn is_glb(data: &[u8]) -> bool {
+    data.starts_with(b"glTF")
+}
+
+/// Imports a GLTF document (.gltf or .glb) and its buffers from memory. `resolve_uri` is called for every buffer
+/// referencing an external file and has to return its content. Embedded (data URI) buffers and the BIN chunk of
+/// GLB files are handled without calling it. Sparse accessors are resolved to regular accessors. Besides GLTF crate
+/// validation the document is checked for problems that would make GLTF crate readers panic, so converters can
+/// rely on accessors being readable.
+pub(crate) fn import_gltf_slice(
+    data: &[u8],
+    resolve_uri: impl FnMut(&str) -> gltf::Result<Vec<u8>>,
+) -> gltf::Result<(gltf::Document, Vec<Data>)> {
+    if !is_glb(data) {
+        return import_gltf_json(data, None, resolve_uri);
+    }
+    let glb = gltf::Glb::from_slice(data)?;
+    import_gltf_json(&glb.json, glb.bin.map(|bin| bin.into_owned()), resolve_uri)
+}
+
+/// Returns range of the BIN chunk data in a GLB file validated by `gltf::Glb::from_slice`. The file header (magic,
+/// version and length) and the JSON chunk are followed by the BIN chunk header (length and type) and its data.
+fn get_glb_bin_chunk_range(data: &[u8]) -> Option<std::ops::Range<usize>> {
+    let read_u32 = |offset: usize| {
+        let bytes = data.get(offset..offset.checked_add(4)?)?;
+        usize::try_from(u32::from_le_bytes(bytes.try_into().ok()?)).ok()
+    };
+    let json_len = read_u32(12)?;
+    let bin_header_offset = json_len.checked_add(12 + 8)?;
+    let bin_len = read_u32(bin_header_offset)?;
+    let start = bin_header_offset + 8;
+    let end = start.checked_add(bin_len)?;
+    (end <= data.len()).then_some(start..end)
+}
+
+/// Imports a GLTF document (.gltf or .glb) and its buffers from a file. External buffers are loaded relative
+/// to the directory of the file.
+pub(crate) fn import_gltf(path: &Path) -> gltf::Result<(gltf::Document, Vec<Data>)> {
+    let mut data = std::fs::read(path).map_err(|e| gltf::Error::Io(add_path_to_error(e, path)))?;
+    let base = path.parent();
+    let resolve_uri = |uri: &str| match Data::from_source(Source::Uri(uri), base) {
+        Ok(data) => Ok(data.0),
+        // external buffer file is missing or cannot be read
+        Err(gltf::Error::Io(e)) => {
+            let buffer_path = base.unwrap_or(Path::new("")).join(uri);
+            Err(gltf::Error::Io(add_path_to_error(e, &buffer_path)))
+        }
+        Err(err) => Err(err),
+    };
+    if !is_glb(&data) {
+        return import_gltf_slice(&data, resolve_uri);
+    }
+    // The BIN chunk is moved to the start of the file data instead of being copied, so a large GLB file is held in
+    // memory once
+    let glb = gltf::Glb::from_slice(&data)?;
+    let json = glb.json.into_owned();
+    let has_bin = glb.bin.is_some();
+    let blob = match get_glb_bin_chunk_range(&data).filter(|_| has_bin) {
+        Some(range) => {
+            data.truncate(range.end);
+            data.drain(..range.start);
+            Some(data)
+        }
+        None => None,
+    };
+    import_gltf_json(&json, blob, resolve_uri)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_glb(json: &[u8], bin: &[u8]) -> Vec<u8> {
+        let mut chunks = Vec::new();
+        for (data, chunk_type) in [(json, b"JSON"), (bin, b"BIN\0")] {
+            chunks.extend((data.len() as u32).to_le_bytes());
+            chunks.extend(chunk_type);
+            chunks.extend(data);
+        }
+        let mut glb = b"glTF".to_vec();
+        glb.extend(2_u32.to_le_bytes());
+        glb.extend((12 + chunks.len() as u32).to_le_bytes());
+        glb.extend(chunks);
+        glb
+    }
+
+    #[test]
+    fn glb_file_bin_chunk_is_imported() {
+        let json = br#"{"asset":{"version":"2.0"},"buffers":[{"byteLength":6}]}  "#;
+        let bin = [1, 2, 3, 4, 5, 6, 0, 0];
+        let glb = make_glb(json, &bin);
+        let path = std::env::temp_dir().join(format!("vmesh-test-{}.glb", std::process::id()));
+        std::fs::write(&path, &glb).unwrap();
+        let imported = import_gltf(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        let (_, buffers) = imported.unwrap();
+        assert_eq!(buffers.len(), 1);
+        assert_eq!(*buffers[0], bin);
+        let (_, buffers) = import_gltf_slice(&glb, |_| unreachable!()).unwrap();
+        assert_eq!(*buffers[0], bin);
+    }
+
+    #[test]
+    fn missing_files_are_reported_with_path() {
+        let dir = std::env::temp_dir();
+        let missing_gltf = dir.join(format!("vmesh-missing-{}.gltf", std::process::id()));
+        let message = import_gltf(&missing_gltf).err().unwrap().to_string();
+        assert!(
+            message.contains(&*missing_gltf.to_string_lossy()),
+            "{}",
+            message
+        );
+
+        let json =
+            br#"{"asset":{"version":"2.0"},"buffers":[{"byteLength":6,"uri":"missing.bin"}]}"#;
+        let path = dir.join(format!("vmesh-test-{}.gltf", std::process::id()));
+        std::fs::write(&path, json).unwrap();
+        let imported = import_gltf(&path);
+        std::fs::remove_file(&path).unwrap();
+        let message = imported.err().unwrap().to_string();
+        assert!(message.contains("missing.bin"), "{}", message);
+    }
+}
@@ -1,5 +1,9 @@
-use byteorder::{ByteOrder, LittleEndian, WriteBytesExt};
-use std::{convert::TryInto, io::Write};
+use byteorder::{ByteOrder, LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::{
+    convert::TryInto,
+    io::{Read, Write},
+    path::Path,
+};
 
 pub(crate) trait WriteExt: Write {
     fn write_f32_slice_le(&mut self, slice: &[f32]) -> std::io::Result<()> {
@@ -47,6 +51,60 @@ pub(crate) trait WriteExt: Write {
 
 impl<T: Write> WriteExt for T {}
 
+pub(crate) trait ReadExt: Read {
+    fn read_f32_array<T: ByteOrder, const N: usize>(&mut self) -> std::io::Result<[f32; N]> {
+        let mut arr = [0_f32; N];
+        self.read_f32_into::<T>(&mut arr)?;
+        Ok(arr)
+    }
+
+    fn read_char_array(&mut self, size: usize) -> std::io::Result<String> {
+        let mut buf = vec![0_u8; size];
+        self.read_exact(&mut buf)?;
+        let len = buf.iter().position(|&b| b == 0).unwrap_or(size);
+        Ok(String::from_utf8_lossy(&buf[..len]).into_owned())
+    }
+
+    fn read_cstr(&mut self) -> std::io::Result<String> {
+        let mut buf = Vec::new();
+        loop {
+            match self.read_u8()? {
+                0 => break,
+                b => buf.push(b),
+            }
+        }
+        Ok(String::from_utf8_lossy(&buf).into_owned())
+    }
+}
+
+impl<T: Read> ReadExt for T {}
+
 pub(crate) fn new_custom_error<S: Into<String>>(msg: S) -> std::io::Error {
-    std::io::Error::new(std::io::ErrorKind::Other, msg.into())
+    std::io::Error::other(msg.into())
+}
+
+/// Adds path of the file to the error message keeping the error kind
+pub(crate) fn add_path_to_error(err: std::io::Error, path: &Path) -> std::io::Error {
+    std::io::Error::new(err.kind(), format!("{}: {}", path.display(), err))
+}
+
+/// Returns an error listing all output files that already exist. Used by `--no-overwrite` to protect
+/// hand-edited files.
+pub(crate) fn check_output_files_do_not_exist<P: AsRef<Path>>(paths: &[P]) -> std::io::Result<()> {
+    let existing: Vec<_> = paths
+        .iter()
+        .map(AsRef::as_ref)
+        .filter(|p| p.exists())
+        .map(|p| p.display().to_string())
+        .collect();
+    if existing.is_empty() {
+        return Ok(());
+    }
+    Err(std::io::Error::new(
+        std::io::ErrorKind::AlreadyExists,
+        format!(
+            "output file(s) already exist and --no-overwrite is used: {}",
+            existing.join(", ")
+        ),
+    ))
 }
@@ -1,5 +1,12 @@
+#[macro_use]
+extern crate log;
+
+mod anim_diff;
+mod anim_mirror;
+mod anim_retarget;
 mod char_anim;
 mod io_utils;
+mod markers;
 mod material;
 mod math_utils;
 mod rfa;
@@ -8,6 +15,7 @@ mod rfg_convert;
 mod v3mc;
 mod v3mc_convert;
 mod gltf_export;
+mod gltf_import;
 
 use clap::ArgAction;
 use clap::Parser;
@@ -26,26 +34,103 @@ use std::vec::Vec;
 
 type BoxResult<T> = Result<T, Box<dyn Error>>;
 
+/// Prints warnings reported with `warn!` to stderr. `--quiet` disables them by lowering the maximal log level
+struct StderrLogger;
+
+impl log::Log for StderrLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        match record.level() {
+            log::Level::Error => eprintln!("Error: {}", record.args()),
+            log::Level::Warn => eprintln!("Warning! {}", record.args()),
+            _ => eprintln!("{}", record.args()),
+        }
+    }
+
+    fn flush(&self) {}
+}
+
 // glTF defines -X as right, RF defines +X as right
 // Both glTF and RF defines +Y as up, +Z as forward
 
-fn gltf_to_rf_vec(vec: [f32; 3]) -> [f32; 3] {
+/// Axis convention of the source file. glTF specification requires +Y up but some tools export files without
+/// converting from their native Z-up coordinate system.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+enum CoordinateConvention {
+    /// +Y is up (glTF standard, Blender glTF exporter with default settings)
+    #[default]
+    YUp,
+    /// +Z is up, -Y is forward (Blender/3ds Max native coordinates)
+    ZUp,
+}
+
+impl CoordinateConvention {
+    fn to_y_up_vec(self, vec: [f32; 3]) -> [f32; 3] {
+        match self {
+            Self::YUp => vec,
+            Self::ZUp => [vec[0], vec[2], -vec[1]],
+        }
+    }
+
+    fn to_y_up_quat(self, quat: [f32; 4]) -> [f32; 4] {
+        // vector part of quaternion is rotated the same way as vectors
+        let [x, y, z] = self.to_y_up_vec([quat[0], quat[1], quat[2]]);
+        [x, y, z, quat[3]]
+    }
+}
+
+fn gltf_to_rf_vec(vec: [f32; 3], convention: CoordinateConvention) -> [f32; 3] {
+    let vec = convention.to_y_up_vec(vec);
     // in GLTF negative X is right, in RF positive X is right
     [-vec[0], vec[1], vec[2]]
 }
 
-fn gltf_to_rf_quat(quat: [f32; 4]) -> [f32; 4] {
+/// Converts a position (not a direction) to RF space and multiplies it by the `--scale` factor
+fn gltf_to_rf_pos(pos: [f32; 3], convention: CoordinateConvention, scale: f32) -> [f32; 3] {
+    gltf_to_rf_vec(pos.map(|x| x * scale), convention)
+}
+
+fn gltf_to_rf_quat(quat: [f32; 4], convention: CoordinateConvention) -> [f32; 4] {
+    let quat = convention.to_y_up_quat(quat);
     // convert to RF coordinate system
     // it seems RF expects inverted quaternions...
     [-quat[0], quat[1], quat[2], quat[3]]
 }
 
+fn gltf_to_rf_uv(uv: [f32; 2], args: &Args) -> [f32; 2] {
+    // both GLTF and RF (Direct3D) use top-left UV origin so by default no conversion is needed
+    let u = if args.flip_uv_u { 1.0 - uv[0] } else { uv[0] };
+    let v = if args.flip_uv_v { 1.0 - uv[1] } else { uv[1] };
+    [u, v]
+}
+
 fn gltf_to_rf_face<T: Copy>(vindices: [T; 3]) -> [T; 3] {
     // because we convert from right-handed to left-handed order of vertices must be flipped to
     // fix backface culling
     [vindices[0], vindices[2], vindices[1]]
 }
 
+fn rf_to_gltf_vec(vec: [f32; 3]) -> [f32; 3] {
+    // inverse of gltf_to_rf_vec (mirroring X axis is an involution), exported files are always Y-up
+    gltf_to_rf_vec(vec, CoordinateConvention::YUp)
+}
+
+fn rf_to_gltf_quat(quat: [f32; 4]) -> [f32; 4] {
+    // inverse of gltf_to_rf_quat
+    gltf_to_rf_quat(quat, CoordinateConvention::YUp)
+}
+
+fn rf_to_gltf_face<T: Copy>(vindices: [T; 3]) -> [T; 3] {
+    // inverse of gltf_to_rf_face
+    gltf_to_rf_face(vindices)
+}
+
 fn build_child_nodes_indices(doc: &gltf::Document) -> Vec<usize> {
     let mut child_indices: Vec<usize> = doc
         .nodes()
@@ -55,7 +140,7 @@ fn build_child_nodes_indices(doc: &gltf::Document) -> Vec<usize> {
     child_indices
 }
 
-fn get_submesh_nodes(doc: &gltf::Document) -> Vec<gltf::Node> {
+fn get_submesh_nodes(doc: &gltf::Document) -> Vec<gltf::Node<'_>> {
     let child_indices = build_child_nodes_indices(doc);
     doc.nodes()
         .filter(|n| n.mesh().is_some() && !child_indices.contains(&n.index()))
@@ -77,6 +162,43 @@ fn get_primitive_vertex_count(prim: &gltf::Primitive) -> usize {
         .map_or(0, |a| a.1.count())
 }
 
+/// Warns about UV sets other than TEXCOORD_0 which are not exported
+fn warn_about_extra_uv_sets(mesh: &gltf::Mesh) {
+    let num_uv_sets = mesh
+        .primitives()
+        .map(|p| {
+            p.attributes()
+                .filter(|a| matches!(a.0, gltf::mesh::Semantic::TexCoords(_)))
+                .count()
+        })
+        .max()
+        .unwrap_or(0);
+    if num_uv_sets > 1 {
+        warn!(
+            "Mesh {} has {} UV sets. Only TEXCOORD_0 is used.",
+            mesh.name().map_or_else(|| format!("#{}", mesh.index()), str::to_owned),
+            num_uv_sets
+        );
+    }
+}
+
+/// Warns about vertex colors which are not exported. White colors written by many exporters are not reported.
+fn warn_about_vertex_colors(mesh: &gltf::Mesh, ctx: &Context) {
+    let has_visible_colors = mesh.primitives().any(|p| {
+        p.reader(|buffer| ctx.get_buffer_data(buffer))
+            .read_colors(0)
+            .is_some_and(|colors| {
+                colors.into_rgba_f32().any(|c| c.iter().any(|x| (x - 1.0_f32).abs() > 0.01_f32))
+            })
+    });
+    if has_visible_colors {
+        warn!(
+            "Mesh {} has vertex colors (COLOR_0). They are ignored.",
+            mesh.name().map_or_else(|| format!("#{}", mesh.index()), str::to_owned)
+        );
+    }
+}
+
 fn count_mesh_vertices(mesh: &gltf::Mesh) -> usize {
     mesh.primitives()
         .map(|p| get_primitive_vertex_count(&p))
@@ -100,6 +222,8 @@ fn get_node_local_transform(node: &gltf::Node) -> glam::Mat4 {
 struct Context {
     buffers: Vec<gltf::buffer::Data>,
     is_character: bool,
+    skin_index: Option<usize>,
+    bone_map: char_anim::BoneMap,
     args: Args,
     output_dir: PathBuf,
 }
@@ -108,6 +232,10 @@ impl Context {
     fn get_buffer_data(&self, buffer: Buffer) -> Option<&[u8]> {
         Some(&*self.buffers[buffer.index()])
     }
+
+    fn get_skin<'a>(&self, doc: &'a gltf::Document) -> Option<gltf::Skin<'a>> {
+        self.skin_index.and_then(|i| doc.skins().nth(i))
+    }
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
@@ -140,92 +268,572 @@ fn determine_output_format(args: &Args, is_character: bool) -> Format {
 }
 
 fn determine_output_file_name(args: &Args, output_format: Format) -> PathBuf {
-    args.output_file.as_ref().map_or_else(
-        || {
-            let ext = match output_format {
-                Format::V3m => "v3m",
-                Format::V3c => "v3c",
-                Format::Rfg => "rfg",
-            };
-            args.input_file.with_extension(ext)
-        },
-        |p| p.clone(),
-    )
+    let ext = match output_format {
+        Format::V3m => "v3m",
+        Format::V3c => "v3c",
+        Format::Rfg => "rfg",
+    };
+    match &args.output_file {
+        // output directory - use input file name
+        Some(p) if p.is_dir() => p
+            .join(args.input_file.file_name().unwrap_or_default())
+            .with_extension(ext),
+        Some(p) => p.clone(),
+        None => args.input_file.with_extension(ext),
+    }
+}
+
+fn select_skin<'a>(doc: &'a gltf::Document, args: &Args) -> BoxResult<Option<gltf::Skin<'a>>> {
+    if let Some(name) = &args.skin {
+        let skin = doc.skins().find(|s| s.name() == Some(name.as_str()));
+        if skin.is_none() {
+            let names: Vec<_> = doc
+                .skins()
+                .map(|s| s.name().map_or_else(|| format!("#{}", s.index()), str::to_owned))
+                .collect();
+            let err_msg = format!(
+                "skin {} not found (available skins: {})",
+                name,
+                names.join(", ")
+            );
+            return Err(io_utils::new_custom_error(err_msg).into());
+        }
+        return Ok(skin);
+    }
+    if doc.skins().count() > 1 {
+        warn!(
+            "There is more than one skin defined. Only first skin will be used \
+            (use --skin to select another one)."
+        );
+    }
+    Ok(doc.skins().next())
+}
+
+fn load_anim_files(ctx: &Context) -> Result<Vec<char_anim::AnimFile>, Box<dyn Error>> {
+    let mut anim_files = Vec::with_capacity(ctx.args.anim_file.len());
+    for path in &ctx.args.anim_file {
+        if ctx.args.verbose >= 1 {
+            println!("Importing GLTF animation file: {}", path.display());
+        }
+        let (document, buffers) = gltf_import::import_gltf(path)?;
+        let anim_ctx = Context {
+            buffers,
+            is_character: true,
+            skin_index: None,
+            bone_map: ctx.bone_map.clone(),
+            args: ctx.args.clone(),
+            output_dir: ctx.output_dir.clone(),
+        };
+        anim_files.push(char_anim::AnimFile {
+            path: path.clone(),
+            document,
+            ctx: anim_ctx,
+        });
+    }
+    Ok(anim_files)
 }
 
 fn do_convert_gltf_to_vmesh(args: Args) -> Result<(), Box<dyn Error>> {
+    if !(args.scale.is_finite() && args.scale > 0.0_f32) {
+        let err_msg = format!("invalid scale {}: it must be a positive number", args.scale);
+        return Err(io_utils::new_custom_error(err_msg).into());
+    }
     if args.verbose >= 1 {
         println!("Importing GLTF file: {}", args.input_file.display());
     }
     let input_path = Path::new(&args.input_file);
-    let gltf = gltf::Gltf::open(input_path)?;
-    let gltf::Gltf { document, blob } = gltf;
+    let (document, buffers) = gltf_import::import_gltf(input_path)?;
+    let mut skin_opt = select_skin(&document, &args)?;
 
-    if args.verbose >= 2 {
-        println!("Importing GLTF buffers");
+    let output_format = determine_output_format(&args, skin_opt.is_some());
+    if output_format == Format::V3m && skin_opt.is_some() {
+        // static mesh has no bones so skin is not needed
+        warn!("Exporting static mesh (V3M). Skin and animations are ignored.");
+        skin_opt = None;
     }
-
-    let buffers = gltf::import_buffers(&document, input_path.parent(), blob)?;
-    let skin_opt = document.skins().next();
     let is_character = skin_opt.is_some();
-
-    let output_format = determine_output_format(&args, is_character);
     let output_file_name = determine_output_file_name(&args, output_format);
-    let output_dir = output_file_name.parent().unwrap().to_owned();
+    // absolute path makes RFA file paths independent of later changes of the working directory
+    // parent of a bare file name is an empty path
+    let output_parent = output_file_name
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let output_dir = std::path::absolute(output_parent)
+        .map_err(|e| io_utils::add_path_to_error(e, &output_file_name))?;
 
-    if args.verbose >= 1 {
+    if args.verbose >= 1 && !args.dry_run {
         println!("Exporting mesh: {}", output_file_name.display());
     }
+    let (bone_map, bone_map_error) = match &skin_opt {
+        Some(skin) => match char_anim::BoneMap::new(&document, skin, &args.exclude_bone) {
+            Ok(bone_map) => (bone_map, None),
+            // dry run reports problems with the joint hierarchy instead of failing
+            Err(err) if args.dry_run => (char_anim::BoneMap::default(), Some(err)),
+            Err(err) => return Err(err.into()),
+        },
+        None => (char_anim::BoneMap::default(), None),
+    };
     let ctx = Context {
         buffers,
         is_character,
+        skin_index: skin_opt.as_ref().map(gltf::Skin::index),
+        bone_map,
         args,
         output_dir,
     };
+    let anim_config = char_anim::ConversionConfig::from(&ctx.args);
+    if ctx.args.dry_run {
+        let issues = match (&skin_opt, bone_map_error) {
+            (_, Some(err)) => vec![err.to_string()],
+            (Some(skin), None) => {
+                let anim_files = load_anim_files(&ctx)?;
+                let jobs = char_anim::select_rfa_jobs(&document, skin, &anim_files, &ctx)?;
+                char_anim::validate_skin(skin, &jobs, &anim_config, &ctx)
+            }
+            (None, None) => Vec::new(),
+        };
+        for issue in &issues {
+            println!("Problem: {}", issue);
+        }
+        if !issues.is_empty() {
+            let err_msg = format!("found {} problem(s)", issues.len());
+            return Err(io_utils::new_custom_error(err_msg).into());
+        }
+        println!("No problems found");
+        return Ok(());
+    }
+    let stem = output_file_name.file_stem().unwrap_or_default().to_string_lossy();
+    let markers_path = ctx.output_dir.join(format!("{}_markers.json", stem));
+    let texture_manifest_path = ctx.output_dir.join(format!("{}_textures.json", stem));
+    let anim_manifest_path = ctx.output_dir.join(format!("{}_anims.json", stem));
+    // RFG has no skeleton so animations are converted only for V3C
+    let anim_skin = skin_opt.as_ref().filter(|_| output_format != Format::Rfg);
+    let anim_files = match anim_skin {
+        Some(_) => load_anim_files(&ctx)?,
+        None => Vec::new(),
+    };
+    let rfa_jobs = match anim_skin {
+        Some(skin) => char_anim::select_rfa_jobs(&document, skin, &anim_files, &ctx)?,
+        None => Vec::new(),
+    };
+    if ctx.args.no_overwrite {
+        let mut output_files = vec![output_file_name.clone()];
+        output_files.extend(ctx.args.export_markers.then(|| markers_path.clone()));
+        if output_format != Format::Rfg {
+            output_files.extend(ctx.args.texture_manifest.then(|| texture_manifest_path.clone()));
+        }
+        if anim_skin.is_some() {
+            output_files.extend(ctx.args.anim_manifest.then(|| anim_manifest_path.clone()));
+        }
+        output_files.extend(rfa_jobs.iter().map(char_anim::RfaJob::output_path));
+        io_utils::check_output_files_do_not_exist(&output_files)?;
+    }
+    std::fs::create_dir_all(&ctx.output_dir)
+        .map_err(|e| io_utils::add_path_to_error(e, &ctx.output_dir))?;
+    if ctx.args.export_markers {
+        markers::export_markers(&document, &markers_path, &ctx)?;
+    } else {
+        markers::check_for_markers(&document);
+    }
     if output_format == Format::Rfg {
         let rfg = rfg_convert::convert_gltf_to_rfg(&document, &ctx)?;
-        let file = File::create(output_file_name)?;
+        let file = File::create(&output_file_name)
+            .map_err(|e| io_utils::add_path_to_error(e, &output_file_name))?;
         let mut wrt = BufWriter::new(file);
         rfg.write(&mut wrt)?;
     } else {
         let v3m = v3mc_convert::convert_gltf_to_v3mc(&document, &ctx)?;
-        let file = File::create(output_file_name)?;
+        let file = File::create(&output_file_name)
+            .map_err(|e| io_utils::add_path_to_error(e, &output_file_name))?;
         let mut wrt = BufWriter::new(file);
         v3m.write(&mut wrt)?;
 
-        if let Some(skin) = skin_opt {
-            for (i, anim) in document.animations().enumerate() {
-                char_anim::convert_animation_to_rfa(&anim, i, &skin, &ctx)?;
+        if ctx.args.texture_manifest {
+            let manifest_path = texture_manifest_path;
+            if ctx.args.verbose >= 1 {
+                println!("Exporting texture manifest: {}", manifest_path.display());
             }
+            let tex_names: Vec<_> = v3m
+                .lod_meshes
+                .iter()
+                .flat_map(|lod_mesh| &lod_mesh.materials)
+                .map(|m| m.tex_name.as_str())
+                .collect();
+            let base_dir = ctx.args.input_file.parent().unwrap_or(Path::new(""));
+            let dependencies = material::get_texture_dependencies(&document, &tex_names, base_dir);
+            let file = File::create(&manifest_path)
+                .map_err(|e| io_utils::add_path_to_error(e, &manifest_path))?;
+            serde_json::to_writer_pretty(BufWriter::new(file), &dependencies)?;
+        }
+
+        if let Some(skin) = anim_skin {
+            let manifest_path = ctx.args.anim_manifest.then_some(anim_manifest_path.as_path());
+            let verbose = ctx.args.verbose;
+            let print_progress = |i: usize, total: usize, name: &str| {
+                if verbose >= 1 {
+                    println!("Exporting animation {}/{}: {}", i + 1, total, name);
+                }
+            };
+            char_anim::convert_animations_to_rfa(
+                skin,
+                &rfa_jobs,
+                manifest_path,
+                print_progress,
+                &anim_config,
+                &ctx,
+            )?;
         }
     }
 
     Ok(())
 }
 
+fn is_gltf_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(OsStr::to_str)
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("gltf") || ext.eq_ignore_ascii_case("glb"))
+}
+
+/// Recursively finds GLTF files in the directory. Files are sorted by path
+fn find_gltf_files(dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    let entries = std::fs::read_dir(dir).map_err(|e| io_utils::add_path_to_error(e, dir))?;
+    let mut paths = entries
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<std::io::Result<Vec<_>>>()
+        .map_err(|e| io_utils::add_path_to_error(e, dir))?;
+    paths.sort();
+    for path in paths {
+        if path.is_dir() {
+            find_gltf_files(&path, files)?;
+        } else if is_gltf_file(&path) {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Converts all GLTF files in the input directory. Directory structure is mirrored in the output directory.
+/// Conversion continues after a failure and a summary is printed at the end
+fn do_batch_convert(args: Args) -> Result<(), Box<dyn Error>> {
+    let input_dir = args.input_file.clone();
+    let output_dir = args.output_file.clone().unwrap_or_else(|| input_dir.clone());
+    let mut input_files = Vec::new();
+    find_gltf_files(&input_dir, &mut input_files)?;
+    if input_files.is_empty() {
+        warn!("Found no GLTF files in {}", input_dir.display());
+    }
+    let mut failures = Vec::new();
+    for (i, input_file) in input_files.iter().enumerate() {
+        println!("[{}/{}] Converting {}", i + 1, input_files.len(), input_file.display());
+        let relative_dir = input_file
+            .parent()
+            .and_then(|dir| dir.strip_prefix(&input_dir).ok())
+            .unwrap_or(Path::new(""));
+        let file_output_dir = output_dir.join(relative_dir);
+        let result = std::fs::create_dir_all(&file_output_dir)
+            .map_err(|e| io_utils::add_path_to_error(e, &file_output_dir).into())
+            .and_then(|()| {
+                let file_args = Args {
+                    input_file: input_file.clone(),
+                    output_file: Some(file_output_dir.clone()),
+                    ..args.clone()
+                };
+                do_convert_gltf_to_vmesh(file_args)
+            });
+        if let Err(e) = result {
+            eprintln!("Error: {}: {}", input_file.display(), e);
+            failures.push((input_file, e));
+        }
+    }
+    println!(
+        "Batch conversion finished: {} succeeded, {} failed",
+        input_files.len() - failures.len(),
+        failures.len()
+    );
+    for (input_file, e) in &failures {
+        println!("Failed: {}: {}", input_file.display(), e);
+    }
+    if !failures.is_empty() {
+        let err_msg = format!("failed to convert {} file(s)", failures.len());
+        return Err(io_utils::new_custom_error(err_msg).into());
+    }
+    Ok(())
+}
 
 fn do_convert_vmesh_to_gltf(args: Args) -> Result<(), Box<dyn Error>> {
     if args.verbose >= 1 {
-        println!("Importing V3C file: {}", args.input_file.display());
+        println!("Importing mesh: {}", args.input_file.display());
+    }
+    let vmesh = gltf_export::parse_vmesh(&args.input_file)?;
+    if args.verbose >= 1 {
+        println!("{}", vmesh.header.summary());
+        println!("Bones: {}", vmesh.bones.len());
+    }
+
+    let output_file_name = args
+        .output_file
+        .clone()
+        .unwrap_or_else(|| args.input_file.with_extension("gltf"));
+    if args.no_overwrite {
+        io_utils::check_output_files_do_not_exist(&[
+            output_file_name.clone(),
+            output_file_name.with_extension("bin"),
+        ])?;
+    }
+    if args.verbose >= 1 {
+        println!("Exporting GLTF file: {}", output_file_name.display());
+    }
+    gltf_export::export_vmesh_to_gltf(&vmesh, &output_file_name)?;
+    Ok(())
+}
+
+/// Returns render mode value with names of its fields. Fields with unknown values are null
+fn render_mode_to_json(render_mode: u32) -> serde_json::Value {
+    fn field_name<T: std::fmt::Debug>(field: Option<T>) -> Option<String> {
+        field.map(|f| format!("{:?}", f))
+    }
+    let fields = v3mc::decode_render_mode(render_mode);
+    serde_json::json!({
+        "value": format!("{:X}", render_mode),
+        "tex_src": field_name(fields.tex_src),
+        "color_op": field_name(fields.color_op),
+        "alpha_op": field_name(fields.alpha_op),
+        "alpha_blend": field_name(fields.alpha_blend),
+        "zbuffer_type": field_name(fields.zbuffer_type),
+        "fog": field_name(fields.fog),
+    })
+}
+
+fn vmesh_to_json(vmesh: &v3mc::File) -> serde_json::Value {
+    let lod_meshes: Vec<_> = vmesh
+        .lod_meshes
+        .iter()
+        .map(|lod_mesh| {
+            let meshes: Vec<_> = lod_mesh
+                .meshes
+                .iter()
+                .map(|mesh| {
+                    serde_json::json!({
+                        "flags": mesh.flags,
+                        "num_vertices": mesh.num_vecs,
+                        "num_chunks": mesh.chunks.len(),
+                        "num_faces": mesh.chunks.iter().map(|c| c.num_faces as usize).sum::<usize>(),
+                        "num_prop_points": mesh.num_prop_points,
+                        "textures": mesh.textures.iter().map(|t| &t.tex_name).collect::<Vec<_>>(),
+                        "render_modes": mesh
+                            .chunks
+                            .iter()
+                            .map(|c| render_mode_to_json(c.render_mode))
+                            .collect::<Vec<_>>(),
+                    })
+                })
+                .collect();
+            serde_json::json!({
+                "name": lod_mesh.name,
+                "parent_name": lod_mesh.parent_name,
+                "distances": lod_mesh.distances,
+                "offset": lod_mesh.offset,
+                "radius": lod_mesh.radius,
+                "bbox_min": lod_mesh.bbox_min,
+                "bbox_max": lod_mesh.bbox_max,
+                "meshes": meshes,
+                "materials": lod_mesh.materials.iter().map(|m| &m.tex_name).collect::<Vec<_>>(),
+            })
+        })
+        .collect();
+    let header = &vmesh.header;
+    serde_json::json!({
+        "header": {
+            "signature": format!("{:X}", header.signature),
+            "version": header.version,
+            "num_lod_meshes": header.num_lod_meshes,
+            "num_all_materials": header.num_all_materials,
+            "num_cspheres": header.num_cspheres,
+        },
+        "lod_meshes": lod_meshes,
+        "cspheres": vmesh.cspheres.iter().map(|c| serde_json::json!({
+            "name": c.name,
+            "parent_index": c.parent_index,
+            "pos": c.pos,
+            "radius": c.radius,
+        })).collect::<Vec<_>>(),
+        "bones": vmesh.bones.iter().map(|b| serde_json::json!({
+            "name": b.name,
+            "parent_index": b.parent_index,
+        })).collect::<Vec<_>>(),
+    })
+}
+
+fn do_dump_vmesh_json(args: Args) -> Result<(), Box<dyn Error>> {
+    let vmesh = gltf_export::parse_vmesh(&args.input_file)?;
+    let json = serde_json::to_string_pretty(&vmesh_to_json(&vmesh))?;
+    match &args.output_file {
+        Some(path) => {
+            if args.no_overwrite {
+                io_utils::check_output_files_do_not_exist(&[path])?;
+            }
+            std::fs::write(path, json)?
+        }
+        None => println!("{}", json),
     }
+    Ok(())
+}
+
+fn get_gltf_anim_time_range(anim: &gltf::Animation, buffers: &[gltf::buffer::Data]) -> (f32, f32) {
+    let times = anim.channels().flat_map(|channel| {
+        let reader = channel.reader(|buffer| Some(&*buffers[buffer.index()]));
+        reader.read_inputs().into_iter().flatten()
+    });
+    times.fold((f32::INFINITY, 0.0_f32), |(min, max), t| (min.min(t), max.max(t)))
+}
 
-    let input_v3c_path = 
-         //Path::new(&args.input_file) 
-         Path::new("R:/rf-tools/vmesh/test-data/bat1.v3c")
-        ;
+fn do_list_gltf(args: Args) -> Result<(), Box<dyn Error>> {
+    let input_path = Path::new(&args.input_file);
+    let (document, buffers) = gltf_import::import_gltf(input_path)?;
+    let config = char_anim::ConversionConfig::from(&args);
+
+    println!("Skins: {}", document.skins().len());
+    for skin in document.skins() {
+        let num_joints = skin.joints().count();
+        println!(
+            "Skin {} '{}': {}/{} joints{}",
+            skin.index(),
+            skin.name().unwrap_or_default(),
+            num_joints,
+            v3mc::MAX_BONES,
+            if num_joints > v3mc::MAX_BONES { " (too many)" } else { "" }
+        );
+    }
+    println!("Animations: {}", document.animations().len());
+    for anim in document.animations() {
+        let (start, end) = get_gltf_anim_time_range(&anim, &buffers);
+        let duration = if end >= start { end - start } else { 0.0_f32 };
+        println!(
+            "Animation {} '{}': {} channels, {:.3} s ({} ticks)",
+            anim.index(),
+            anim.name().unwrap_or_default(),
+            anim.channels().count(),
+            duration,
+            rfa::RfaTime::from_seconds(duration, config.fps)
+        );
+    }
+    Ok(())
+}
+
+fn do_mirror_rfa(args: Args, axis: anim_mirror::MirrorAxis) -> Result<(), Box<dyn Error>> {
+    let Some(output_file) = &args.output_file else {
+        return Err(io_utils::new_custom_error("output RFA file is required for --mirror").into());
+    };
+    let file = File::open(&args.input_file)
+        .map_err(|e| io_utils::add_path_to_error(e, &args.input_file))?;
+    let mut rdr = std::io::BufReader::new(file);
+    let rfa = rfa::File::read(&mut rdr)?;
+    let pairs = args
+        .mirror_bone_pair
+        .iter()
+        .map(|p| anim_mirror::parse_bone_name_pair(p))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    let bone_names: Vec<_> = match &args.skeleton {
+        Some(path) => gltf_export::parse_vmesh(path)?.bones.into_iter().map(|b| b.name).collect(),
+        None if !pairs.is_empty() => {
+            let err_msg = "--mirror-bone-pair requires --skeleton to get bone names";
+            return Err(io_utils::new_custom_error(err_msg).into());
+        }
+        None => Vec::new(),
+    };
+    let mirrored = anim_mirror::mirror_rfa(&rfa, axis, &bone_names, &pairs)?;
+    if args.no_overwrite {
+        io_utils::check_output_files_do_not_exist(&[output_file])?;
+    }
+    if args.verbose >= 1 {
+        println!("Exporting mirrored animation: {}", output_file.display());
+    }
+    let endian = if args.big_endian { binrw::Endian::Big } else { binrw::Endian::Little };
+    std::fs::write(output_file, mirrored.to_bytes(endian)?)
+        .map_err(|e| io_utils::add_path_to_error(e, output_file))?;
+    Ok(())
+}
 
-    gltf_export::parse_vmesh(input_v3c_path)?;
-    //
+fn do_retarget_rfa(args: Args, target_skeleton: &Path) -> Result<(), Box<dyn Error>> {
+    let Some(output_file) = &args.output_file else {
+        return Err(io_utils::new_custom_error("output RFA file is required for --retarget").into());
+    };
+    let Some(source_skeleton) = &args.skeleton else {
+        let err_msg = "--retarget requires --skeleton to get bone names of the source skeleton";
+        return Err(io_utils::new_custom_error(err_msg).into());
+    };
+    let rfa = read_rfa_file(&args.input_file)?;
+    let source_bone_names: Vec<_> =
+        gltf_export::parse_vmesh(source_skeleton)?.bones.into_iter().map(|b| b.name).collect();
+    let target_bones = gltf_export::parse_vmesh(target_skeleton)?.bones;
+    let retargeted = anim_retarget::retarget_rfa(&rfa, &source_bone_names, &target_bones)?;
+    if args.no_overwrite {
+        io_utils::check_output_files_do_not_exist(&[output_file])?;
+    }
+    if args.verbose >= 1 {
+        println!("Exporting retargeted animation: {}", output_file.display());
+    }
+    let endian = if args.big_endian { binrw::Endian::Big } else { binrw::Endian::Little };
+    std::fs::write(output_file, retargeted.to_bytes(endian)?)
+        .map_err(|e| io_utils::add_path_to_error(e, output_file))?;
     Ok(())
 }
 
-#[derive(Parser, Debug)]
+fn read_rfa_file(path: &Path) -> Result<rfa::File, Box<dyn Error>> {
+    let file = File::open(path).map_err(|e| io_utils::add_path_to_error(e, path))?;
+    let mut rdr = std::io::BufReader::new(file);
+    Ok(rfa::File::read(&mut rdr)?)
+}
+
+fn do_diff_rfa(args: Args, new_file: &Path) -> Result<(), Box<dyn Error>> {
+    let old = read_rfa_file(&args.input_file)?;
+    let new = read_rfa_file(new_file)?;
+    let bone_names: Vec<_> = match &args.skeleton {
+        Some(path) => gltf_export::parse_vmesh(path)?.bones.into_iter().map(|b| b.name).collect(),
+        None => Vec::new(),
+    };
+    println!("{}", anim_diff::diff_rfa(&old, &new).summary(&bone_names));
+    Ok(())
+}
+
+fn do_dump_rfa(args: Args) -> Result<(), Box<dyn Error>> {
+    let file = File::open(&args.input_file)
+        .map_err(|e| io_utils::add_path_to_error(e, &args.input_file))?;
+    let mut rdr = std::io::BufReader::new(file);
+    let rfa = rfa::File::read(&mut rdr)?;
+    println!("{}", rfa.summary(args.fps));
+    if args.verbose >= 2 {
+        for (i, bone) in rfa.bones.iter().enumerate() {
+            println!("Bone {} keys:", i);
+            for k in &bone.rotation_keys {
+                println!("  {:?}", k);
+            }
+            for k in &bone.translation_keys {
+                println!("  {:?}", k);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Parses a frame rate argument. Frame rate must be a finite positive number
+fn parse_fps(s: &str) -> Result<f32, String> {
+    let fps: f32 = s.parse().map_err(|e| format!("{}", e))?;
+    if !(fps.is_finite() && fps > 0.0_f32) {
+        return Err(format!("{} is not a positive number", s));
+    }
+    Ok(fps)
+}
+
+#[derive(Parser, Debug, Clone)]
 #[clap(author, version, about, about = "GLTF to V3M/V3C/RFG converter")]
 pub struct Args {
-    /// Input GLTF or V3C filename
+    /// Input GLTF (.gltf or .glb), V3M or V3C filename. RFA file can be used to print its content.
+    /// If a directory is specified all GLTF files in it (including subdirectories) are converted
     input_file: PathBuf,
 
-    /// Output filename
+    /// Output filename or directory. In batch mode output directory mirrors structure of the input directory
     output_file: Option<PathBuf>,
 
     /// Output file format. If not specified format is detected from output file extension and input file content.
@@ -242,44 +850,270 @@ pub struct Args {
     #[clap(long)]
     ramp_in_time: Option<f32>,
 
-    /// Default ramp in time in seconds to be used when it is not defined in bone extras.
-    /// Default is 0 for death animation, 0.1 fot other animations
+    /// Default ramp out time in seconds to be used when it is not defined in bone extras.
+    /// Default is 0 for death animation, 0.1 fot other animations. Ramp out time is reduced if ramp in and ramp out
+    /// times together exceed the animation duration
     #[clap(long)]
     ramp_out_time: Option<f32>,
 
+    /// Frame rate used for conversion of animation time to RFA ticks (160 ticks per frame). When printing an RFA file
+    /// it is used for showing times in seconds
+    #[clap(long, default_value_t = char_anim::DEFAULT_FPS, value_parser = parse_fps)]
+    fps: f32,
+
+    /// Remove uniform scale from joints instead of failing. Joint positions in the bind pose are preserved
+    #[clap(long)]
+    bake_bone_scale: bool,
+
+    /// Resample all animation channels with the specified frame rate instead of using keys from the source file
+    #[clap(long, value_parser = parse_fps)]
+    bake_fps: Option<f32>,
+
+    /// Remove rotation keys that differ from linear interpolation of neighbouring keys by at most
+    /// the specified angle in degrees. Disabled by default
+    #[clap(long)]
+    reduce_rotation_tolerance: Option<f32>,
+
+    /// Remove translation keys that differ from linear interpolation of neighbouring keys by at most
+    /// the specified distance. Disabled by default
+    #[clap(long)]
+    reduce_translation_tolerance: Option<f32>,
+
+    /// Round translation keys to multiples of the specified distance. Keys that become equal to their neighbours
+    /// are removed. Reduces size of animations at the cost of precision. Disabled by default
+    #[clap(long)]
+    quantize_translation: Option<f32>,
+
+    /// Keep only the specified number of most significant bits (2-16) of rotation key components. The top 2 bits are
+    /// headroom so 8 bits keep about 6 bits of precision. Keys that become equal to their neighbours are removed.
+    /// Disabled by default
+    #[clap(long, value_parser = clap::value_parser!(u32).range(2..=16))]
+    rotation_bits: Option<u32>,
+
+    /// Mark animations with names matching the pattern (`*` matches any characters) as looping, e.g.
+    /// `--loop-anim "*walk"`. First and last poses of looping animations are compared. Can be used multiple times
+    #[clap(long)]
+    loop_anim: Vec<String>,
+
+    /// Easing of all animation keys. `source` keeps easing derived from the source keys, `linear` removes all easing
+    #[clap(long, value_enum, default_value_t = char_anim::EasePreset::Source)]
+    ease: char_anim::EasePreset,
+
+    /// Export only the part of animations between the specified times in seconds, e.g. `--trim 1.5 3`.
+    /// Poses at both ends are interpolated and the trimmed animation starts at 0
+    #[clap(long, num_args = 2, value_names = ["START", "END"])]
+    trim: Option<Vec<f32>>,
+
+    /// Replace translation keys of non-root bones with a single key if the bone does not move further than
+    /// the specified distance during the animation. Disabled by default
+    #[clap(long)]
+    collapse_translation_tolerance: Option<f32>,
+
+    /// Flip horizontal texture coordinate (u -> 1 - u) of exported meshes
+    #[clap(long)]
+    flip_uv_u: bool,
+
+    /// Flip vertical texture coordinate (v -> 1 - v) of exported meshes. Use it for assets that were authored
+    /// with bottom-left UV origin and have vertically flipped textures in game
+    #[clap(long)]
+    flip_uv_v: bool,
+
+    /// Multiply all positions and translations (vertices, bones, animation keys, collision spheres, prop points)
+    /// by the specified factor, e.g. to convert units of the source file to RF units
+    #[clap(long, default_value_t = 1.0)]
+    scale: f32,
+
+    /// Axis convention of the input file. Use `z-up` for files exported from Z-up tools without axis conversion
+    #[clap(long, value_enum, default_value_t = CoordinateConvention::YUp)]
+    coordinate_convention: CoordinateConvention,
+
+    /// Insert the bind pose at the start of animations that do not begin in it (existing keys are delayed by
+    /// one frame). It makes blending into action clips smooth
+    #[clap(long)]
+    prepend_bind_pose: bool,
+
+    /// Name of the joint carrying root motion of animations (total rotation and translation in RFA header).
+    /// By default joint named root or the only joint without a parent is used
+    #[clap(long)]
+    root_bone: Option<String>,
+
+    /// Do not export joints with names matching the pattern (`*` matches any characters), e.g. `--exclude-bone "IK_*"`.
+    /// Children of excluded joints are attached to the nearest exported ancestor. Can be used multiple times
+    #[clap(long)]
+    exclude_bone: Vec<String>,
+
+    /// Convert only animation with the specified name. Can be used multiple times
+    #[clap(long)]
+    anim: Vec<String>,
+
+    /// Convert only animation with the specified index (useful for unnamed animations). Can be used multiple times
+    #[clap(long)]
+    anim_index: Vec<usize>,
+
+    /// Name of the skin to be converted if GLTF file contains more than one skin. By default the first skin is used
+    #[clap(long)]
+    skin: Option<String>,
+
+    /// Change name of RFA file created for an animation, e.g. `--rename-anim "Armature|walk=walk"`.
+    /// Target name can contain subdirectories. Can be used multiple times
+    #[clap(long)]
+    rename_anim: Vec<String>,
+
+    /// Remove the prefix selected by --anim-prefix from animation names when naming RFA files, e.g.
+    /// `Armature|walk` is saved as `walk.rfa`. Names matched by --rename-anim are not affected
+    #[clap(long)]
+    strip_anim_prefix: bool,
+
+    /// Animation name prefix removed by --strip-anim-prefix
+    #[clap(long, default_value = "Armature|")]
+    anim_prefix: String,
+
+    /// Additional GLTF file with animations for the character skeleton. Joints are matched by name.
+    /// Can be used multiple times
+    #[clap(long)]
+    anim_file: Vec<PathBuf>,
+
+    /// Print number of rotation and translation keys and time span of every bone in exported animations
+    #[clap(long)]
+    anim_stats: bool,
+
+    /// Write RFA files in big-endian byte order (used by some non-PC ports). Meshes are always little-endian
+    #[clap(long)]
+    big_endian: bool,
+
+    /// Write a JSON manifest (<output>_textures.json) listing textures referenced by the exported
+    /// V3M/V3C file with status of their source images (resolved, embedded or unresolved)
+    #[clap(long)]
+    texture_manifest: bool,
+
+    /// Write a JSON manifest (<output>_anims.json) listing exported RFA files with their time range and bone count
+    #[clap(long)]
+    anim_manifest: bool,
+
+    /// Write cameras and lights of the GLTF file with their RF space transforms to a JSON file
+    /// (<output>_markers.json) which can be used by scripted sequences
+    #[clap(long)]
+    export_markers: bool,
+
+    /// Mirror input RFA file across the plane perpendicular to the specified axis (RF space) and save it as
+    /// the output file, e.g. to make a right turn animation from a left turn
+    #[clap(long, value_enum)]
+    mirror: Option<anim_mirror::MirrorAxis>,
+
+    /// Swap keys of bones with names differing by the specified parts when mirroring, e.g.
+    /// `--mirror-bone-pair "L_=R_"`. Requires --skeleton. Can be used multiple times
+    #[clap(long)]
+    mirror_bone_pair: Vec<String>,
+
+    /// Compare input RFA file (old version) with the specified RFA file (new version) and print differences
+    /// of header times and changed bones instead of converting it
+    #[clap(long)]
+    diff: Option<PathBuf>,
+
+    /// Reorder bones of input RFA file made for the --skeleton character so it plays on the character from
+    /// the specified V3C file and save it as the output file. Bones are matched by name
+    #[clap(long)]
+    retarget: Option<PathBuf>,
+
+    /// V3C file providing bone names of the mirrored, compared or retargeted RFA files
+    #[clap(long)]
+    skeleton: Option<PathBuf>,
+
+    /// Check the input file for problems with bones and animations and report all of them without writing any files
+    #[clap(long)]
+    dry_run: bool,
+
+    /// Fail instead of overwriting output files that already exist (e.g. hand-edited RFA files). The error lists
+    /// all existing files. By default existing files are overwritten
+    #[clap(long)]
+    no_overwrite: bool,
+
+    /// List skins (with joint count) and animations (with channel count and duration) of GLTF input file
+    /// instead of converting it
+    #[clap(long)]
+    list: bool,
+
+    /// Print structure of V3M/V3C input file (header, meshes, materials, bones) as JSON instead of converting it.
+    /// JSON is saved to the output file if specified
+    #[clap(long)]
+    json: bool,
+
     /// Enable verbose output. Can be used 2 times to increase verbosity
-    #[clap(short, long, action = ArgAction::Count)]
+    #[clap(short, long, action = ArgAction::Count, conflicts_with = "quiet")]
     verbose: u8,
+
+    /// Do not print warnings. Errors are still reported
+    #[clap(short, long)]
+    quiet: bool,
 }
 
 fn main() {
-    let mut args = Args::parse();
-
-    args.verbose=2;
-
-    let path = std::env::current_dir().unwrap();
-    println!("The current directory is {}", path.display());
+    let args = Args::parse();
+    log::set_logger(&StderrLogger).expect("logger should be set only once");
+    log::set_max_level(if args.quiet { log::LevelFilter::Error } else { log::LevelFilter::Warn });
 
     if args.verbose >= 1 {
         println!("vmesh {}", env!("CARGO_PKG_VERSION"));
     }
 
-    let input_file_path = args.input_file.as_path();
-    let extension = input_file_path.extension().map(|p| p.to_str()).flatten();
-    match extension {
-        Some(extension)=>{
-            println!("input_file_extension: {}", extension);
-            if extension=="v3c"{
-                println!("V3C!!!");
-                if let Err(e) = do_convert_vmesh_to_gltf(args) {
-                    eprintln!("Error: {}", e);
-                    std::process::exit(1);
-                }
+    if args.input_file.is_dir() {
+        if let Err(e) = do_batch_convert(args) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
 
-                return;
-            }
-        },
-        None => println!("No file extension")
+    let extension = args.input_file.extension().and_then(OsStr::to_str);
+    if let (Some("rfa"), Some(axis)) = (extension, args.mirror) {
+        if let Err(e) = do_mirror_rfa(args, axis) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+    if let (Some("rfa"), Some(target_skeleton)) = (extension, args.retarget.clone()) {
+        if let Err(e) = do_retarget_rfa(args, &target_skeleton) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+    if let (Some("rfa"), Some(new_file)) = (extension, args.diff.clone()) {
+        if let Err(e) = do_diff_rfa(args, &new_file) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+    if extension == Some("rfa") {
+        if let Err(e) = do_dump_rfa(args) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+    if matches!(extension, Some("v3m") | Some("v3c")) && args.json {
+        if let Err(e) = do_dump_vmesh_json(args) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+    if matches!(extension, Some("v3m") | Some("v3c")) {
+        if let Err(e) = do_convert_vmesh_to_gltf(args) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if args.list {
+        if let Err(e) = do_list_gltf(args) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return;
     }
 
     if let Err(e) = do_convert_gltf_to_vmesh(args) {
@@ -287,3 +1121,111 @@ fn main() {
         std::process::exit(1);
     }
 }
+
+/// Imports a GLTF file without external references and creates a conversion context for its first skin. `args` are
+/// command line options added before the input file name. Used by unit tests of all modules.
+#[cfg(test)]
+fn load_test_gltf(data: &[u8], args: &[&str]) -> Result<(gltf::Document, Context), Box<dyn Error>> {
+    let (document, buffers) =
+        gltf_import::import_gltf_slice(data, |_| Err(gltf::Error::ExternalReferenceInSliceImport))?;
+    let bone_map = {
+        let skin = document.skins().next().ok_or("no skin")?;
+        char_anim::BoneMap::new(&document, &skin, &[])?
+    };
+    let args = Args::parse_from(["vmesh"].iter().chain(args).chain(&["test.gltf"]));
+    let ctx = Context {
+        buffers,
+        is_character: true,
+        skin_index: Some(0),
+        bone_map,
+        args,
+        output_dir: PathBuf::new(),
+    };
+    Ok((document, ctx))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 0.0001;
+
+    fn assert_vec_eq(actual: [f32; 3], expected: [f32; 3]) {
+        let diff = (glam::Vec3::from(actual) - glam::Vec3::from(expected)).abs().max_element();
+        assert!(diff < EPSILON, "{:?} != {:?}", actual, expected);
+    }
+
+    #[test]
+    fn gltf_to_rf_vec_mirrors_x_axis() {
+        let convention = CoordinateConvention::YUp;
+        assert_eq!(gltf_to_rf_vec([1.0, 0.0, 0.0], convention), [-1.0, 0.0, 0.0]);
+        assert_eq!(gltf_to_rf_vec([0.0, 1.0, 0.0], convention), [0.0, 1.0, 0.0]);
+        assert_eq!(gltf_to_rf_vec([0.0, 0.0, 1.0], convention), [0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn gltf_to_rf_quat_keeps_identity() {
+        assert_eq!(
+            gltf_to_rf_quat([0.0, 0.0, 0.0, 1.0], CoordinateConvention::YUp),
+            [0.0, 0.0, 0.0, 1.0]
+        );
+    }
+
+    #[test]
+    fn gltf_to_rf_quat_matches_vec_conversion() {
+        // RF quaternions are inverted so rotating a converted vector by the inverse of the converted
+        // quaternion must give the same result as converting the rotated vector
+        let angle = f32::consts::FRAC_PI_2;
+        let rotations = [
+            glam::Quat::from_rotation_x(angle),
+            glam::Quat::from_rotation_y(angle),
+            glam::Quat::from_rotation_z(angle),
+        ];
+        let vectors = [glam::Vec3::X, glam::Vec3::Y, glam::Vec3::Z];
+        for convention in [CoordinateConvention::YUp, CoordinateConvention::ZUp] {
+            for gltf_quat in rotations {
+                let rf_quat =
+                    glam::Quat::from_array(gltf_to_rf_quat(gltf_quat.to_array(), convention));
+                for gltf_vec in vectors {
+                    let expected = gltf_to_rf_vec((gltf_quat * gltf_vec).to_array(), convention);
+                    let rf_vec = glam::Vec3::from(gltf_to_rf_vec(gltf_vec.to_array(), convention));
+                    assert_vec_eq((rf_quat.inverse() * rf_vec).to_array(), expected);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn gltf_to_rf_vec_converts_z_up_to_y_up() {
+        let convention = CoordinateConvention::ZUp;
+        assert_eq!(gltf_to_rf_vec([0.0, 0.0, 1.0], convention), [0.0, 1.0, 0.0]);
+        assert_eq!(gltf_to_rf_vec([0.0, -1.0, 0.0], convention), [0.0, 0.0, 1.0]);
+        assert_eq!(gltf_to_rf_vec([1.0, 0.0, 0.0], convention), [-1.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn gltf_to_rf_face_flips_winding() {
+        assert_eq!(gltf_to_rf_face([0, 1, 2]), [0, 2, 1]);
+    }
+
+    #[test]
+    fn rf_to_gltf_conversions_are_inverse() {
+        let vec = [1.0, 2.0, 3.0];
+        assert_eq!(rf_to_gltf_vec(gltf_to_rf_vec(vec, CoordinateConvention::YUp)), vec);
+        let quat = glam::Quat::from_rotation_y(0.5).to_array();
+        assert_eq!(rf_to_gltf_quat(gltf_to_rf_quat(quat, CoordinateConvention::YUp)), quat);
+        assert_eq!(rf_to_gltf_face(gltf_to_rf_face([3, 4, 5])), [3, 4, 5]);
+    }
+
+    #[test]
+    fn fps_must_be_positive() {
+        let args = Args::try_parse_from(["vmesh", "test.gltf", "--fps", "30"]).unwrap();
+        assert_eq!(args.fps, 30.0);
+        for fps in ["0", "-15", "inf", "NaN", "abc"] {
+            let fps_arg = format!("--fps={}", fps);
+            assert!(Args::try_parse_from(["vmesh", "test.gltf", &fps_arg]).is_err());
+            let bake_fps_arg = format!("--bake-fps={}", fps);
+            assert!(Args::try_parse_from(["vmesh", "test.gltf", &bake_fps_arg]).is_err());
+        }
+    }
+}
@@ -0,0 +1,192 @@
+use crate::io_utils::add_path_to_error;
+use crate::{gltf_to_rf_quat, gltf_to_rf_vec, Context, CoordinateConvention};
+use gltf::camera::Projection;
+use gltf::khr_lights_punctual::Kind;
+use serde_derive::Serialize;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+/// Position and orientation of a marker in RF space
+#[derive(Serialize, Debug)]
+struct MarkerTransform {
+    position: [f32; 3],
+    orientation: [f32; 4],
+}
+
+#[derive(Serialize, Debug)]
+struct CameraMarker {
+    name: String,
+    projection: &'static str,
+    /// Vertical field of view in degrees (perspective cameras only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    yfov: Option<f32>,
+    znear: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    zfar: Option<f32>,
+    #[serde(flatten)]
+    transform: MarkerTransform,
+}
+
+#[derive(Serialize, Debug)]
+struct LightMarker {
+    name: String,
+    kind: &'static str,
+    color: [f32; 3],
+    intensity: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    range: Option<f32>,
+    /// Inner and outer cone angles in degrees (spot lights only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cone_angles: Option<[f32; 2]>,
+    #[serde(flatten)]
+    transform: MarkerTransform,
+}
+
+#[derive(Serialize, Debug, Default)]
+struct Markers {
+    cameras: Vec<CameraMarker>,
+    lights: Vec<LightMarker>,
+}
+
+fn get_node_name(node: &gltf::Node) -> String {
+    node.name()
+        .map_or_else(|| format!("node_{}", node.index()), str::to_owned)
+}
+
+fn convert_transform(transform: glam::Mat4, convention: CoordinateConvention) -> MarkerTransform {
+    let (_, rotation, translation) = transform.to_scale_rotation_translation();
+    MarkerTransform {
+        position: gltf_to_rf_vec(translation.into(), convention),
+        orientation: gltf_to_rf_quat(rotation.into(), convention),
+    }
+}
+
+fn convert_camera(
+    node: &gltf::Node,
+    camera: &gltf::Camera,
+    transform: MarkerTransform,
+) -> CameraMarker {
+    let name = get_node_name(node);
+    match camera.projection() {
+        Projection::Perspective(p) => CameraMarker {
+            name,
+            projection: "perspective",
+            yfov: Some(p.yfov().to_degrees()),
+            znear: p.znear(),
+            zfar: p.zfar(),
+            transform,
+        },
+        Projection::Orthographic(o) => CameraMarker {
+            name,
+            projection: "orthographic",
+            yfov: None,
+            znear: o.znear(),
+            zfar: Some(o.zfar()),
+            transform,
+        },
+    }
+}
+
+fn convert_light(
+    node: &gltf::Node,
+    light: &gltf::khr_lights_punctual::Light,
+    transform: MarkerTransform,
+) -> LightMarker {
+    let (kind, cone_angles) = match light.kind() {
+        Kind::Directional => ("directional", None),
+        Kind::Point => ("point", None),
+        Kind::Spot {
+            inner_cone_angle,
+            outer_cone_angle,
+        } => (
+            "spot",
+            Some([inner_cone_angle.to_degrees(), outer_cone_angle.to_degrees()]),
+        ),
+    };
+    LightMarker {
+        name: get_node_name(node),
+        kind,
+        color: light.color(),
+        intensity: light.intensity(),
+        range: light.range(),
+        cone_angles,
+        transform,
+    }
+}
+
+fn collect_markers(
+    node: &gltf::Node,
+    parent_transform: glam::Mat4,
+    convention: CoordinateConvention,
+    markers: &mut Markers,
+) {
+    let transform = parent_transform * glam::Mat4::from_cols_array_2d(&node.transform().matrix());
+    if let Some(camera) = node.camera() {
+        let marker = convert_camera(node, &camera, convert_transform(transform, convention));
+        markers.cameras.push(marker);
+    }
+    if let Some(light) = node.light() {
+        let marker = convert_light(node, &light, convert_transform(transform, convention));
+        markers.lights.push(marker);
+    }
+    for child in node.children() {
+        collect_markers(&child, transform, convention, markers);
+    }
+}
+
+fn count_marker_nodes(doc: &gltf::Document) -> (usize, usize) {
+    let num_cameras = doc.nodes().filter(|n| n.camera().is_some()).count();
+    let num_lights = doc.nodes().filter(|n| n.light().is_some()).count();
+    (num_cameras, num_lights)
+}
+
+/// Warns about camera and light nodes which are not part of RF meshes. They can be exported by `--export-markers`.
+pub(crate) fn check_for_markers(doc: &gltf::Document) {
+    let (num_cameras, num_lights) = count_marker_nodes(doc);
+    if num_cameras > 0 || num_lights > 0 {
+        warn!(
+            "GLTF file contains {} camera(s) and {} light(s) which are ignored. Use --export-markers to save \
+            them to a JSON file.",
+            num_cameras, num_lights
+        );
+    }
+}
+
+/// Saves world transforms (in RF space) and properties of camera and light nodes of the default scene to a JSON
+/// file which can be used by scripted sequences. Nothing is written if there are no such nodes.
+pub(crate) fn export_markers(
+    doc: &gltf::Document,
+    path: &Path,
+    ctx: &Context,
+) -> std::io::Result<()> {
+    let mut markers = Markers::default();
+    let scene = doc.default_scene().or_else(|| doc.scenes().next());
+    for node in scene.iter().flat_map(gltf::Scene::nodes) {
+        // uniform scale of the root changes positions but not orientations
+        collect_markers(
+            &node,
+            glam::Mat4::from_scale(glam::Vec3::splat(ctx.args.scale)),
+            ctx.args.coordinate_convention,
+            &mut markers,
+        );
+    }
+    if markers.cameras.is_empty() && markers.lights.is_empty() {
+        if ctx.args.verbose >= 1 {
+            println!("No cameras or lights found - markers file is not created");
+        }
+        return Ok(());
+    }
+    if ctx.args.verbose >= 1 {
+        println!(
+            "Exporting {} camera(s) and {} light(s): {}",
+            markers.cameras.len(),
+            markers.lights.len(),
+            path.display()
+        );
+    }
+    let file = File::create(path).map_err(|e| add_path_to_error(e, path))?;
+    let wrt = BufWriter::new(file);
+    serde_json::to_writer_pretty(wrt, &markers)?;
+    Ok(())
+}
@@ -1,9 +1,64 @@
 use crate::v3mc;
+use serde_derive::{Deserialize, Serialize};
 use std::convert::TryInto;
 use std::f32;
 use std::path::Path;
 
+/// Material properties of V3M/V3C files that have no GLTF equivalent. They are stored in material extras
+/// (custom properties) when exporting V3M/V3C files so converting the GLTF file back preserves them.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub(crate) struct MaterialExtras {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) rf_reflection_amount: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) rf_reflection_texture: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) rf_material_flags: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) rf_render_mode: Option<u32>,
+}
+
+fn get_material_extras(material: &gltf::Material) -> MaterialExtras {
+    material
+        .extras()
+        .as_ref()
+        .and_then(|raw| serde_json::from_str(raw.get()).ok())
+        .unwrap_or_default()
+}
+
+/// Returns GLTF alpha mode corresponding to a render mode (reverse of `compute_render_mode_for_material`)
+pub(crate) fn get_alpha_mode_for_render_mode(render_mode: u32) -> gltf::material::AlphaMode {
+    use gltf::material::AlphaMode;
+    let fields = v3mc::decode_render_mode(render_mode);
+    if fields.alpha_blend != Some(v3mc::AlphaBlend::None) {
+        AlphaMode::Blend
+    } else if fields.zbuffer_type == Some(v3mc::ZbufferType::FullAlphaTest) {
+        AlphaMode::Mask
+    } else {
+        AlphaMode::Opaque
+    }
+}
+
+pub(crate) fn is_render_mode_clamped(render_mode: u32) -> bool {
+    let tex_src = v3mc::decode_render_mode(render_mode).tex_src;
+    matches!(tex_src, Some(v3mc::TextureSource::Clamp | v3mc::TextureSource::ClampNoFiltering))
+}
+
 pub(crate) fn compute_render_mode_for_material(material: &gltf::material::Material) -> u32 {
+    let render_mode = compute_default_render_mode_for_material(material);
+    // Render mode stored by V3M/V3C export is kept unless the material was changed in a way that affects it
+    match get_material_extras(material).rf_render_mode {
+        Some(stored)
+            if get_alpha_mode_for_render_mode(stored) == material.alpha_mode()
+                && is_render_mode_clamped(stored) == is_render_mode_clamped(render_mode) =>
+        {
+            stored
+        }
+        _ => render_mode,
+    }
+}
+
+fn compute_default_render_mode_for_material(material: &gltf::material::Material) -> u32 {
     // for example 0x400C41 (sofa1.v3m):
     //   tex_src = 1, color_op = 2, alpha_op = 3, alpha_blend = 0, zbuffer_type = 5, fog = 0
     // for example 0x518C41 (paper1.v3m, per1.v3m, ...):
@@ -13,10 +68,10 @@ pub(crate) fn compute_render_mode_for_material(material: &gltf::material::Materi
         use gltf::texture::WrappingMode;
         let sampler = tex_info.texture().sampler();
         if sampler.wrap_t() != sampler.wrap_s() {
-            eprintln!("Ignoring wrapT - wrapping mode must be the same for T and S");
+            warn!("Ignoring wrapT - wrapping mode must be the same for T and S");
         }
         if sampler.wrap_s() == WrappingMode::MirroredRepeat {
-            eprintln!("MirroredRepeat wrapping mode is not supported");
+            warn!("MirroredRepeat wrapping mode is not supported");
         }
 
         tex_src = if sampler.wrap_s() == WrappingMode::ClampToEdge {
@@ -43,29 +98,81 @@ pub(crate) fn compute_render_mode_for_material(material: &gltf::material::Materi
 }
 
 fn change_texture_ext_to_tga(name: &str) -> String {
-    String::from(
-        Path::new(name)
-            .with_extension("tga")
-            .file_name()
-            .unwrap()
-            .to_string_lossy(),
-    )
+    Path::new(name)
+        .with_extension("tga")
+        .file_name()
+        .map(|file_name| file_name.to_string_lossy().into_owned())
+        .unwrap_or_default()
 }
 
-pub(crate) fn get_material_base_color_texture_name(material: &gltf::material::Material) -> String {
-    const DEFAULT_TEXTURE: &str = "Rck_Default.tga";
-    if let Some(tex_info) = material.pbr_metallic_roughness().base_color_texture() {
-        let tex = tex_info.texture();
-        let img = tex.source();
-        if let Some(img_name) = img.name() {
-            return change_texture_ext_to_tga(img_name);
+/// Decodes percent-encoded characters (e.g. `%20`) used in glTF URIs
+fn decode_uri(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|h| std::str::from_utf8(h).ok());
+        match hex.filter(|_| bytes[i] == b'%').and_then(|h| u8::from_str_radix(h, 16).ok()) {
+            Some(b) => {
+                decoded.push(b);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
         }
-        if let gltf::image::Source::Uri { uri, .. } = img.source() {
-            return change_texture_ext_to_tga(uri);
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn get_texture_file_name(tex: &gltf::Texture) -> Option<String> {
+    let img = tex.source();
+    if let Some(img_name) = img.name() {
+        return Some(img_name.to_owned());
+    }
+    match img.source() {
+        // data URIs and buffer views (GLB) do not contain a file name
+        gltf::image::Source::Uri { uri, .. } if !uri.starts_with("data:") => Some(decode_uri(uri)),
+        _ => tex.name().map(str::to_owned),
+    }
+}
+
+/// Maximal length of texture name in V3M/V3C files (excluding the terminating zero)
+const MAX_TEXTURE_NAME_LEN: usize = 31;
+
+/// Texture used for materials without a base color texture
+const DEFAULT_TEXTURE: &str = "Rck_Default.tga";
+
+/// Returns name of the base color texture as it is stored in V3M/V3C files without printing warnings
+fn find_material_base_color_texture_name(material: &gltf::material::Material) -> Option<String> {
+    material
+        .pbr_metallic_roughness()
+        .base_color_texture()
+        .and_then(|tex_info| get_texture_file_name(&tex_info.texture()))
+        .map(|tex_name| change_texture_ext_to_tga(&tex_name))
+}
+
+pub(crate) fn get_material_base_color_texture_name(material: &gltf::material::Material) -> String {
+    if let Some(tex_name) = find_material_base_color_texture_name(material) {
+        if tex_name.len() > MAX_TEXTURE_NAME_LEN || !tex_name.is_ascii() {
+            warn!(
+                "Texture name {} is not supported (it must be ASCII and not longer than {} characters)",
+                tex_name, MAX_TEXTURE_NAME_LEN
+            );
         }
+        return tex_name;
+    }
+    match material.index() {
+        Some(index) => warn!(
+            "Cannot obtain texture name for material {} (materials without base color texture are not supported). Using {}",
+            index, DEFAULT_TEXTURE
+        ),
+        None => warn!(
+            "Found primitive without a material. Using {}",
+            DEFAULT_TEXTURE
+        ),
     }
-    eprintln!("Cannot obtain texture name for material {} (materials without base color texture are not supported)",
-        material.index().unwrap_or(0));
     DEFAULT_TEXTURE.into()
 }
 
@@ -75,6 +182,12 @@ fn get_material_self_illumination(mat: &gltf::Material) -> f32 {
 
 pub(crate) fn convert_material(mat: &gltf::Material) -> v3mc::Material {
     let tex_name = get_material_base_color_texture_name(mat);
+    if mat.normal_texture().is_some() {
+        warn!(
+            "Material {} uses a normal map. It is ignored.",
+            mat.name().map_or_else(|| format!("#{}", mat.index().unwrap_or_default()), str::to_owned)
+        );
+    }
     let self_illumination = get_material_self_illumination(mat);
     let specular_level = mat.pbr_specular_glossiness().map_or_else(
         || mat.pbr_metallic_roughness().metallic_factor(),
@@ -91,13 +204,16 @@ pub(crate) fn convert_material(mat: &gltf::Material) -> v3mc::Material {
         |spec_glos| spec_glos.glossiness_factor(),
     );
 
+    let extras = get_material_extras(mat);
+
     v3mc::Material {
         tex_name,
         self_illumination,
         specular_level,
         glossiness,
-        flags: 0x11,
-        ..v3mc::Material::default()
+        reflection_amount: extras.rf_reflection_amount.unwrap_or_default(),
+        refl_tex_name: extras.rf_reflection_texture.unwrap_or_default(),
+        flags: extras.rf_material_flags.unwrap_or(0x11),
     }
 }
 
@@ -116,3 +232,106 @@ pub(crate) fn create_mesh_material_ref(
         tex_name: get_material_base_color_texture_name(material),
     }
 }
+
+#[derive(Serialize, Debug)]
+pub(crate) struct TextureDependency {
+    name: String,
+    /// URI of the source image (relative to the GLTF file) if it is an external file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source: Option<String>,
+    /// `resolved` if the source image exists, `embedded` if it is stored in the GLTF file and `unresolved` if it
+    /// is missing or the texture is not defined by the GLTF file (e.g. the default texture)
+    status: &'static str,
+}
+
+fn get_texture_source_status(
+    tex: &gltf::Texture,
+    base_dir: &Path,
+) -> (Option<String>, &'static str) {
+    match tex.source().source() {
+        gltf::image::Source::Uri { uri, .. } if !uri.starts_with("data:") => {
+            let path = decode_uri(uri);
+            let status = if base_dir.join(&path).is_file() {
+                "resolved"
+            } else {
+                "unresolved"
+            };
+            (Some(path), status)
+        }
+        _ => (None, "embedded"),
+    }
+}
+
+/// Lists distinct texture names referenced by the converted file with the status of their source images.
+/// Source images are looked up relative to `base_dir` (the directory of the GLTF file).
+pub(crate) fn get_texture_dependencies(
+    doc: &gltf::Document,
+    tex_names: &[&str],
+    base_dir: &Path,
+) -> Vec<TextureDependency> {
+    let mut dependencies: Vec<TextureDependency> = Vec::new();
+    for &name in tex_names {
+        if dependencies.iter().any(|d| d.name == name) {
+            continue;
+        }
+        let texture = doc.materials().find_map(|m| {
+            let tex_info = m.pbr_metallic_roughness().base_color_texture()?;
+            let is_same_name = find_material_base_color_texture_name(&m).as_deref() == Some(name);
+            is_same_name.then(|| tex_info.texture())
+        });
+        let (source, status) = match texture {
+            Some(tex) => get_texture_source_status(&tex, base_dir),
+            None => (None, "unresolved"),
+        };
+        dependencies.push(TextureDependency {
+            name: name.to_owned(),
+            source,
+            status,
+        });
+    }
+    dependencies
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn texture_dependencies_list_base_color_textures() {
+        let gltf = serde_json::json!({
+            "asset": {"version": "2.0"},
+            "images": [
+                {"uri": "diffuse%20map.png"},
+                {"uri": "glow.png"},
+                {"uri": "data:image/png;base64,", "name": "embedded.png"}
+            ],
+            "textures": [{"source": 0}, {"source": 1}, {"source": 2}],
+            "materials": [
+                {"pbrMetallicRoughness": {"baseColorTexture": {"index": 0}}, "emissiveTexture": {"index": 1}},
+                {"pbrMetallicRoughness": {"baseColorTexture": {"index": 2}}},
+                {}
+            ]
+        });
+        let doc = gltf::Gltf::from_slice(&serde_json::to_vec(&gltf).unwrap()).unwrap().document;
+        let base_dir = std::env::temp_dir().join(format!("vmesh-texture-deps-{}", std::process::id()));
+        std::fs::create_dir_all(&base_dir).unwrap();
+        std::fs::write(base_dir.join("diffuse map.png"), b"").unwrap();
+        std::fs::write(base_dir.join("glow.png"), b"").unwrap();
+
+        let mut tex_names: Vec<_> = doc.materials().map(|m| get_material_base_color_texture_name(&m)).collect();
+        tex_names.push(tex_names[0].clone());
+        let tex_names: Vec<_> = tex_names.iter().map(String::as_str).collect();
+        let dependencies = get_texture_dependencies(&doc, &tex_names, &base_dir);
+        std::fs::remove_dir_all(&base_dir).unwrap();
+
+        // emissive textures are not exported so they are not dependencies
+        assert_eq!(
+            serde_json::to_value(&dependencies).unwrap(),
+            serde_json::json!([
+                {"name": "diffuse map.tga", "source": "diffuse map.png", "status": "resolved"},
+                {"name": "embedded.tga", "status": "embedded"},
+                {"name": "Rck_Default.tga", "status": "unresolved"}
+            ])
+        );
+    }
+}
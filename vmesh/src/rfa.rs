@@ -1,10 +1,148 @@
-use crate::io_utils::WriteExt;
-use byteorder::{LittleEndian, WriteBytesExt};
-use std::io::{Result, Seek, SeekFrom, Write};
+use crate::io_utils::{new_custom_error, WriteExt};
+use binrw::{binread, BinReaderExt, BinResult, Endian};
+use byteorder::{BigEndian, ByteOrder, LittleEndian, WriteBytesExt};
+use serde_derive::Serialize;
+use std::fmt;
+use std::io::{Cursor, Read, Result, Seek, SeekFrom, Write};
+use std::ops::{Add, AddAssign, Sub, SubAssign};
 
 pub const RFA_SIGNATURE: u32 = 0x4656_4D56; // 'VMVF'
+/// Maximal number of rotation or translation keys of a bone (counts are stored as 16-bit integers)
+pub const MAX_BONE_KEYS: usize = i16::MAX as usize;
 pub const RFA_VERSION: i32 = 8; // 'VMVF'
 
+/// Scale of quaternion components stored as `i16` in rotation keys. Unit quaternion components are in the -1..1
+/// range and RF maps 1.0 to 16383 (2^14 - 1), leaving headroom below `i16::MAX`.
+pub const RFA_QUAT_SCALE: f32 = 16383.0_f32;
+
+/// Number of time ticks in one animation frame. RF divides a frame into 160 ticks so the highest time resolution
+/// of the engine is kept regardless of the animation frame rate.
+pub const RFA_TICKS_PER_FRAME: i32 = 160;
+
+/// Number of time ticks in one second for the 30 FPS frame rate of RF animations (30 * 160).
+pub const RFA_TICKS_PER_SECOND: i32 = 4800;
+
+/// Animation time in ticks. RF animations have 30 frames per second and 160 ticks per frame, so times converted
+/// from seconds depend on the frame rate the animation is converted with.
+#[binread]
+#[derive(Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(transparent)]
+pub struct RfaTime(pub i32);
+
+impl RfaTime {
+    pub const ZERO: Self = Self(0);
+
+    /// Number of ticks in one second for the specified frame rate
+    pub fn ticks_per_second(fps: f32) -> f32 {
+        fps * RFA_TICKS_PER_FRAME as f32
+    }
+
+    pub fn checked_add(self, other: Self) -> Option<Self> {
+        self.0.checked_add(other.0).map(Self)
+    }
+
+    pub fn checked_sub(self, other: Self) -> Option<Self> {
+        self.0.checked_sub(other.0).map(Self)
+    }
+
+    /// Converts time in seconds. Time is rounded to the nearest tick to avoid drift caused by truncation.
+    pub fn from_seconds(seconds: f32, fps: f32) -> Self {
+        Self((seconds * Self::ticks_per_second(fps)).round() as i32)
+    }
+
+    pub fn to_seconds(self, fps: f32) -> f32 {
+        self.0 as f32 / Self::ticks_per_second(fps)
+    }
+
+    pub fn ticks(self) -> i32 {
+        self.0
+    }
+}
+
+impl Add for RfaTime {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self(self.0 + other.0)
+    }
+}
+
+impl Sub for RfaTime {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Self(self.0 - other.0)
+    }
+}
+
+impl AddAssign for RfaTime {
+    fn add_assign(&mut self, other: Self) {
+        self.0 += other.0;
+    }
+}
+
+impl SubAssign for RfaTime {
+    fn sub_assign(&mut self, other: Self) {
+        self.0 -= other.0;
+    }
+}
+
+// Times are printed as plain number of ticks
+impl fmt::Debug for RfaTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.0, f)
+    }
+}
+
+impl fmt::Display for RfaTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+/// Unit quaternion packed into `i16` components scaled by `RFA_QUAT_SCALE`. RF quaternions are inverted
+/// compared to GLTF ones (see `gltf_to_rf_quat`).
+#[binread]
+#[derive(Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub struct ShortQuat(pub [i16; 4]);
+
+impl ShortQuat {
+    pub const IDENTITY: Self = Self([0, 0, 0, RFA_QUAT_SCALE as i16]);
+
+    /// Packs a quaternion. It is normalized first so slightly denormalized input from exporters cannot go
+    /// outside of the `i16` range. Returns `None` if the quaternion cannot be normalized (zero length or NaN
+    /// components).
+    pub fn from_quat(quat: glam::Quat) -> Option<Self> {
+        let quat = glam::Vec4::from(quat).try_normalize()?;
+        Some(Self(quat.to_array().map(|x| {
+            (x * RFA_QUAT_SCALE)
+                .round()
+                .clamp(-RFA_QUAT_SCALE, RFA_QUAT_SCALE) as i16
+        })))
+    }
+
+    pub fn to_quat(self) -> glam::Quat {
+        glam::Quat::from_array(self.0.map(|x| x as f32 / RFA_QUAT_SCALE)).normalize()
+    }
+}
+
+// Quaternions are printed as plain array of components
+impl fmt::Debug for ShortQuat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.0, f)
+    }
+}
+
+/// Formats time in ticks together with time in seconds at the specified frame rate
+fn format_ticks(time: RfaTime, fps: f32) -> String {
+    format!(
+        "{} ticks ({:.3} s at {} FPS)",
+        time,
+        time.to_seconds(fps),
+        fps
+    )
+}
+
 pub struct File {
     pub header: FileHeader,
     pub bones: Vec<Bone>,
@@ -12,135 +150,459 @@ pub struct File {
 }
 
 impl File {
-    pub fn write<W: Write + Seek>(&self, wrt: &mut W) -> Result<()> {
-        self.header.write(wrt)?;
+    /// Writes the file with the specified byte order. PC version of RF uses little-endian.
+    pub fn write<W: Write + Seek>(&self, wrt: &mut W, endian: Endian) -> Result<()> {
+        match endian {
+            Endian::Little => self.write_with_byte_order::<LittleEndian, W>(wrt),
+            Endian::Big => self.write_with_byte_order::<BigEndian, W>(wrt),
+        }
+    }
+
+    fn write_with_byte_order<B: ByteOrder, W: Write + Seek>(&self, wrt: &mut W) -> Result<()> {
+        self.header.write::<B, W>(wrt)?;
         let mut offsets = FileOffsets {
             morph_vert_mappings_offset: 0,
             morph_vert_data_offset: 0,
             bone_offsets: vec![0; self.bones.len()],
         };
         let offsets_struct_offset = wrt.stream_position()?;
-        offsets.write(wrt)?;
+        offsets.write::<B, W>(wrt)?;
 
         for (i, b) in self.bones.iter().enumerate() {
             let bone_offset = wrt.stream_position()?;
             offsets.bone_offsets[i] = bone_offset as i32;
-            b.write(wrt)?;
+            b.write::<B, W>(wrt)?;
         }
         offsets.morph_vert_mappings_offset = wrt.stream_position()? as i32;
         offsets.morph_vert_data_offset = wrt.stream_position()? as i32;
 
         wrt.seek(SeekFrom::Start(offsets_struct_offset))?;
-        offsets.write(wrt)?;
+        offsets.write::<B, W>(wrt)?;
         Ok(())
     }
+
+    /// Reads the file. Byte order is detected from the file signature.
+    pub fn read<R: Read + Seek>(rdr: &mut R) -> BinResult<Self> {
+        let start_pos = rdr.stream_position()?;
+        let magic: u32 = rdr.read_le()?;
+        rdr.seek(SeekFrom::Start(start_pos))?;
+        let endian = if magic.swap_bytes() == RFA_SIGNATURE {
+            Endian::Big
+        } else {
+            Endian::Little
+        };
+        let header: FileHeader = rdr.read_type(endian)?;
+        let num_bones = header.num_bones as usize;
+        let offsets: FileOffsets = rdr.read_type_args(endian, (num_bones,))?;
+        let mut bones = Vec::with_capacity(num_bones);
+        for bone_offset in &offsets.bone_offsets {
+            rdr.seek(SeekFrom::Start(*bone_offset as u64))?;
+            bones.push(rdr.read_type(endian)?);
+        }
+        Ok(Self { header, bones })
+    }
+
+    /// Checks if header is consistent with bone data. Returns an error listing all violations.
+    pub fn validate(&self) -> Result<()> {
+        let h = &self.header;
+        let mut violations = Vec::new();
+        if h.num_bones as usize != self.bones.len() {
+            violations.push(format!(
+                "header declares {} bones but the file contains {}",
+                h.num_bones,
+                self.bones.len()
+            ));
+        }
+        if h.start_time > h.end_time {
+            violations.push(format!(
+                "start time {} is greater than end time {}",
+                h.start_time, h.end_time
+            ));
+        }
+        if h.ramp_in_time < RfaTime::ZERO || h.ramp_out_time < RfaTime::ZERO {
+            violations.push(format!(
+                "negative ramp time (in {}, out {})",
+                h.ramp_in_time, h.ramp_out_time
+            ));
+        }
+        // times come from the file (or saturated conversion of huge GLTF times) so they can overflow
+        let ramp_time = h.ramp_in_time.checked_add(h.ramp_out_time);
+        match h.end_time.checked_sub(h.start_time) {
+            Some(duration) if ramp_time.is_some_and(|t| t <= duration) => {}
+            Some(duration) => violations.push(format!(
+                "ramp in time {} and ramp out time {} exceed duration {}",
+                h.ramp_in_time, h.ramp_out_time, duration
+            )),
+            None => violations.push(format!(
+                "duration of time range {}-{} is out of range",
+                h.start_time, h.end_time
+            )),
+        }
+        let time_range = h.start_time..=h.end_time;
+        for (i, b) in self.bones.iter().enumerate() {
+            let key_times = b
+                .rotation_keys
+                .iter()
+                .map(|k| k.time)
+                .chain(b.translation_keys.iter().map(|k| k.time));
+            let num_outside = key_times.filter(|t| !time_range.contains(t)).count();
+            if num_outside > 0 {
+                violations.push(format!(
+                    "bone {} has {} key(s) outside of time range {}-{}",
+                    i, num_outside, h.start_time, h.end_time
+                ));
+            }
+            let num_keys = b.rotation_keys.len().max(b.translation_keys.len());
+            if num_keys > MAX_BONE_KEYS {
+                violations.push(format!(
+                    "bone {} has {} keys of one kind but at most {} are supported",
+                    i, num_keys, MAX_BONE_KEYS
+                ));
+            }
+        }
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(new_custom_error(format!(
+                "invalid RFA: {}",
+                violations.join("; ")
+            )))
+        }
+    }
+
+    /// Returns a human-readable description of the animation: header with times converted to seconds and
+    /// number of keys of every bone. The file does not store its frame rate so times are converted with `fps`.
+    pub fn summary(&self, fps: f32) -> String {
+        let h = &self.header;
+        let mut lines = vec![
+            format!("Version: {}", h.version),
+            format!("Start time: {}", format_ticks(h.start_time, fps)),
+            format!("End time: {}", format_ticks(h.end_time, fps)),
+            match h.end_time.checked_sub(h.start_time) {
+                Some(duration) => format!("Duration: {}", format_ticks(duration, fps)),
+                None => "Duration: invalid".to_owned(),
+            },
+            format!("Ramp in time: {}", format_ticks(h.ramp_in_time, fps)),
+            format!("Ramp out time: {}", format_ticks(h.ramp_out_time, fps)),
+            format!("Total rotation: {:?}", h.total_rotation),
+            format!("Total translation: {:?}", h.total_translation),
+            format!("Bones: {}", self.bones.len()),
+        ];
+        for (i, bone) in self.bones.iter().enumerate() {
+            lines.push(format!(
+                "Bone {}: weight {}, {} rotation keys, {} translation keys",
+                i,
+                bone.weight,
+                bone.rotation_keys.len(),
+                bone.translation_keys.len()
+            ));
+        }
+        lines.join("\n")
+    }
+
+    pub fn to_bytes(&self, endian: Endian) -> Result<Vec<u8>> {
+        let mut cursor = Cursor::new(Vec::new());
+        self.write(&mut cursor, endian)?;
+        Ok(cursor.into_inner())
+    }
 }
 
+// Note: there is no flag for additive animations. RF decides how animations are blended (state or action)
+// in the game code
+#[binread]
 #[derive(Debug, Default)]
+#[br(magic = 0x4656_4D56_u32)] // RFA_SIGNATURE
 pub struct FileHeader {
-    pub magic: u32,
+    #[br(assert(version == RFA_VERSION, "unsupported RFA version {}", version))]
     pub version: i32,
     pub pos_reduction: f32,
     pub rot_reduction: f32,
-    pub start_time: i32,
-    pub end_time: i32,
+    pub start_time: RfaTime,
+    pub end_time: RfaTime,
     pub num_bones: i32,
     pub num_morph_vertices: i32,
     pub num_morph_keyframes: i32,
-    pub ramp_in_time: i32,
-    pub ramp_out_time: i32,
+    pub ramp_in_time: RfaTime,
+    pub ramp_out_time: RfaTime,
     pub total_rotation: [f32; 4],
     pub total_translation: [f32; 3],
 }
 
 impl FileHeader {
-    pub fn write<W: Write>(&self, wrt: &mut W) -> Result<()> {
-        wrt.write_u32::<LittleEndian>(RFA_SIGNATURE)?;
-        wrt.write_i32::<LittleEndian>(RFA_VERSION)?;
-        wrt.write_f32::<LittleEndian>(self.pos_reduction)?;
-        wrt.write_f32::<LittleEndian>(self.rot_reduction)?;
-        wrt.write_i32::<LittleEndian>(self.start_time)?;
-        wrt.write_i32::<LittleEndian>(self.end_time)?;
-        wrt.write_i32::<LittleEndian>(self.num_bones)?;
-        wrt.write_i32::<LittleEndian>(self.num_morph_vertices)?;
-        wrt.write_i32::<LittleEndian>(self.num_morph_keyframes)?;
-        wrt.write_i32::<LittleEndian>(self.ramp_in_time)?;
-        wrt.write_i32::<LittleEndian>(self.ramp_out_time)?;
-        wrt.write_f32_slice::<LittleEndian>(&self.total_rotation)?;
-        wrt.write_f32_slice::<LittleEndian>(&self.total_translation)?;
+    pub fn write<B: ByteOrder, W: Write>(&self, wrt: &mut W) -> Result<()> {
+        wrt.write_u32::<B>(RFA_SIGNATURE)?;
+        wrt.write_i32::<B>(RFA_VERSION)?;
+        wrt.write_f32::<B>(self.pos_reduction)?;
+        wrt.write_f32::<B>(self.rot_reduction)?;
+        wrt.write_i32::<B>(self.start_time.ticks())?;
+        wrt.write_i32::<B>(self.end_time.ticks())?;
+        wrt.write_i32::<B>(self.num_bones)?;
+        wrt.write_i32::<B>(self.num_morph_vertices)?;
+        wrt.write_i32::<B>(self.num_morph_keyframes)?;
+        wrt.write_i32::<B>(self.ramp_in_time.ticks())?;
+        wrt.write_i32::<B>(self.ramp_out_time.ticks())?;
+        wrt.write_f32_slice::<B>(&self.total_rotation)?;
+        wrt.write_f32_slice::<B>(&self.total_translation)?;
         Ok(())
     }
 }
 
+#[binread]
+#[br(import(num_bones: usize))]
 pub struct FileOffsets {
     pub morph_vert_mappings_offset: i32,
     pub morph_vert_data_offset: i32,
+    #[br(count = num_bones)]
     pub bone_offsets: Vec<i32>,
 }
 
 impl FileOffsets {
-    pub fn write<W: Write>(&self, wrt: &mut W) -> Result<()> {
-        wrt.write_i32::<LittleEndian>(self.morph_vert_mappings_offset)?;
-        wrt.write_i32::<LittleEndian>(self.morph_vert_data_offset)?;
+    pub fn write<B: ByteOrder, W: Write>(&self, wrt: &mut W) -> Result<()> {
+        wrt.write_i32::<B>(self.morph_vert_mappings_offset)?;
+        wrt.write_i32::<B>(self.morph_vert_data_offset)?;
         for o in &self.bone_offsets {
-            wrt.write_i32::<LittleEndian>(*o)?;
+            wrt.write_i32::<B>(*o)?;
         }
         Ok(())
     }
 }
 
+// Note: RFA format has no scale keys
+#[binread]
+#[derive(Clone)]
 pub struct Bone {
     pub weight: f32,
+    #[br(temp)]
+    num_rotation_keys: i16,
+    #[br(temp)]
+    num_translation_keys: i16,
+    #[br(count = num_rotation_keys.max(0))]
     pub rotation_keys: Vec<RotationKey>,
+    #[br(count = num_translation_keys.max(0))]
     pub translation_keys: Vec<TranslationKey>,
 }
 
 impl Bone {
-    pub fn write<W: Write>(&self, wrt: &mut W) -> Result<()> {
-        wrt.write_f32::<LittleEndian>(self.weight)?;
-        wrt.write_i16::<LittleEndian>(self.rotation_keys.len() as i16)?;
-        wrt.write_i16::<LittleEndian>(self.translation_keys.len() as i16)?;
+    /// Size of the bone data in the file (weight, key counts and keys)
+    pub fn size(&self) -> usize {
+        8 + self.rotation_keys.len() * RotationKey::SIZE
+            + self.translation_keys.len() * TranslationKey::SIZE
+    }
+
+    pub fn write<B: ByteOrder, W: Write>(&self, wrt: &mut W) -> Result<()> {
+        wrt.write_f32::<B>(self.weight)?;
+        let count_error = |len: usize| {
+            new_custom_error(format!(
+                "bone has {} keys of one kind but at most {} are supported",
+                len, MAX_BONE_KEYS
+            ))
+        };
+        let num_rotation_keys = self.rotation_keys.len();
+        let num_translation_keys = self.translation_keys.len();
+        wrt.write_i16::<B>(
+            i16::try_from(num_rotation_keys).map_err(|_| count_error(num_rotation_keys))?,
+        )?;
+        wrt.write_i16::<B>(
+            i16::try_from(num_translation_keys).map_err(|_| count_error(num_translation_keys))?,
+        )?;
         for k in &self.rotation_keys {
-            k.write(wrt)?;
+            k.write::<B, W>(wrt)?;
         }
         for k in &self.translation_keys {
-            k.write(wrt)?;
+            k.write::<B, W>(wrt)?;
         }
         Ok(())
     }
 }
 
+#[binread]
+#[derive(Clone, Debug)]
 pub struct RotationKey {
-    pub time: i32,
-    pub rotation: [i16; 4],
+    pub time: RfaTime,
+    pub rotation: ShortQuat,
     pub ease_in: i8,
+    #[br(pad_after = 2)]
     pub ease_out: i8,
 }
 
 impl RotationKey {
-    pub fn write<W: Write>(&self, wrt: &mut W) -> Result<()> {
-        wrt.write_i32::<LittleEndian>(self.time)?;
-        wrt.write_i16_slice::<LittleEndian>(&self.rotation)?;
+    pub const SIZE: usize = 16;
+
+    pub fn write<B: ByteOrder, W: Write>(&self, wrt: &mut W) -> Result<()> {
+        wrt.write_i32::<B>(self.time.ticks())?;
+        wrt.write_i16_slice::<B>(&self.rotation.0)?;
         wrt.write_i8(self.ease_in)?;
         wrt.write_i8(self.ease_out)?;
-        wrt.write_i16::<LittleEndian>(0)?; // pad
+        wrt.write_i16::<B>(0)?; // pad
         Ok(())
     }
 }
 
+#[binread]
+#[derive(Clone, Debug)]
 pub struct TranslationKey {
-    pub time: i32,
+    pub time: RfaTime,
     pub translation: [f32; 3],
     pub in_tangent: [f32; 3],
     pub out_tangent: [f32; 3],
 }
 
 impl TranslationKey {
-    pub fn write<W: Write>(&self, wrt: &mut W) -> Result<()> {
-        wrt.write_i32::<LittleEndian>(self.time)?;
-        wrt.write_f32_slice::<LittleEndian>(&self.translation)?;
-        wrt.write_f32_slice::<LittleEndian>(&self.in_tangent)?;
-        wrt.write_f32_slice::<LittleEndian>(&self.out_tangent)?;
+    pub const SIZE: usize = 40;
+
+    pub fn write<B: ByteOrder, W: Write>(&self, wrt: &mut W) -> Result<()> {
+        wrt.write_i32::<B>(self.time.ticks())?;
+        wrt.write_f32_slice::<B>(&self.translation)?;
+        wrt.write_f32_slice::<B>(&self.in_tangent)?;
+        wrt.write_f32_slice::<B>(&self.out_tangent)?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rfa_time_converts_seconds_at_frame_rate() {
+        assert_eq!(
+            RfaTime::from_seconds(1.0, 30.0),
+            RfaTime(RFA_TICKS_PER_SECOND)
+        );
+        assert_eq!(RfaTime::from_seconds(0.5, 60.0).ticks(), 4800);
+        // rounded instead of truncated
+        assert_eq!(RfaTime::from_seconds(0.9999 / 30.0, 30.0).ticks(), 160);
+        assert_eq!(RfaTime(2400).to_seconds(30.0), 0.5);
+    }
+
+    #[test]
+    fn rfa_time_checked_math_detects_overflow() {
+        assert_eq!(RfaTime(4800).checked_sub(RfaTime(480)), Some(RfaTime(4320)));
+        assert_eq!(
+            RfaTime(2_000_000_000).checked_sub(RfaTime(-2_000_000_000)),
+            None
+        );
+        assert_eq!(RfaTime(i32::MAX).checked_add(RfaTime(1)), None);
+    }
+
+    #[test]
+    fn summary_converts_times_with_frame_rate_and_does_not_overflow() {
+        let rfa = File {
+            header: FileHeader {
+                start_time: RfaTime(-2_000_000_000),
+                end_time: RfaTime(2_000_000_000),
+                ramp_in_time: RfaTime(4800),
+                ..FileHeader::default()
+            },
+            bones: Vec::new(),
+        };
+        let summary = rfa.summary(60.0);
+        assert!(summary.contains("Duration: invalid"), "{}", summary);
+        assert!(
+            summary.contains("Ramp in time: 4800 ticks (0.500 s at 60 FPS)"),
+            "{}",
+            summary
+        );
+    }
+
+    #[test]
+    fn validate_reports_overflowing_times() {
+        let mut rfa = File {
+            header: FileHeader {
+                start_time: RfaTime(-2_000_000_000),
+                end_time: RfaTime(2_000_000_000),
+                ..FileHeader::default()
+            },
+            bones: Vec::new(),
+        };
+        let err = rfa.validate().unwrap_err().to_string();
+        assert!(err.contains("is out of range"), "{}", err);
+        rfa.header.start_time = RfaTime::ZERO;
+        rfa.header.ramp_in_time = RfaTime(i32::MAX);
+        rfa.header.ramp_out_time = RfaTime(i32::MAX);
+        let err = rfa.validate().unwrap_err().to_string();
+        assert!(err.contains("exceed duration"), "{}", err);
+    }
+
+    #[test]
+    fn read_checks_signature_in_both_byte_orders() {
+        let rfa = File {
+            header: FileHeader::default(),
+            bones: Vec::new(),
+        };
+        for endian in [Endian::Little, Endian::Big] {
+            let mut data = rfa.to_bytes(endian).unwrap();
+            assert!(File::read(&mut Cursor::new(&data)).is_ok());
+            data[0] ^= 0xFF;
+            assert!(File::read(&mut Cursor::new(&data)).is_err());
+        }
+    }
+
+    #[test]
+    fn too_many_bone_keys_are_rejected() {
+        let key = RotationKey {
+            time: RfaTime::ZERO,
+            rotation: ShortQuat([0, 0, 0, 0x4000]),
+            ease_in: 0,
+            ease_out: 0,
+        };
+        let rfa = File {
+            header: FileHeader {
+                num_bones: 1,
+                ..FileHeader::default()
+            },
+            bones: vec![Bone {
+                weight: 1.0,
+                rotation_keys: vec![key; MAX_BONE_KEYS + 1],
+                translation_keys: Vec::new(),
+            }],
+        };
+        let err = rfa.validate().unwrap_err().to_string();
+        assert!(err.contains("bone 0 has 32768 keys"), "{}", err);
+        // the count would wrap around if it was written
+        assert!(rfa.to_bytes(Endian::Little).is_err());
+    }
+
+    #[test]
+    fn short_quat_rejects_zero_and_nan_quaternions() {
+        assert_eq!(
+            ShortQuat::from_quat(glam::Quat::from_xyzw(0.0, 0.0, 0.0, 0.0)),
+            None
+        );
+        assert_eq!(
+            ShortQuat::from_quat(glam::Quat::from_xyzw(f32::NAN, 0.0, 0.0, 1.0)),
+            None
+        );
+    }
+
+    #[test]
+    fn short_quat_packs_identity_exactly() {
+        assert_eq!(
+            ShortQuat::from_quat(glam::Quat::IDENTITY).unwrap(),
+            ShortQuat::IDENTITY
+        );
+        assert_eq!(ShortQuat::IDENTITY.0, [0, 0, 0, 16383]);
+    }
+
+    #[test]
+    fn short_quat_round_trip_is_within_quantization_error() {
+        // rounding to the nearest step gives at most half of the step error per component
+        const MAX_ERROR: f32 = 0.5 / RFA_QUAT_SCALE;
+        let angle = std::f32::consts::FRAC_PI_4;
+        let quats = [
+            glam::Quat::from_rotation_x(angle),
+            glam::Quat::from_rotation_y(angle),
+            glam::Quat::from_rotation_z(angle),
+            glam::Quat::from_euler(glam::EulerRot::XYZ, 0.3, -1.2, 2.5),
+            glam::Quat::from_xyzw(-0.5, 0.5, -0.5, 0.5),
+        ];
+        for quat in quats {
+            let packed = ShortQuat::from_quat(quat).unwrap();
+            let unpacked = glam::Vec4::from(packed.0.map(|x| x as f32 / RFA_QUAT_SCALE));
+            let diff = (unpacked - glam::Vec4::from(quat)).abs().max_element();
+            assert!(diff <= MAX_ERROR, "{:?} -> {:?}", quat, packed);
+            assert!((unpacked.length() - 1.0).abs() < 2.0 * MAX_ERROR);
+            assert!(packed.to_quat().angle_between(quat) < 0.001);
+        }
+    }
+}
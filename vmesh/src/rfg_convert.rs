@@ -3,12 +3,12 @@ use std::collections::HashMap;
 use glam::{Quat, Vec3};
 
 use crate::{
-    gltf_to_rf_face, gltf_to_rf_quat, gltf_to_rf_vec,
+    gltf_to_rf_face, gltf_to_rf_pos, gltf_to_rf_quat, gltf_to_rf_uv,
     io_utils::new_custom_error,
     material::get_material_base_color_texture_name,
     math_utils::{compute_triangle_plane, generate_uv},
     rfg::{Brush, Face, FaceVertex, Group, Rfg, Solid},
-    BoxResult, Context,
+    warn_about_extra_uv_sets, warn_about_vertex_colors, BoxResult, Context,
 };
 
 pub fn convert_gltf_to_rfg(doc: &gltf::Document, ctx: &Context) -> BoxResult<Rfg> {
@@ -41,6 +41,8 @@ fn create_brush(
     let mut vertices = Vec::new();
     let mut textures = Vec::new();
     let mut faces = Vec::new();
+    warn_about_extra_uv_sets(&mesh);
+    warn_about_vertex_colors(&mesh, ctx);
 
     for prim in mesh.primitives() {
         if prim.mode() != gltf::mesh::Mode::Triangles {
@@ -62,9 +64,9 @@ fn create_brush(
 
         let prim_v_index_to_brush_v_index: HashMap<usize, usize> = reader
             .read_positions()
-            .expect("mesh has no positions")
-            .map(gltf_to_rf_vec)
+            .ok_or_else(|| new_custom_error("mesh has no positions"))?
             .map(|v| (Vec3::from_array(v) * scale).to_array())
+            .map(|v| gltf_to_rf_pos(v, ctx.args.coordinate_convention, ctx.args.scale))
             .enumerate()
             .map(|(prim_v_index, prim_v)| {
                 for (brush_v_index, v) in vertices.iter().enumerate() {
@@ -78,15 +80,32 @@ fn create_brush(
             })
             .collect();
 
-        let uvs_opt: Option<Vec<_>> = reader
-            .read_tex_coords(0)
-            .map(|iter| iter.into_f32().collect());
+        let uvs_opt: Option<Vec<_>> = reader.read_tex_coords(0).map(|iter| {
+            iter.into_f32()
+                .map(|uv| gltf_to_rf_uv(uv, &ctx.args))
+                .collect()
+        });
 
         let indices: Vec<u32> = reader
             .read_indices()
-            .expect("mesh has no indices")
+            .ok_or_else(|| new_custom_error("mesh has no indices"))?
             .into_u32()
             .collect();
+        let num_prim_vertices = prim_v_index_to_brush_v_index.len();
+        if let Some(&index) = indices.iter().find(|&&i| i as usize >= num_prim_vertices) {
+            return Err(new_custom_error(format!(
+                "vertex index {} is out of range (mesh has {} vertices)",
+                index, num_prim_vertices
+            )));
+        }
+        if uvs_opt
+            .as_ref()
+            .is_some_and(|uvs| uvs.len() != num_prim_vertices)
+        {
+            return Err(new_custom_error(
+                "number of UVs does not match number of vertices",
+            ));
+        }
 
         indices
             .chunks_exact(3)
@@ -133,8 +152,9 @@ fn create_brush(
         vertices,
         faces,
     };
-    let pos = gltf_to_rf_vec(translation.to_array());
-    let orient_quat = gltf_to_rf_quat(rotation.to_array());
+    let convention = ctx.args.coordinate_convention;
+    let pos = gltf_to_rf_pos(translation.to_array(), convention, ctx.args.scale);
+    let orient_quat = gltf_to_rf_quat(rotation.to_array(), convention);
     let orient = glam::Mat3::from_quat(Quat::from_array(orient_quat)).to_cols_array();
     let brush = Brush {
         uid,
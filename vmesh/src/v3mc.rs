@@ -1,10 +1,8 @@
-use crate::io_utils::WriteExt;
-use byteorder::{LittleEndian, WriteBytesExt, ReadBytesExt};
+use crate::io_utils::{new_custom_error, ReadExt, WriteExt};
+use binrw::binread;
+use byteorder::{BigEndian, ByteOrder, LittleEndian, ReadBytesExt, WriteBytesExt};
 use std::convert::TryInto;
-use std::io::{Result, Seek, SeekFrom, Write, Read};
-use binrw::{
-    binread, BinWrite // trait for writing
-};
+use std::io::{Cursor, Read, Result, Seek, SeekFrom, Write};
 
 // File signatures
 pub const V3M_SIGNATURE: u32 = 0x5246_3344; // RF3D
@@ -22,7 +20,6 @@ pub const BONE_CHUNK: u32 = 0x424F_4E45; // 'BONE'
 pub const MAX_BONES: usize = 50;
 
 // Vif mesh flags
-#[allow(unused)]
 pub const VIF_MESH_FLAG_MORPH: u32 = 0x01;
 pub const VIF_MESH_FLAG_CHARACTER: u32 = 0x02;
 #[allow(unused)]
@@ -72,6 +69,76 @@ impl File {
         }
         .write(wrt)
     }
+
+    /// Reads the file. Byte order is detected from the file signature. Data blocks of meshes read from
+    /// a big-endian file are converted to little-endian so `Mesh::read_data_block` works for both.
+    pub fn read<R: Read + Seek>(rdr: &mut R) -> Result<Self> {
+        let start_pos = rdr.stream_position()?;
+        let signature = rdr.read_u32::<LittleEndian>()?;
+        rdr.seek(SeekFrom::Start(start_pos))?;
+        if ![V3M_SIGNATURE, V3C_SIGNATURE].contains(&signature.swap_bytes()) {
+            return Self::read_with_byte_order::<LittleEndian, R>(rdr);
+        }
+        let mut file = Self::read_with_byte_order::<BigEndian, R>(rdr)?;
+        for mesh in file
+            .lod_meshes
+            .iter_mut()
+            .flat_map(|lod_mesh| &mut lod_mesh.meshes)
+        {
+            let data_block =
+                MeshDataBlock::read::<BigEndian, _>(&mut Cursor::new(&mesh.data_block), mesh)?;
+            let mut cursor = Cursor::new(Vec::new());
+            data_block.write(&mut cursor)?;
+            mesh.data_block = cursor.into_inner();
+        }
+        Ok(file)
+    }
+
+    fn read_with_byte_order<B: ByteOrder, R: Read + Seek>(rdr: &mut R) -> Result<Self> {
+        let header = FileHeader::read::<B, _>(&mut *rdr)?;
+        let header_end_pos = rdr.stream_position()?;
+        let file_end_pos = rdr.seek(SeekFrom::End(0))?;
+        rdr.seek(SeekFrom::Start(header_end_pos))?;
+        let mut lod_meshes = Vec::new();
+        let mut cspheres = Vec::new();
+        let mut bones = Vec::new();
+        loop {
+            let chunk = FileChunk::read::<B, _>(rdr)?;
+            // sizes come from the file so they are checked before seeking (submesh chunks have size 0)
+            let chunk_data_pos = rdr.stream_position()?;
+            let remaining = file_end_pos.saturating_sub(chunk_data_pos);
+            if u64::from(chunk.chunk_size) > remaining {
+                return Err(new_custom_error(format!(
+                    "chunk {:#X} at offset {:#X} has size {} but only {} bytes remain in the file",
+                    chunk.chunk_type,
+                    chunk_data_pos - 8,
+                    chunk.chunk_size,
+                    remaining
+                )));
+            }
+            match chunk.chunk_type {
+                END_CHUNK => break,
+                SUBMESH_CHUNK => lod_meshes.push(LodMesh::read::<B, _>(rdr)?),
+                CSPHERE_CHUNK => cspheres.push(ColSphere::read::<B, _>(rdr)?),
+                BONE_CHUNK => {
+                    let num_bones = read_count::<B, _>(rdr)?;
+                    for _ in 0..num_bones {
+                        bones.push(Bone::read::<B, _>(rdr)?);
+                    }
+                }
+                _ => {
+                    // skip unsupported chunk
+                    rdr.seek(SeekFrom::Current(chunk.chunk_size.into()))?;
+                }
+            }
+        }
+        Ok(File {
+            header,
+            lod_meshes,
+            cspheres,
+            bones,
+        })
+    }
 }
 
 #[binread]
@@ -90,6 +157,34 @@ pub struct FileHeader {
 }
 
 impl FileHeader {
+    /// Returns a human-readable description of the header. Total vertex, face and mesh counts are only shown if
+    /// they are set because ccrunch resets them to 0.
+    pub fn summary(&self) -> String {
+        let format = match self.signature {
+            V3M_SIGNATURE => "V3M (static mesh)",
+            V3C_SIGNATURE => "V3C (character mesh)",
+            _ => "unknown",
+        };
+        let mut lines = vec![
+            format!("Format: {}", format),
+            format!("Version: {:#X}", self.version),
+            format!("LOD meshes: {}", self.num_lod_meshes),
+            format!("Materials: {}", self.num_all_materials),
+            format!("Collision spheres: {}", self.num_cspheres),
+        ];
+        let totals = [
+            ("Meshes", self.num_all_meshes),
+            ("Vertices", self.num_all_vertices),
+            ("Faces", self.num_all_faces),
+            ("Vertex normals", self.num_all_vertex_normals),
+            ("Dumbs", self.num_dumbs),
+        ];
+        for (name, count) in totals.into_iter().filter(|(_, count)| *count != 0) {
+            lines.push(format!("{}: {}", name, count));
+        }
+        lines.join("\n")
+    }
+
     pub fn write<W: Write>(&self, wrt: &mut W) -> Result<()> {
         wrt.write_u32::<LittleEndian>(self.signature)?;
         wrt.write_u32::<LittleEndian>(self.version)?;
@@ -103,18 +198,19 @@ impl FileHeader {
         wrt.write_i32::<LittleEndian>(self.num_cspheres)?;
         Ok(())
     }
-    pub fn read<R: Read>(mut reader: R) -> Result<Self> {
+
+    pub fn read<B: ByteOrder, R: Read>(mut reader: R) -> Result<Self> {
         Ok(FileHeader {
-            signature: reader.read_u32::<LittleEndian>()?,
-            version: reader.read_u32::<LittleEndian>()?,
-            num_lod_meshes: reader.read_i32::<LittleEndian>()?,
-            num_all_vertices: reader.read_i32::<LittleEndian>()?,
-            num_all_faces: reader.read_i32::<LittleEndian>()?,
-            num_all_vertex_normals: reader.read_i32::<LittleEndian>()?,
-            num_all_materials: reader.read_i32::<LittleEndian>()?,
-            num_all_meshes: reader.read_i32::<LittleEndian>()?,
-            num_dumbs: reader.read_i32::<LittleEndian>()?,
-            num_cspheres: reader.read_i32::<LittleEndian>()?,
+            signature: reader.read_u32::<B>()?,
+            version: reader.read_u32::<B>()?,
+            num_lod_meshes: reader.read_i32::<B>()?,
+            num_all_vertices: reader.read_i32::<B>()?,
+            num_all_faces: reader.read_i32::<B>()?,
+            num_all_vertex_normals: reader.read_i32::<B>()?,
+            num_all_materials: reader.read_i32::<B>()?,
+            num_all_meshes: reader.read_i32::<B>()?,
+            num_dumbs: reader.read_i32::<B>()?,
+            num_cspheres: reader.read_i32::<B>()?,
         })
     }
 }
@@ -153,6 +249,13 @@ impl FileChunk {
         wrt.seek(SeekFrom::Start(pos_after))?;
         Ok(())
     }
+
+    pub fn read<B: ByteOrder, R: Read>(rdr: &mut R) -> Result<Self> {
+        Ok(FileChunk {
+            chunk_type: rdr.read_u32::<B>()?,
+            chunk_size: rdr.read_u32::<B>()?,
+        })
+    }
 }
 
 pub struct LodMesh {
@@ -200,6 +303,44 @@ impl LodMesh {
 
         Ok(())
     }
+
+    pub fn read<B: ByteOrder, R: Read>(rdr: &mut R) -> Result<Self> {
+        let name = rdr.read_char_array(24)?;
+        let parent_name = rdr.read_char_array(24)?;
+        let version = rdr.read_i32::<B>()?;
+        let num_meshes = read_count::<B, _>(rdr)?;
+        let distances = (0..num_meshes)
+            .map(|_| rdr.read_f32::<B>())
+            .collect::<Result<Vec<_>>>()?;
+        let offset = rdr.read_f32_array::<B, _>()?;
+        let radius = rdr.read_f32::<B>()?;
+        let bbox_min = rdr.read_f32_array::<B, _>()?;
+        let bbox_max = rdr.read_f32_array::<B, _>()?;
+        let meshes = (0..num_meshes)
+            .map(|_| Mesh::read::<B, _>(rdr))
+            .collect::<Result<Vec<_>>>()?;
+        let num_materials = read_count::<B, _>(rdr)?;
+        let materials = (0..num_materials)
+            .map(|_| Material::read::<B, _>(rdr))
+            .collect::<Result<Vec<_>>>()?;
+        let num_unknown1 = read_count::<B, _>(rdr)?;
+        for _ in 0..num_unknown1 {
+            rdr.read_char_array(24)?; // unknown1[i].unknown0
+            rdr.read_f32::<B>()?; // unknown1[i].unknown1
+        }
+        Ok(LodMesh {
+            name,
+            parent_name,
+            version,
+            distances,
+            offset,
+            radius,
+            bbox_min,
+            bbox_max,
+            meshes,
+            materials,
+        })
+    }
 }
 
 pub struct Mesh {
@@ -237,6 +378,46 @@ impl Mesh {
 
         Ok(())
     }
+
+    pub fn read<B: ByteOrder, R: Read>(rdr: &mut R) -> Result<Self> {
+        let flags = rdr.read_u32::<B>()?;
+        let num_vecs = rdr.read_i32::<B>()?;
+        let num_chunks = rdr.read_u16::<B>()?;
+        let data_block_size = read_count::<B, _>(rdr)?;
+        // do not allocate the declared size upfront because it may be bogus
+        let mut data_block = Vec::new();
+        rdr.by_ref()
+            .take(data_block_size as u64)
+            .read_to_end(&mut data_block)?;
+        if data_block.len() != data_block_size {
+            return Err(new_custom_error(format!(
+                "mesh data block declares size of {} bytes but only {} bytes remain in the file",
+                data_block_size,
+                data_block.len()
+            )));
+        }
+        rdr.read_i32::<B>()?; // unknown1
+        let chunks = (0..num_chunks)
+            .map(|_| MeshChunk::read::<B, _>(rdr))
+            .collect::<Result<Vec<_>>>()?;
+        let num_prop_points = rdr.read_i32::<B>()?;
+        let num_textures = read_count::<B, _>(rdr)?;
+        let textures = (0..num_textures)
+            .map(|_| MeshTextureRef::read(rdr))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Mesh {
+            flags,
+            num_vecs,
+            chunks,
+            data_block,
+            num_prop_points,
+            textures,
+        })
+    }
+
+    pub fn read_data_block(&self) -> Result<MeshDataBlock> {
+        MeshDataBlock::read::<LittleEndian, _>(&mut Cursor::new(&self.data_block), self)
+    }
 }
 
 pub struct MeshChunk {
@@ -262,6 +443,19 @@ impl MeshChunk {
         wrt.write_u32::<LittleEndian>(self.render_mode)?;
         Ok(())
     }
+
+    pub fn read<B: ByteOrder, R: Read>(rdr: &mut R) -> Result<Self> {
+        Ok(MeshChunk {
+            num_vecs: rdr.read_u16::<B>()?,
+            num_faces: rdr.read_u16::<B>()?,
+            vecs_alloc: rdr.read_u16::<B>()?,
+            faces_alloc: rdr.read_u16::<B>()?,
+            same_pos_vertex_offsets_alloc: rdr.read_u16::<B>()?,
+            wi_alloc: rdr.read_u16::<B>()?,
+            uvs_alloc: rdr.read_u16::<B>()?,
+            render_mode: rdr.read_u32::<B>()?,
+        })
+    }
 }
 
 pub struct MeshTextureRef {
@@ -276,6 +470,13 @@ impl MeshTextureRef {
         wrt.write_u8(0)?;
         Ok(())
     }
+
+    pub fn read<R: Read>(rdr: &mut R) -> Result<Self> {
+        Ok(MeshTextureRef {
+            material_index: rdr.read_u8()?,
+            tex_name: rdr.read_cstr()?,
+        })
+    }
 }
 
 pub struct MeshDataBlock {
@@ -303,6 +504,27 @@ impl MeshDataBlock {
         }
         Ok(())
     }
+
+    pub fn read<B: ByteOrder, R: Read + Seek>(rdr: &mut R, mesh: &Mesh) -> Result<Self> {
+        let chunks = (0..mesh.chunks.len())
+            .map(|_| MeshDataBlockChunkInfo::read::<B, _>(rdr))
+            .collect::<Result<Vec<_>>>()?;
+        skip_v3mc_data_block_padding(rdr)?;
+        let chunks_data = mesh
+            .chunks
+            .iter()
+            .map(|chunk| MeshChunkData::read::<B, _>(rdr, chunk, mesh.flags))
+            .collect::<Result<Vec<_>>>()?;
+        skip_v3mc_data_block_padding(rdr)?;
+        let prop_points = (0..mesh.num_prop_points)
+            .map(|_| PropPoint::read::<B, _>(rdr))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(MeshDataBlock {
+            chunks,
+            chunks_data,
+            prop_points,
+        })
+    }
 }
 
 fn write_v3mc_data_block_padding<W: Write + Seek>(wrt: &mut W) -> std::io::Result<()> {
@@ -314,6 +536,19 @@ fn write_v3mc_data_block_padding<W: Write + Seek>(wrt: &mut W) -> std::io::Resul
     Ok(())
 }
 
+fn skip_v3mc_data_block_padding<R: Seek>(rdr: &mut R) -> std::io::Result<()> {
+    let pos = rdr.stream_position()?;
+    rdr.seek(SeekFrom::Start(pos.next_multiple_of(0x10)))?;
+    Ok(())
+}
+
+fn read_count<B: ByteOrder, R: Read>(rdr: &mut R) -> Result<usize> {
+    let count = rdr.read_i32::<B>()?;
+    count
+        .try_into()
+        .map_err(|_| new_custom_error(format!("invalid element count: {}", count)))
+}
+
 pub struct MeshDataBlockChunkInfo {
     pub texture_index: i32,
 }
@@ -330,6 +565,15 @@ impl MeshDataBlockChunkInfo {
         wrt.write_all(&unused_24)?;
         Ok(())
     }
+
+    pub fn read<B: ByteOrder, R: Read>(rdr: &mut R) -> Result<Self> {
+        let mut unused_0 = [0_u8; 0x20];
+        rdr.read_exact(&mut unused_0)?;
+        let texture_index = rdr.read_i32::<B>()?;
+        let mut unused_24 = [0_u8; 0x38 - 0x24];
+        rdr.read_exact(&mut unused_24)?;
+        Ok(MeshDataBlockChunkInfo { texture_index })
+    }
 }
 
 pub struct MeshChunkData {
@@ -387,6 +631,72 @@ impl MeshChunkData {
         // }
         Ok(())
     }
+
+    pub fn read<B: ByteOrder, R: Read + Seek>(
+        rdr: &mut R,
+        chunk: &MeshChunk,
+        mesh_flags: u32,
+    ) -> Result<Self> {
+        let num_vecs = usize::from(chunk.num_vecs);
+        let num_faces = usize::from(chunk.num_faces);
+
+        let vecs = (0..num_vecs)
+            .map(|_| rdr.read_f32_array::<B, _>())
+            .collect::<Result<Vec<_>>>()?;
+        skip_v3mc_data_block_padding(rdr)?;
+
+        let norms = (0..num_vecs)
+            .map(|_| rdr.read_f32_array::<B, _>())
+            .collect::<Result<Vec<_>>>()?;
+        skip_v3mc_data_block_padding(rdr)?;
+
+        let uvs = (0..num_vecs)
+            .map(|_| rdr.read_f32_array::<B, _>())
+            .collect::<Result<Vec<_>>>()?;
+        skip_v3mc_data_block_padding(rdr)?;
+
+        let faces = (0..num_faces)
+            .map(|_| MeshFace::read::<B, _>(rdr))
+            .collect::<Result<Vec<_>>>()?;
+        skip_v3mc_data_block_padding(rdr)?;
+
+        let num_face_planes = if mesh_flags & VIF_MESH_FLAG_FACE_PLANES != 0 {
+            num_faces
+        } else {
+            0
+        };
+        let face_planes = (0..num_face_planes)
+            .map(|_| rdr.read_f32_array::<B, _>())
+            .collect::<Result<Vec<_>>>()?;
+        skip_v3mc_data_block_padding(rdr)?;
+
+        let same_pos_vertex_offsets = (0..chunk.same_pos_vertex_offsets_alloc / 2)
+            .map(|_| rdr.read_i16::<B>())
+            .collect::<Result<Vec<_>>>()?;
+        skip_v3mc_data_block_padding(rdr)?;
+
+        let num_wi = if chunk.wi_alloc != 0 { num_vecs } else { 0 };
+        let wi = (0..num_wi)
+            .map(|_| WeightIndexArray::read(rdr))
+            .collect::<Result<Vec<_>>>()?;
+        skip_v3mc_data_block_padding(rdr)?;
+
+        if mesh_flags & VIF_MESH_FLAG_MORPH != 0 {
+            // skip morph_vertices_map
+            rdr.seek(SeekFrom::Current((num_vecs * 2) as i64))?;
+            skip_v3mc_data_block_padding(rdr)?;
+        }
+
+        Ok(MeshChunkData {
+            vecs,
+            norms,
+            uvs,
+            faces,
+            face_planes,
+            same_pos_vertex_offsets,
+            wi,
+        })
+    }
 }
 
 pub struct MeshFace {
@@ -404,6 +714,13 @@ impl MeshFace {
         wrt.write_u16::<LittleEndian>(self.flags)?;
         Ok(())
     }
+
+    pub fn read<B: ByteOrder, R: Read>(rdr: &mut R) -> Result<Self> {
+        let mut vindices = [0_u16; 3];
+        rdr.read_u16_into::<B>(&mut vindices)?;
+        let flags = rdr.read_u16::<B>()?;
+        Ok(MeshFace { vindices, flags })
+    }
 }
 
 #[derive(Clone, Copy, Debug, Default)]
@@ -413,6 +730,9 @@ pub struct WeightIndexArray {
 }
 
 impl WeightIndexArray {
+    /// Maximal number of bones influencing a vertex
+    pub const MAX_WEIGHTS: usize = 4;
+
     pub fn write<W: Write>(&self, wrt: &mut W) -> Result<()> {
         for w in &self.weights {
             wrt.write_u8(*w)?;
@@ -422,6 +742,14 @@ impl WeightIndexArray {
         }
         Ok(())
     }
+
+    pub fn read<R: Read>(rdr: &mut R) -> Result<Self> {
+        let mut weights = [0_u8; 4];
+        rdr.read_exact(&mut weights)?;
+        let mut indices = [0_u8; 4];
+        rdr.read_exact(&mut indices)?;
+        Ok(WeightIndexArray { weights, indices })
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -440,6 +768,15 @@ impl PropPoint {
         wrt.write_i32::<LittleEndian>(self.parent_index)?;
         Ok(())
     }
+
+    pub fn read<B: ByteOrder, R: Read>(rdr: &mut R) -> Result<Self> {
+        Ok(PropPoint {
+            name: rdr.read_char_array(0x44)?,
+            orient: rdr.read_f32_array::<B, _>()?,
+            pos: rdr.read_f32_array::<B, _>()?,
+            parent_index: rdr.read_i32::<B>()?,
+        })
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -458,6 +795,15 @@ impl ColSphere {
         wrt.write_f32::<LittleEndian>(self.radius)?;
         Ok(())
     }
+
+    pub fn read<B: ByteOrder, R: Read>(rdr: &mut R) -> Result<Self> {
+        Ok(ColSphere {
+            name: rdr.read_char_array(24)?,
+            parent_index: rdr.read_i32::<B>()?,
+            pos: rdr.read_f32_array::<B, _>()?,
+            radius: rdr.read_f32::<B>()?,
+        })
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -476,6 +822,15 @@ impl Bone {
         wrt.write_i32::<LittleEndian>(self.parent_index)?;
         Ok(())
     }
+
+    pub fn read<B: ByteOrder, R: Read>(rdr: &mut R) -> Result<Self> {
+        Ok(Bone {
+            name: rdr.read_char_array(24)?,
+            base_rotation: rdr.read_f32_array::<B, _>()?,
+            base_translation: rdr.read_f32_array::<B, _>()?,
+            parent_index: rdr.read_i32::<B>()?,
+        })
+    }
 }
 
 #[derive(Default, PartialEq)]
@@ -500,9 +855,21 @@ impl Material {
         wrt.write_u32::<LittleEndian>(self.flags)?;
         Ok(())
     }
+
+    pub fn read<B: ByteOrder, R: Read>(rdr: &mut R) -> Result<Self> {
+        Ok(Material {
+            tex_name: rdr.read_char_array(32)?,
+            self_illumination: rdr.read_f32::<B>()?,
+            specular_level: rdr.read_f32::<B>()?,
+            glossiness: rdr.read_f32::<B>()?,
+            reflection_amount: rdr.read_f32::<B>()?,
+            refl_tex_name: rdr.read_char_array(32)?,
+            flags: rdr.read_u32::<B>()?,
+        })
+    }
 }
 
-#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum TextureSource {
     None = 0,
     Wrap = 1,
@@ -512,7 +879,19 @@ pub enum TextureSource {
     // V3M and V3C support only a single diffuse texture
 }
 
-#[allow(dead_code)]
+impl TextureSource {
+    fn from_bits(bits: u32) -> Option<Self> {
+        match bits {
+            0 => Some(Self::None),
+            1 => Some(Self::Wrap),
+            2 => Some(Self::Clamp),
+            3 => Some(Self::ClampNoFiltering),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum ColorOp {
     SelectArg0IgnoreCurrentColor = 0x0,
     SelectArg0 = 0x1,
@@ -521,7 +900,20 @@ pub enum ColorOp {
     Mul2x = 0x4,
 }
 
-#[allow(dead_code)]
+impl ColorOp {
+    fn from_bits(bits: u32) -> Option<Self> {
+        match bits {
+            0x0 => Some(Self::SelectArg0IgnoreCurrentColor),
+            0x1 => Some(Self::SelectArg0),
+            0x2 => Some(Self::Mul),
+            0x3 => Some(Self::Add),
+            0x4 => Some(Self::Mul2x),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum AlphaOp {
     SelArg2 = 0x0,
     SelArg1 = 0x1,
@@ -529,7 +921,19 @@ pub enum AlphaOp {
     Mul = 0x3,
 }
 
-#[allow(dead_code)]
+impl AlphaOp {
+    fn from_bits(bits: u32) -> Option<Self> {
+        match bits {
+            0x0 => Some(Self::SelArg2),
+            0x1 => Some(Self::SelArg1),
+            0x2 => Some(Self::SelArg1IgnoreCurrentColor),
+            0x3 => Some(Self::Mul),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 #[allow(clippy::enum_variant_names)]
 pub enum AlphaBlend {
     None = 0x0,
@@ -542,7 +946,23 @@ pub enum AlphaBlend {
     SwappedSrcDestColor = 0x7,
 }
 
-#[allow(dead_code)]
+impl AlphaBlend {
+    fn from_bits(bits: u32) -> Option<Self> {
+        match bits {
+            0x0 => Some(Self::None),
+            0x1 => Some(Self::AlphaAdditive),
+            0x2 => Some(Self::SrcAlpha2),
+            0x3 => Some(Self::AlphaBlendAlpha),
+            0x4 => Some(Self::SrcAlpha4),
+            0x5 => Some(Self::DestColor),
+            0x6 => Some(Self::InvDestColor),
+            0x7 => Some(Self::SwappedSrcDestColor),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum ZbufferType {
     None = 0x0,
     Read = 0x1,
@@ -552,7 +972,21 @@ pub enum ZbufferType {
     FullAlphaTest = 0x5,
 }
 
-#[allow(dead_code)]
+impl ZbufferType {
+    fn from_bits(bits: u32) -> Option<Self> {
+        match bits {
+            0x0 => Some(Self::None),
+            0x1 => Some(Self::Read),
+            0x2 => Some(Self::ReadEqFunc),
+            0x3 => Some(Self::Write),
+            0x4 => Some(Self::Full),
+            0x5 => Some(Self::FullAlphaTest),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum FogType {
     Type0 = 0x0,
     Type1 = 0x1,
@@ -560,6 +994,18 @@ pub enum FogType {
     ForceOff = 0x3,
 }
 
+impl FogType {
+    fn from_bits(bits: u32) -> Option<Self> {
+        match bits {
+            0x0 => Some(Self::Type0),
+            0x1 => Some(Self::Type1),
+            0x2 => Some(Self::Type2),
+            0x3 => Some(Self::ForceOff),
+            _ => None,
+        }
+    }
+}
+
 pub fn encode_render_mode(
     tex_src: TextureSource,
     color_op: ColorOp,
@@ -575,3 +1021,90 @@ pub fn encode_render_mode(
         | ((zbuffer_type as u32) << 20)
         | ((fog as u32) << 25)
 }
+
+/// Fields of a render mode (see `encode_render_mode`). Fields with unknown values are `None`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RenderModeFields {
+    pub tex_src: Option<TextureSource>,
+    pub color_op: Option<ColorOp>,
+    pub alpha_op: Option<AlphaOp>,
+    pub alpha_blend: Option<AlphaBlend>,
+    pub zbuffer_type: Option<ZbufferType>,
+    pub fog: Option<FogType>,
+}
+
+pub fn decode_render_mode(render_mode: u32) -> RenderModeFields {
+    RenderModeFields {
+        tex_src: TextureSource::from_bits(render_mode & 0x1F),
+        color_op: ColorOp::from_bits((render_mode >> 5) & 0x1F),
+        alpha_op: AlphaOp::from_bits((render_mode >> 10) & 0x1F),
+        alpha_blend: AlphaBlend::from_bits((render_mode >> 15) & 0x1F),
+        zbuffer_type: ZbufferType::from_bits((render_mode >> 20) & 0x1F),
+        fog: FogType::from_bits((render_mode >> 25) & 0x1F),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{load_test_gltf, v3mc_convert};
+
+    const SKINNED_ANIM_GLTF: &[u8] = include_bytes!("../tests/data/skinned_anim.gltf");
+
+    /// Converts the skinned fixture and returns content of the V3C file
+    fn make_v3c_data() -> Vec<u8> {
+        let (document, ctx) = load_test_gltf(SKINNED_ANIM_GLTF, &[]).unwrap();
+        let v3c = v3mc_convert::convert_gltf_to_v3mc(&document, &ctx).unwrap();
+        let mut cursor = Cursor::new(Vec::new());
+        v3c.write(&mut cursor).unwrap();
+        cursor.into_inner()
+    }
+
+    #[test]
+    fn render_mode_fields_are_decoded() {
+        // paper1.v3m
+        let fields = decode_render_mode(0x518C41);
+        assert_eq!(
+            fields,
+            RenderModeFields {
+                tex_src: Some(TextureSource::Wrap),
+                color_op: Some(ColorOp::Mul),
+                alpha_op: Some(AlphaOp::Mul),
+                alpha_blend: Some(AlphaBlend::AlphaBlendAlpha),
+                zbuffer_type: Some(ZbufferType::FullAlphaTest),
+                fog: Some(FogType::Type0),
+            }
+        );
+        // multi-texturing source is not supported
+        assert_eq!(decode_render_mode(0x400C45).tex_src, None);
+    }
+
+    #[test]
+    fn truncated_and_corrupted_v3c_files_are_rejected() {
+        let data = make_v3c_data();
+        assert!(File::read(&mut Cursor::new(&data)).is_ok());
+
+        for len in 0..data.len() {
+            assert!(File::read(&mut Cursor::new(&data[..len])).is_err());
+        }
+        // any result is fine as long as reading does not panic or allocate declared sizes
+        for i in 0..data.len() - 3 {
+            let mut corrupted = data.clone();
+            corrupted[i..i + 4].copy_from_slice(&0x7FFF_FFFF_u32.to_le_bytes());
+            let _ = File::read(&mut Cursor::new(&corrupted));
+        }
+
+        let bone_chunk_pos = data
+            .windows(4)
+            .position(|w| w == BONE_CHUNK.to_le_bytes())
+            .unwrap();
+        let mut corrupted = data.clone();
+        corrupted[bone_chunk_pos + 4..bone_chunk_pos + 8].copy_from_slice(&[0xFF; 4]);
+        let err = File::read(&mut Cursor::new(&corrupted)).err().unwrap();
+        assert!(
+            err.to_string().contains("bytes remain in the file"),
+            "{}",
+            err
+        );
+    }
+}
@@ -1,3 +1,4 @@
+use crate::build_child_nodes_indices;
 use crate::char_anim;
 use crate::count_mesh_vertices;
 use crate::extract_translation_from_matrix;
@@ -6,7 +7,9 @@ use crate::get_node_local_transform;
 use crate::get_primitive_vertex_count;
 use crate::get_submesh_nodes;
 use crate::gltf_to_rf_face;
+use crate::gltf_to_rf_pos;
 use crate::gltf_to_rf_quat;
+use crate::gltf_to_rf_uv;
 use crate::gltf_to_rf_vec;
 use crate::io_utils::new_custom_error;
 use crate::material;
@@ -15,6 +18,8 @@ use crate::math_utils::{
     compute_triangle_plane, generate_uv, get_vector_len, transform_normal, transform_point, Matrix3,
 };
 use crate::v3mc;
+use crate::warn_about_extra_uv_sets;
+use crate::warn_about_vertex_colors;
 use crate::Context;
 use serde_derive::Deserialize;
 use std::convert::TryInto;
@@ -66,11 +71,13 @@ fn compute_mesh_bbox(
         max: [f32::MIN; 3],
     };
     // Calculate AABB manually using vertex position data
+    let convention = ctx.args.coordinate_convention;
     for prim in mesh.primitives() {
         let reader = prim.reader(|buffer| ctx.get_buffer_data(buffer));
         if let Some(iter) = reader.read_positions() {
             for pos in iter {
-                let tpos = gltf_to_rf_vec(transform_point(&pos, transform));
+                let tpos =
+                    gltf_to_rf_pos(transform_point(&pos, transform), convention, ctx.args.scale);
                 #[allow(clippy::needless_range_loop)]
                 for i in 0..3 {
                     aabb.min[i] = aabb.min[i].min(tpos[i]);
@@ -94,11 +101,9 @@ fn compute_mesh_bounding_sphere_radius(
             for pos in iter {
                 let tpos = transform_point(&pos, transform);
                 let diff = [tpos[0], tpos[1], tpos[2]];
-                let dist = get_vector_len(&diff);
+                let dist = get_vector_len(&diff) * ctx.args.scale;
                 radius = radius.max(dist);
             }
-        } else {
-            panic!("mesh has no positions");
         }
     }
     radius
@@ -107,59 +112,189 @@ fn compute_mesh_bounding_sphere_radius(
 fn create_mesh_chunk_info(
     prim: &gltf::Primitive,
     materials: &[gltf::Material],
-) -> v3mc::MeshDataBlockChunkInfo {
+) -> std::io::Result<v3mc::MeshDataBlockChunkInfo> {
     // write texture index in LOD model textures array
     let prim_mat = prim.material();
     let texture_index = materials
         .iter()
         .position(|m| m.index() == prim_mat.index())
-        .expect("cannot find texture") as i32;
+        .ok_or_else(|| new_custom_error("cannot find texture"))? as i32;
 
-    v3mc::MeshDataBlockChunkInfo { texture_index }
+    Ok(v3mc::MeshDataBlockChunkInfo { texture_index })
+}
+
+/// Converts float weights summing to 1 into bytes summing to 255. Weights must be sorted in descending order.
+fn quantize_vertex_weights(weights: &[f32]) -> [u8; 4] {
+    let mut result = [0_u8; 4];
+    for (i, w) in weights.iter().enumerate() {
+        result[i] = (w * 255.0_f32).round() as u8;
+    }
+    // fix rounding error on the biggest weight
+    let sum: i32 = result.iter().map(|&w| i32::from(w)).sum();
+    if !weights.is_empty() {
+        result[0] = (i32::from(result[0]) + 255 - sum).clamp(0, 255) as u8;
+    }
+    result
+}
+
+/// Reads all JOINTS_n/WEIGHTS_n sets of the primitive and converts them to RF vertex weights. Joint indices are
+/// remapped to exported bones. RF supports up to 4 weights per vertex so if a vertex is influenced by more bones
+/// the smallest weights are dropped and the remaining ones are renormalized.
+fn convert_vertex_weights<'a, 's, F>(
+    reader: &gltf::mesh::Reader<'a, 's, F>,
+    nv: usize,
+    ctx: &Context,
+) -> std::io::Result<Vec<v3mc::WeightIndexArray>>
+where
+    F: Clone + Fn(gltf::Buffer<'a>) -> Option<&'s [u8]>,
+{
+    let mut influences: Vec<Vec<(usize, f32)>> = vec![Vec::new(); nv];
+    let mut num_dropped_weights = 0;
+    let mut set = 0;
+    while let Some(joints) = reader.read_joints(set) {
+        let weights = reader
+            .read_weights(set)
+            .ok_or_else(|| new_custom_error(format!("mesh has JOINTS_{} but no WEIGHTS_{}", set, set)))?
+            .into_f32();
+        let joints = joints.into_u16();
+        if joints.len() != nv || weights.len() != nv {
+            return Err(new_custom_error(format!(
+                "mesh has {} JOINTS_{} and {} WEIGHTS_{} values for {} vertices",
+                joints.len(),
+                set,
+                weights.len(),
+                set,
+                nv
+            )));
+        }
+        for (vertex_influences, (joint_indices, joint_weights)) in influences.iter_mut().zip(joints.zip(weights))
+        {
+            for (&joint_index, &weight) in joint_indices.iter().zip(&joint_weights) {
+                if weight <= 0.0_f32 {
+                    continue;
+                }
+                let joint_index = usize::from(joint_index);
+                let bone_index = if ctx.is_character {
+                    ctx.bone_map.get_bone_index(joint_index)
+                } else {
+                    Some(joint_index)
+                };
+                let Some(bone_index) = bone_index else {
+                    // excluded joint without exported ancestor
+                    num_dropped_weights += 1;
+                    continue;
+                };
+                // joints merged because of excluded bones are combined into one weight
+                match vertex_influences.iter_mut().find(|(b, _)| *b == bone_index) {
+                    Some((_, w)) => *w += weight,
+                    None => vertex_influences.push((bone_index, weight)),
+                }
+            }
+        }
+        set += 1;
+    }
+    if num_dropped_weights > 0 {
+        warn!(
+            "{} vertex weight(s) reference excluded joints that have no exported ancestor. \
+            They have been removed.",
+            num_dropped_weights
+        );
+    }
+    let mut num_clamped_vertices = 0;
+    let wis = influences
+        .into_iter()
+        .map(|mut vertex_influences| {
+            vertex_influences.sort_by(|a, b| b.1.total_cmp(&a.1));
+            if vertex_influences.len() > v3mc::WeightIndexArray::MAX_WEIGHTS {
+                num_clamped_vertices += 1;
+                vertex_influences.truncate(v3mc::WeightIndexArray::MAX_WEIGHTS);
+            }
+            let sum: f32 = vertex_influences.iter().map(|(_, w)| w).sum();
+            let normalized: Vec<_> = vertex_influences.iter().map(|(_, w)| w / sum).collect();
+            let mut indices = [0_u8; 4];
+            for (i, (bone_index, _)) in vertex_influences.iter().enumerate() {
+                indices[i] = (*bone_index).try_into().map_err(|_| {
+                    new_custom_error(format!("joint index {} does not fit in 8 bits", bone_index))
+                })?;
+            }
+            Ok(v3mc::WeightIndexArray {
+                weights: quantize_vertex_weights(&normalized),
+                indices,
+            })
+        })
+        .collect::<std::io::Result<Vec<_>>>()?;
+    if num_clamped_vertices > 0 {
+        warn!(
+            "{} vertices are influenced by more than {} bones. Smallest weights have been dropped and \
+            the remaining ones renormalized.",
+            num_clamped_vertices,
+            v3mc::WeightIndexArray::MAX_WEIGHTS
+        );
+    }
+    Ok(wis)
 }
 
 fn create_mesh_chunk_data(
     prim: &gltf::Primitive,
     transform: &Matrix3,
     ctx: &Context,
-) -> v3mc::MeshChunkData {
+) -> std::io::Result<v3mc::MeshChunkData> {
     let reader = prim.reader(|buffer| ctx.get_buffer_data(buffer));
 
+    let convention = ctx.args.coordinate_convention;
     let vecs: Vec<_> = reader
         .read_positions()
-        .expect("mesh has no positions")
-        .map(|pos| gltf_to_rf_vec(transform_point(&pos, transform)))
+        .ok_or_else(|| new_custom_error("mesh has no positions"))?
+        .map(|pos| gltf_to_rf_pos(transform_point(&pos, transform), convention, ctx.args.scale))
         .collect();
     let norms: Vec<_> = reader
         .read_normals()
         // FIXME: according to GLTF spec we should generate flat normals here
-        .expect("mesh has no normals")
-        .map(|norm| gltf_to_rf_vec(transform_normal(&norm, transform)))
+        .ok_or_else(|| new_custom_error("mesh has no normals"))?
+        .map(|norm| gltf_to_rf_vec(transform_normal(&norm, transform), convention))
         .collect();
+    if vecs.len() != norms.len() {
+        return Err(new_custom_error(format!(
+            "mesh has {} positions but {} normals",
+            vecs.len(),
+            norms.len()
+        )));
+    }
     let uvs: Vec<_> = reader.read_tex_coords(0).map_or_else(
         || {
             (0..vecs.len())
                 .map(|i| generate_uv(&vecs[i], &norms[i]))
                 .collect()
         },
-        |iter| iter.into_f32().collect(),
+        |iter| iter.into_f32().map(|uv| gltf_to_rf_uv(uv, &ctx.args)).collect(),
     );
+    if uvs.len() != vecs.len() {
+        return Err(new_custom_error(format!(
+            "mesh has {} positions but {} UVs",
+            vecs.len(),
+            uvs.len()
+        )));
+    }
+    let nv = vecs.len();
     let indices: Vec<u16> = reader
         .read_indices()
-        .expect("mesh has no indices")
+        .ok_or_else(|| new_custom_error("mesh has no indices"))?
         .into_u32()
-        .map(|vindex| {
-            TryInto::<u16>::try_into(vindex).expect("vertex index does not fit in 16 bits")
+        .map(|vindex| match u16::try_from(vindex) {
+            Ok(vindex) if usize::from(vindex) < nv => Ok(vindex),
+            _ => Err(new_custom_error(format!(
+                "vertex index {} is out of range (mesh has {} vertices)",
+                vindex, nv
+            ))),
         })
-        .collect();
+        .collect::<std::io::Result<_>>()?;
     // Sanity checks
-    assert!(
-        indices.len() % 3 == 0,
-        "number of indices is not a multiple of three: {}",
-        indices.len()
-    );
-    assert!(vecs.len() == norms.len());
-    let nv = vecs.len();
+    if !indices.len().is_multiple_of(3) {
+        return Err(new_custom_error(format!(
+            "number of indices is not a multiple of three: {}",
+            indices.len()
+        )));
+    }
     let face_flags = if prim.material().double_sided() {
         v3mc::MeshFace::DOUBLE_SIDED
     } else {
@@ -187,26 +322,9 @@ fn create_mesh_chunk_data(
 
     let same_pos_vertex_offsets: Vec<i16> = vec![0; nv];
 
-    let wis: Vec<_> = if let Some(joints) = reader.read_joints(0) {
-        joints
-            .into_u16()
-            .zip(
-                reader
-                    .read_weights(0)
-                    .expect("mesh has no weights")
-                    .into_u8(),
-            )
-            .map(|(indices_u16, weights)| {
-                let indices =
-                    indices_u16.map(|x| x.try_into().expect("joint index should fit in u8"));
-                v3mc::WeightIndexArray { weights, indices }
-            })
-            .collect()
-    } else {
-        vec![v3mc::WeightIndexArray::default(); nv]
-    };
+    let wis = convert_vertex_weights(&reader, nv, ctx)?;
 
-    v3mc::MeshChunkData {
+    Ok(v3mc::MeshChunkData {
         vecs,
         norms,
         uvs,
@@ -214,7 +332,7 @@ fn create_mesh_chunk_data(
         face_planes,
         same_pos_vertex_offsets,
         wi: wis,
-    }
+    })
 }
 
 fn create_mesh_data_block(
@@ -223,18 +341,18 @@ fn create_mesh_data_block(
     mesh_materials: &[gltf::Material],
     prop_points: &[v3mc::PropPoint],
     ctx: &Context,
-) -> v3mc::MeshDataBlock {
-    v3mc::MeshDataBlock {
+) -> std::io::Result<v3mc::MeshDataBlock> {
+    Ok(v3mc::MeshDataBlock {
         chunks: mesh
             .primitives()
             .map(|prim| create_mesh_chunk_info(&prim, mesh_materials))
-            .collect(),
+            .collect::<std::io::Result<_>>()?,
         chunks_data: mesh
             .primitives()
             .map(|prim| create_mesh_chunk_data(&prim, transform, ctx))
-            .collect(),
+            .collect::<std::io::Result<_>>()?,
         prop_points: prop_points.to_vec(),
-    }
+    })
 }
 
 fn create_mesh_chunk(
@@ -247,20 +365,21 @@ fn create_mesh_chunk(
             "only triangle list primitives are supported",
         ));
     }
-    if prim.indices().is_none() {
+    let Some(indices) = prim.indices() else {
         return Err(new_custom_error("not indexed geometry is not supported"));
-    }
+    };
 
     let vertex_count = get_primitive_vertex_count(prim);
     let vertex_limit = 6000 - 768;
 
-    let index_count = prim.indices().unwrap().count();
+    let index_count = indices.count();
     let index_limit = 10000 - 768;
-    assert!(
-        index_count % 3 == 0,
-        "number of indices is not a multiple of three: {}",
-        index_count
-    );
+    if !index_count.is_multiple_of(3) {
+        return Err(new_custom_error(format!(
+            "number of indices is not a multiple of three: {}",
+            index_count
+        )));
+    }
     let tri_count = index_count / 3;
 
     if ctx.args.verbose >= 2 {
@@ -285,15 +404,16 @@ fn create_mesh_chunk(
         }
     }
 
-    const TOO_MANY_VERTICES: &str = "Too many vertices";
-    const TOO_MANY_TRIANGLES: &str = "Too many triangles";
-    let num_vecs = vertex_count.try_into().expect(TOO_MANY_VERTICES);
-    let num_faces = tri_count.try_into().expect(TOO_MANY_TRIANGLES);
-    let vecs_alloc = (vertex_count * 3 * 4).try_into().expect(TOO_MANY_VERTICES);
-    let faces_alloc = (tri_count * 4 * 2).try_into().expect(TOO_MANY_TRIANGLES);
-    let same_pos_vertex_offsets_alloc = (vertex_count * 2).try_into().expect(TOO_MANY_VERTICES);
-    let wi_alloc = (vertex_count * 2 * 4).try_into().expect(TOO_MANY_VERTICES);
-    let uvs_alloc = (vertex_count * 2 * 4).try_into().expect(TOO_MANY_VERTICES);
+    let too_many_vertices = |_| new_custom_error("Too many vertices");
+    let too_many_triangles = |_| new_custom_error("Too many triangles");
+    let num_vecs = vertex_count.try_into().map_err(too_many_vertices)?;
+    let num_faces = tri_count.try_into().map_err(too_many_triangles)?;
+    let vecs_alloc = (vertex_count * 3 * 4).try_into().map_err(too_many_vertices)?;
+    let faces_alloc = (tri_count * 4 * 2).try_into().map_err(too_many_triangles)?;
+    let same_pos_vertex_offsets_alloc =
+        (vertex_count * 2).try_into().map_err(too_many_vertices)?;
+    let wi_alloc = (vertex_count * 2 * 4).try_into().map_err(too_many_vertices)?;
+    let uvs_alloc = (vertex_count * 2 * 4).try_into().map_err(too_many_vertices)?;
     let render_mode = material::compute_render_mode_for_material(&prim.material());
     Ok(v3mc::MeshChunk {
         num_vecs,
@@ -321,6 +441,8 @@ fn convert_mesh(
         v3mc::VIF_MESH_FLAG_FACE_PLANES
     };
     let num_vecs = count_mesh_vertices(&mesh) as i32;
+    warn_about_extra_uv_sets(&mesh);
+    warn_about_vertex_colors(&mesh, ctx);
 
     let materials: Vec<_> = get_mesh_materials(&mesh);
     if materials.len() > v3mc::Mesh::MAX_TEXTURES {
@@ -337,7 +459,7 @@ fn convert_mesh(
     }
 
     let mut data_block_cur = Cursor::new(Vec::<u8>::new());
-    create_mesh_data_block(&mesh, transform, &materials, prop_points, ctx)
+    create_mesh_data_block(&mesh, transform, &materials, prop_points, ctx)?
         .write(&mut data_block_cur)?;
     let data_block: Vec<u8> = data_block_cur.into_inner();
 
@@ -357,83 +479,159 @@ fn convert_mesh(
     })
 }
 
-fn convert_prop_point(
-    node: &gltf::Node,
+fn make_prop_point(
+    name: String,
     transform: &glam::Mat4,
     parent_index: i32,
+    ctx: &Context,
 ) -> v3mc::PropPoint {
-    let local_transform = get_node_local_transform(node);
-    let (_scale, rotation, translation) = transform
-        .mul(local_transform)
-        .to_scale_rotation_translation();
-
+    let (_scale, rotation, translation) = transform.to_scale_rotation_translation();
     v3mc::PropPoint {
-        name: node.name().expect("prop point name is missing").to_string(),
-        orient: gltf_to_rf_quat(rotation.into()),
-        pos: gltf_to_rf_vec(translation.into()),
+        name,
+        orient: gltf_to_rf_quat(rotation.into(), ctx.args.coordinate_convention),
+        pos: gltf_to_rf_pos(translation.into(), ctx.args.coordinate_convention, ctx.args.scale),
         parent_index,
     }
 }
 
+fn convert_prop_point(
+    node: &gltf::Node,
+    transform: &glam::Mat4,
+    parent_index: i32,
+    ctx: &Context,
+) -> std::io::Result<v3mc::PropPoint> {
+    let local_transform = get_node_local_transform(node);
+    let Some(name) = node.name() else {
+        return Err(new_custom_error(format!("prop point node #{} has no name", node.index())));
+    };
+    Ok(make_prop_point(name.to_string(), &transform.mul(local_transform), parent_index, ctx))
+}
+
 fn get_prop_points(
     parent: &gltf::Node,
     transform: &glam::Mat4,
     ctx: &Context,
-) -> Vec<v3mc::PropPoint> {
+) -> std::io::Result<Vec<v3mc::PropPoint>> {
     let mut prop_points = parent
         .children()
-        .filter(|n| n.mesh().is_none())
-        .map(|n| convert_prop_point(&n, transform, -1))
-        .collect::<Vec<_>>();
-    if let Some(skin) = parent.skin() {
+        .filter(|n| n.mesh().is_none() && !is_csphere(n))
+        .map(|n| convert_prop_point(&n, transform, -1, ctx))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    if let Some(skin) = parent.skin().filter(|s| Some(s.index()) == ctx.skin_index) {
+        for (node, parent_index, transform) in
+            char_anim::get_nodes_parented_to_bones(&skin, &ctx.bone_map)
+                .filter(|(node, _, _)| node.mesh().is_none() && !is_csphere(node))
+                .filter(|(node, _, _)| node.name().is_some())
+        {
+            prop_points.push(convert_prop_point(&node, &transform, parent_index, ctx)?);
+        }
         prop_points.extend(
-            char_anim::get_nodes_parented_to_bones(&skin)
-                .filter(|(node, _)| node.mesh().is_none())
-                .filter(|(node, _)| node.name().is_some())
-                .map(|(node, parent_index)| {
-                    convert_prop_point(&node, &glam::Mat4::IDENTITY, parent_index)
+            char_anim::get_joint_attach_points(&skin, &ctx.bone_map)
+                .map(|(name, parent_index, transform)| {
+                    make_prop_point(name, &transform, parent_index, ctx)
                 }),
         );
     }
     if ctx.args.verbose >= 2 {
         println!("Found {} prop points", prop_points.len());
     }
-    prop_points
+    Ok(prop_points)
 }
 
+const CSPHERE_NAME_PREFIXES: [&str; 2] = ["csphere_", "col_sphere_"];
+
+/// Name of the collision sphere generated from mesh bounds if the file does not define any
+const DEFAULT_CSPHERE_NAME: &str = "csphere_default";
+
 fn is_csphere(node: &gltf::Node) -> bool {
-    node.mesh().is_none() && node.name().unwrap_or_default().starts_with("csphere_")
+    let name = node.name().unwrap_or_default();
+    CSPHERE_NAME_PREFIXES.iter().any(|p| name.starts_with(p))
 }
 
-fn convert_cspheres(doc: &gltf::Document, ctx: &Context) -> Vec<v3mc::ColSphere> {
-    let mut cspheres = doc
+fn convert_cspheres(
+    doc: &gltf::Document,
+    lod_meshes: &[v3mc::LodMesh],
+    ctx: &Context,
+) -> Vec<v3mc::ColSphere> {
+    let child_indices = build_child_nodes_indices(doc);
+    let skin = ctx.get_skin(doc);
+    let mut csphere_nodes = doc
         .nodes()
+        .filter(|n| !child_indices.contains(&n.index()))
         .filter(is_csphere)
-        .map(|n| convert_csphere(&n, -1, ctx))
+        .map(|n| (n, -1, glam::Mat4::IDENTITY))
         .collect::<Vec<_>>();
-    if let Some(skin) = doc.skins().next() {
-        cspheres.extend(
-            char_anim::get_nodes_parented_to_bones(&skin)
-                .filter(|(node, _)| is_csphere(node))
-                .filter(|(node, _)| node.name().is_some())
-                .map(|(node, parent_index)| convert_csphere(&node, parent_index, ctx)),
+    if let Some(skin) = &skin {
+        csphere_nodes.extend(
+            char_anim::get_nodes_parented_to_bones(skin, &ctx.bone_map)
+                .filter(|(node, _, _)| is_csphere(node))
+                .filter(|(node, _, _)| node.name().is_some()),
         );
     }
+    for node in doc.nodes().filter(is_csphere) {
+        if !csphere_nodes.iter().any(|(n, _, _)| n.index() == node.index()) {
+            warn!(
+                "Collision sphere {} is neither a root node nor a child of a joint. It has been skipped!",
+                node.name().unwrap_or_default()
+            );
+        }
+    }
+    let mut cspheres = csphere_nodes
+        .iter()
+        .map(|(node, parent_index, transform)| convert_csphere(node, *parent_index, transform, ctx))
+        .collect::<Vec<_>>();
+    if cspheres.is_empty() && ctx.is_character {
+        if let Some(csphere) = create_default_csphere(lod_meshes) {
+            warn!(
+                "Found no collision spheres. Using '{}' sphere enclosing the mesh (radius {}).",
+                csphere.name, csphere.radius
+            );
+            cspheres.push(csphere);
+        }
+    }
     cspheres
 }
 
-fn convert_csphere(node: &gltf::Node, parent_index: i32, ctx: &Context) -> v3mc::ColSphere {
+/// Creates a collision sphere enclosing bounding box of the first LOD mesh
+fn create_default_csphere(lod_meshes: &[v3mc::LodMesh]) -> Option<v3mc::ColSphere> {
+    let lod_mesh = lod_meshes.first()?;
+    let bbox_min = glam::Vec3::from(lod_mesh.bbox_min);
+    let bbox_max = glam::Vec3::from(lod_mesh.bbox_max);
+    let center = (bbox_min + bbox_max) / 2.0;
+    let radius = (bbox_max - bbox_min).length() / 2.0;
+    if radius <= 0.0_f32 {
+        return None;
+    }
+    Some(v3mc::ColSphere {
+        name: DEFAULT_CSPHERE_NAME.to_owned(),
+        parent_index: -1,
+        pos: (center + glam::Vec3::from(lod_mesh.offset)).to_array(),
+        radius,
+    })
+}
+
+fn convert_csphere(
+    node: &gltf::Node,
+    parent_index: i32,
+    transform: &glam::Mat4,
+    ctx: &Context,
+) -> v3mc::ColSphere {
     let name = node.name().expect("csphere name is missing").to_owned();
     if ctx.args.verbose >= 2 {
         println!("Processing csphere: node #{} '{}'", node.index(), name);
     }
-    let transform = get_node_local_transform(node);
+    let transform = transform.mul(get_node_local_transform(node));
     let (scale, _rotation, translation) = transform.to_scale_rotation_translation();
-    let radius = scale.max_element();
+    // mesh defines radius in its local space, empty object has radius 1. Both are scaled like positions
+    let local_radius = node.mesh().map_or(ctx.args.scale, |mesh| {
+        let identity = glam::Mat3::IDENTITY.to_cols_array_2d();
+        compute_mesh_bounding_sphere_radius(&mesh, &identity, ctx)
+    });
+    let radius = local_radius * scale.max_element();
     v3mc::ColSphere {
         name,
         parent_index,
-        pos: gltf_to_rf_vec(translation.into()),
+        pos: gltf_to_rf_pos(translation.into(), ctx.args.coordinate_convention, ctx.args.scale),
         radius,
     }
 }
@@ -442,10 +640,13 @@ fn convert_lod_meshes(
     doc: &gltf::Document,
     ctx: &Context,
 ) -> Result<Vec<v3mc::LodMesh>, Box<dyn Error>> {
-    let submesh_nodes = get_submesh_nodes(doc);
+    let submesh_nodes: Vec<_> = get_submesh_nodes(doc)
+        .into_iter()
+        .filter(|n| !is_csphere(n))
+        .collect();
     let mut lod_meshes = Vec::with_capacity(submesh_nodes.len());
-    if lod_meshes.is_empty() {
-        eprintln!("Warning! Found no LOD groups");
+    if submesh_nodes.is_empty() {
+        warn!("Found no LOD groups");
     }
     for n in &submesh_nodes {
         lod_meshes.push(convert_lod_mesh(n, ctx)?);
@@ -472,11 +673,11 @@ fn convert_lod_mesh(node: &gltf::Node, ctx: &Context) -> Result<v3mc::LodMesh, B
     let bbox = compute_mesh_bbox(&mesh, &rot_scale_mat, ctx);
     let (bbox_min, bbox_max) = (bbox.min, bbox.max);
 
-    let offset = gltf_to_rf_vec(origin);
+    let offset = gltf_to_rf_pos(origin, ctx.args.coordinate_convention, ctx.args.scale);
     let radius = compute_mesh_bounding_sphere_radius(&mesh, &rot_scale_mat, ctx);
 
     let transform = glam::Mat4::from_mat3(glam::Mat3::from_cols_array_2d(&rot_scale_mat));
-    let prop_points = get_prop_points(node, &transform, ctx);
+    let prop_points = get_prop_points(node, &transform, ctx)?;
 
     let mut gltf_materials: Vec<_> = child_node_dist_vec
         .iter()
@@ -523,14 +724,10 @@ pub fn convert_gltf_to_v3mc(
     doc: &gltf::Document,
     ctx: &Context,
 ) -> Result<v3mc::File, Box<dyn Error>> {
-    if doc.skins().count() > 1 {
-        eprintln!("Warning! There is more than one skin defined. Only first skin will be used.");
-    }
-
     let lod_meshes = convert_lod_meshes(doc, ctx)?;
-    let cspheres = convert_cspheres(doc, ctx);
-    let bones = if let Some(skin) = doc.skins().next() {
-        char_anim::convert_bones(&skin, ctx)?
+    let cspheres = convert_cspheres(doc, &lod_meshes, ctx);
+    let bones = if let Some(skin) = ctx.get_skin(doc) {
+        char_anim::convert_bones(&skin, &char_anim::ConversionConfig::from(&ctx.args), ctx)?
     } else {
         Vec::new()
     };
@@ -561,13 +758,22 @@ fn find_lod_nodes<'a>(node: &'a gltf::Node) -> Vec<(gltf::Node<'a>, f32)> {
         .children()
         .filter(|n| n.mesh().is_some())
         .map(|n| {
-            let dist_opt = get_node_extras::<NodeExtras>(&n).lod_distance;
+            let dist_opt = get_node_extras::<NodeExtras>(&n).lod_distance.or_else(|| {
+                let level = parse_lod_level(n.name().unwrap_or_default())?;
+                let dist = level as f32 * DEFAULT_LOD_DISTANCE_STEP;
+                warn!(
+                    "Child node {} has no LOD_distance. Using {} based on LOD level in its name",
+                    n.name().unwrap_or("None"),
+                    dist
+                );
+                Some(dist)
+            });
             (n, dist_opt)
         })
         .filter_map(|(n, dist_opt)| {
             if dist_opt.is_none() {
-                eprintln!(
-                    "Warning! Expected LOD_distance in child node {}",
+                warn!(
+                    "Expected LOD_distance in child node {}",
                     n.name().unwrap_or("None")
                 );
             }
@@ -575,6 +781,60 @@ fn find_lod_nodes<'a>(node: &'a gltf::Node) -> Vec<(gltf::Node<'a>, f32)> {
         })
         .chain(iter::once((node.clone(), 0_f32)))
         .collect();
-    child_node_dist_vec.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+    child_node_dist_vec.sort_by(|a, b| a.1.total_cmp(&b.1));
+    for pair in child_node_dist_vec.windows(2) {
+        if pair[0].1 == pair[1].1 {
+            warn!(
+                "LOD meshes {} and {} have the same distance {}",
+                pair[0].0.name().unwrap_or("None"),
+                pair[1].0.name().unwrap_or("None"),
+                pair[1].1
+            );
+        }
+    }
     child_node_dist_vec
 }
+
+/// Distance between LOD levels used for child nodes that have no `LOD_distance` property but have
+/// LOD level in their name
+const DEFAULT_LOD_DISTANCE_STEP: f32 = 10.0;
+
+/// Parses LOD level from node name suffix, e.g. `chair_LOD1` or `chair.lod2` gives 1 and 2
+fn parse_lod_level(name: &str) -> Option<u32> {
+    let lower_name = name.to_ascii_lowercase();
+    let pos = lower_name.rfind("lod")?;
+    let level = lower_name[pos + 3..].parse::<u32>().ok()?;
+    (level > 0).then_some(level)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::load_test_gltf;
+
+    const SKINNED_ANIM_GLTF: &[u8] = include_bytes!("../tests/data/skinned_anim.gltf");
+
+    #[test]
+    fn empty_node_csphere_radius_is_scaled() {
+        let mut gltf: serde_json::Value = serde_json::from_slice(SKINNED_ANIM_GLTF).unwrap();
+        let nodes = gltf["nodes"].as_array_mut().unwrap();
+        nodes.push(serde_json::json!({"name": "csphere_body", "translation": [0, 100, 0]}));
+        let csphere_index = nodes.len() - 1;
+        gltf["scenes"][0]["nodes"].as_array_mut().unwrap().push(csphere_index.into());
+        let (document, ctx) = load_test_gltf(&serde_json::to_vec(&gltf).unwrap(), &["--scale", "0.01"]).unwrap();
+        let v3c = convert_gltf_to_v3mc(&document, &ctx).unwrap();
+        let csphere = v3c.cspheres.iter().find(|c| c.name == "csphere_body").unwrap();
+        assert!((csphere.radius - 0.01).abs() < 1e-6, "{}", csphere.radius);
+        assert!((glam::Vec3::from(csphere.pos).length() - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn vertex_weight_count_mismatch_is_rejected() {
+        let mut gltf: serde_json::Value = serde_json::from_slice(SKINNED_ANIM_GLTF).unwrap();
+        // 4 vertices but only 3 weights
+        gltf["accessors"][3]["count"] = 3.into();
+        let (document, ctx) = load_test_gltf(&serde_json::to_vec(&gltf).unwrap(), &[]).unwrap();
+        let message = convert_gltf_to_v3mc(&document, &ctx).err().unwrap().to_string();
+        assert!(message.contains("4 JOINTS_0 and 3 WEIGHTS_0 values for 4 vertices"), "{}", message);
+    }
+}
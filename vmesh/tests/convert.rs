@@ -0,0 +1,173 @@
+//! End-to-end conversion of the checked-in fixtures by the `vmesh` binary
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+fn fixture_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("data")
+        .join(name)
+}
+
+/// Returns an empty directory for test output. It is unique per test and process so tests can run in parallel.
+fn make_output_dir(test_name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("vmesh-{}-{}", test_name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn read_i32(data: &[u8], offset: usize) -> i32 {
+    i32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+#[test]
+fn skinned_anim_fixture_converts_to_v3c_and_rfa() {
+    let output_dir = make_output_dir("skinned-anim");
+    let status = Command::new(env!("CARGO_BIN_EXE_vmesh"))
+        .arg("--quiet")
+        .arg("--anim-manifest")
+        .arg(fixture_path("skinned_anim.gltf"))
+        .arg(&output_dir)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    // signature is stored as little-endian 'RFCM'
+    let v3c = fs::read(output_dir.join("skinned_anim.v3c")).unwrap();
+    assert_eq!(&v3c[0..4], b"MCFR");
+
+    // RFA header: signature, version, reduction factors, start and end time, number of bones
+    let rfa = fs::read(output_dir.join("walk.rfa")).unwrap();
+    assert_eq!(&rfa[0..4], b"VMVF");
+    assert_eq!(read_i32(&rfa, 4), 8);
+    assert_eq!(read_i32(&rfa, 16), 0);
+    assert_eq!(read_i32(&rfa, 20), 4800);
+    assert_eq!(read_i32(&rfa, 24), 2);
+
+    let manifest: serde_json::Value =
+        serde_json::from_slice(&fs::read(output_dir.join("skinned_anim_anims.json")).unwrap())
+            .unwrap();
+    assert_eq!(
+        manifest,
+        serde_json::json!([{
+            "animation": "walk",
+            "file_name": "walk.rfa",
+            "start_time": 0,
+            "end_time": 4800,
+            "num_bones": 2,
+        }])
+    );
+
+    fs::remove_dir_all(&output_dir).unwrap();
+}
+
+#[test]
+fn invalid_input_fails_without_output() {
+    let output_dir = make_output_dir("invalid-input");
+    let input = output_dir.join("invalid.gltf");
+    fs::write(&input, b"{ not a gltf").unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_vmesh"))
+        .arg(&input)
+        .arg(output_dir.join("invalid.v3m"))
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(!output_dir.join("invalid.v3m").exists());
+
+    fs::remove_dir_all(&output_dir).unwrap();
+}
+
+#[test]
+fn huge_sparse_accessor_count_is_rejected_without_allocating() {
+    let output_dir = make_output_dir("huge-sparse");
+    let input = output_dir.join("huge_sparse.gltf");
+    // sparse accessor without a base buffer view claiming 48 TB of VEC3 floats
+    fs::write(
+        &input,
+        br#"{
+            "asset": {"version": "2.0"},
+            "buffers": [{"byteLength": 16, "uri": "data:application/octet-stream;base64,AAAAAAAAAAAAAAAAAAAAAA=="}],
+            "bufferViews": [{"buffer": 0, "byteLength": 16}],
+            "accessors": [{
+                "componentType": 5126,
+                "count": 4000000000000,
+                "type": "VEC3",
+                "sparse": {
+                    "count": 1,
+                    "indices": {"bufferView": 0, "componentType": 5125},
+                    "values": {"bufferView": 0, "byteOffset": 4}
+                }
+            }]
+        }"#,
+    )
+    .unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_vmesh"))
+        .arg(&input)
+        .arg(output_dir.join("huge_sparse.v3m"))
+        .output()
+        .unwrap();
+    // exit code 1 is an error reported by vmesh, an allocation failure aborts the process
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("invalid element count"), "{}", stderr);
+
+    fs::remove_dir_all(&output_dir).unwrap();
+}
+
+#[test]
+fn no_overwrite_lists_existing_files_and_keeps_them() {
+    let output_dir = make_output_dir("no-overwrite");
+    let rfa_path = output_dir.join("walk.rfa");
+    fs::write(&rfa_path, b"hand-edited").unwrap();
+    let convert = || {
+        Command::new(env!("CARGO_BIN_EXE_vmesh"))
+            .arg("--quiet")
+            .arg("--no-overwrite")
+            .arg(fixture_path("skinned_anim.gltf"))
+            .arg(&output_dir)
+            .output()
+            .unwrap()
+    };
+
+    let output = convert();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("walk.rfa"), "{}", stderr);
+    assert_eq!(fs::read(&rfa_path).unwrap(), b"hand-edited");
+
+    // mesh is checked before anything is written
+    fs::remove_file(&rfa_path).unwrap();
+    assert!(convert().status.success());
+    let output = convert();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("skinned_anim.v3c"), "{}", stderr);
+
+    fs::remove_dir_all(&output_dir).unwrap();
+}
+
+#[test]
+fn dry_run_reports_sampler_output_count_mismatch_without_output() {
+    let output_dir = make_output_dir("dry-run");
+    let mut gltf: serde_json::Value =
+        serde_json::from_slice(&fs::read(fixture_path("skinned_anim.gltf")).unwrap()).unwrap();
+    // 3 key times but only 2 rotations of bone1
+    gltf["accessors"][7]["count"] = 2.into();
+    let input = output_dir.join("mismatch.gltf");
+    fs::write(&input, serde_json::to_vec(&gltf).unwrap()).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_vmesh"))
+        .arg("--dry-run")
+        .arg(&input)
+        .arg(&output_dir)
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("2 outputs for 3 inputs"), "{}", stdout);
+    assert_eq!(fs::read_dir(&output_dir).unwrap().count(), 1);
+
+    fs::remove_dir_all(&output_dir).unwrap();
+}